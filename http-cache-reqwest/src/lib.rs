@@ -41,14 +41,35 @@
 //! `reqwest-middleware` extensions system.
 //!
 //! ```no_run
+//! # use http_cache_reqwest::CacheMode;
+//! # async fn run(client: reqwest_middleware::ClientWithMiddleware) -> reqwest_middleware::Result<()> {
 //! client.get("https://developer.mozilla.org/en-US/docs/Web/HTTP/Caching")
 //!     .with_extension(CacheMode::OnlyIfCached)
 //!     .send()
 //!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Reading the cache status
+//!
+//! With [`HttpCacheOptions::cache_info`] enabled, the responses carry a
+//! [`CacheInfo`] extension describing how the cache handled the request.
+//!
+//! ```no_run
+//! # use http_cache_reqwest::CacheInfo;
+//! # async fn run(client: reqwest_middleware::ClientWithMiddleware) -> reqwest_middleware::Result<()> {
+//! let res = client.get("https://developer.mozilla.org/en-US/docs/Web/HTTP/Caching")
+//!     .send()
+//!     .await?;
+//! let info = res.extensions().get::<CacheInfo>();
+//! # Ok(())
+//! # }
 //! ```
 mod error;
 
 use anyhow::anyhow;
+use bytes::Bytes;
 
 pub use error::BadRequest;
 
@@ -73,8 +94,8 @@ use reqwest_middleware::{Error, Next};
 use url::Url;
 
 pub use http_cache::{
-    Body, CacheManager, CacheMode, CacheOptions, HttpCache, HttpCacheOptions,
-    HttpResponse, Parts as HttpParts,
+    Body, CacheInfo, CacheManager, CacheMode, CacheOptions, HttpCache,
+    HttpCacheOptions, HttpResponse, Parts as HttpParts,
 };
 
 #[cfg(feature = "manager-cacache")]
@@ -138,6 +159,10 @@ impl Middleware for ReqwestMiddleware<'_> {
             .insert(CACHE_CONTROL, HeaderValue::from_str("no-cache")?);
         Ok(())
     }
+    fn set_method(&mut self, method: Method) -> Result<()> {
+        *self.req.method_mut() = method;
+        Ok(())
+    }
     fn parts(&self) -> Result<Parts> {
         let copied_req = clone_req(&self.req)?;
         let converted = match http::Request::try_from(copied_req) {
@@ -152,6 +177,14 @@ impl Middleware for ReqwestMiddleware<'_> {
     fn method(&self) -> Result<String> {
         Ok(self.req.method().as_ref().to_string())
     }
+    fn request_body(&self) -> Result<Option<Bytes>> {
+        // Streaming bodies return `None` from `as_bytes` and are left untouched
+        Ok(self
+            .req
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(Bytes::copy_from_slice))
+    }
     async fn remote_fetch(&mut self) -> Result<HttpResponse> {
         let copied_req = clone_req(&self.req)?;
         let res = match self.next.clone().run(copied_req, self.extensions).await
@@ -180,6 +213,7 @@ impl Middleware for ReqwestMiddleware<'_> {
 
 // Converts an [`HttpResponse`] to a reqwest [`Response`]
 fn convert_response(response: HttpResponse) -> anyhow::Result<Response> {
+    let cache_info = response.cache_info().cloned();
     let (parts, body) = response.into_parts();
     let body = reqwest::Body::wrap_stream(body.into_data_stream());
     let mut ret_res = http::Response::builder()
@@ -193,6 +227,9 @@ fn convert_response(response: HttpResponse) -> anyhow::Result<Response> {
             HeaderValue::from_str(header.1.clone().as_str())?,
         );
     }
+    if let Some(cache_info) = cache_info {
+        ret_res.extensions_mut().insert(cache_info);
+    }
     Ok(Response::from(ret_res))
 }
 
@@ -0,0 +1,160 @@
+//! Measures the throughput of the [`CacheManager`](http_cache::CacheManager)
+//! operations of each built-in manager, for response bodies of varying sizes, and of
+//! collecting streamed bodies with and without a size hint.
+//!
+//! Run with `cargo bench -p http-cache --features manager-moka` to include the
+//! in-memory manager.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+// Only needed by the benches of the managers enabled by features
+#[cfg(any(feature = "manager-cacache", feature = "manager-moka"))]
+mod support {
+    use bytes::Bytes;
+    use criterion::{BatchSize, BenchmarkId, Criterion, Throughput};
+    use http_cache::{CacheManager, HttpResponse, HttpVersion, Parts};
+    use http_cache_semantics::CachePolicy;
+    use tokio::runtime::Runtime;
+    use url::Url;
+
+    const URL: &str = "http://example.com/item";
+    const SIZES: [usize; 3] = [1024, 64 * 1024, 1024 * 1024];
+
+    // The parts and body of a cacheable response with a body of `size` bytes, along
+    // with its policy. Mirrors `payload` of the crate's test support, which benches
+    // can't reach as they're built as a crate of their own
+    fn payload(size: usize) -> (Parts, Bytes, CachePolicy) {
+        let headers = [
+            ("cache-control", "max-age=86400, public"),
+            ("content-type", "application/octet-stream"),
+            ("etag", "\"33a64df551425fcc55e4d42a148795d9f25f89d4\""),
+        ];
+        let parts = Parts {
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            status: 200,
+            url: Url::parse(URL).unwrap(),
+            version: HttpVersion::Http11,
+        };
+        let body: Bytes = (0..size).map(|i| (i % 251) as u8).collect();
+        let req = http::Request::get(URL).body(()).unwrap().into_parts().0;
+        let res = HttpResponse::from_parts(parts.clone(), Bytes::new().into());
+        let policy = CachePolicy::new(&req, &res.parts().unwrap());
+        (parts, body, policy)
+    }
+
+    pub fn bench_manager(
+        c: &mut Criterion,
+        name: &str,
+        manager: impl CacheManager,
+    ) {
+        let rt = Runtime::new().unwrap();
+        let mut group = c.benchmark_group(name);
+        for size in SIZES {
+            let (parts, body, policy) = payload(size);
+            let res =
+                || HttpResponse::from_parts(parts.clone(), body.clone().into());
+            let key = format!("GET:{URL}:{size}");
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_function(BenchmarkId::new("put", size), |b| {
+                b.iter_batched(
+                    res,
+                    |res| {
+                        rt.block_on(manager.put(
+                            key.clone(),
+                            res,
+                            policy.clone(),
+                        ))
+                        .unwrap()
+                    },
+                    BatchSize::SmallInput,
+                )
+            });
+            group.bench_function(BenchmarkId::new("get", size), |b| {
+                rt.block_on(manager.put(key.clone(), res(), policy.clone()))
+                    .unwrap();
+                b.iter(|| {
+                    rt.block_on(async {
+                        let (res, _) =
+                            manager.get(&key).await.unwrap().unwrap();
+                        res.into_parts().1.bytes().await.unwrap()
+                    })
+                })
+            });
+            group.bench_function(BenchmarkId::new("delete", size), |b| {
+                b.iter_batched(
+                    || {
+                        rt.block_on(manager.put(
+                            key.clone(),
+                            res(),
+                            policy.clone(),
+                        ))
+                        .unwrap()
+                    },
+                    |_| rt.block_on(manager.delete(&key)).unwrap(),
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+        group.finish();
+    }
+}
+
+#[cfg(feature = "manager-cacache")]
+fn cacache(c: &mut Criterion) {
+    let tmp = tempfile::tempdir().unwrap();
+    let manager = http_cache::CACacheManager { path: tmp.path().to_path_buf() };
+    support::bench_manager(c, "cacache", manager);
+}
+
+#[cfg(feature = "manager-moka")]
+fn moka(c: &mut Criterion) {
+    support::bench_manager(
+        c,
+        "moka",
+        http_cache::MokaManager::new(http_cache::MokaCache::new(1024)),
+    );
+}
+
+// Collecting a body streamed in 16 KiB chunks, growing the buffer as chunks arrive
+// or reserving it up front from the Content-Length
+fn body(c: &mut Criterion) {
+    use bytes::Bytes;
+    use criterion::{BenchmarkId, Throughput};
+    use http_cache::Body;
+
+    const CHUNK: usize = 16 * 1024;
+
+    let mut group = c.benchmark_group("body");
+    for size in [64 * 1024, 1024 * 1024] {
+        let chunk = Bytes::from(vec![0; CHUNK]);
+        let stream = || {
+            let chunk = chunk.clone();
+            let chunks = (0..size / CHUNK)
+                .map(move |_| Ok::<_, http_cache::BoxError>(chunk.clone()));
+            Body::wrap_stream(futures::stream::iter(chunks))
+        };
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_function(BenchmarkId::new("grow", size), |b| {
+            b.iter(|| futures::executor::block_on(stream().bytes()).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("hint", size), |b| {
+            b.iter(|| {
+                futures::executor::block_on(stream().bytes_with_hint(size))
+                    .unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+#[cfg(not(feature = "manager-cacache"))]
+fn cacache(_: &mut Criterion) {}
+
+#[cfg(not(feature = "manager-moka"))]
+fn moka(_: &mut Criterion) {}
+
+criterion_group!(benches, cacache, moka, body);
+criterion_main!(benches);
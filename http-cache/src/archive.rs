@@ -0,0 +1,148 @@
+use crate::{
+    unblock, CacheManager, HttpCache, HttpResponse, Parts, RequestSnapshot,
+    Result,
+};
+
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+use futures::{channel::mpsc, executor, SinkExt, StreamExt};
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+
+// Archive layout:
+// a tar archive holding one `entries/{n}` file per record, each containing the
+// bincode encoded ArchiveEntry. Keys are stored inside the entries since they
+// aren't valid paths in general.
+
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct ArchiveEntry {
+    pub(crate) key: String,
+    pub(crate) parts: Parts,
+    pub(crate) body: Vec<u8>,
+    pub(crate) policy: CachePolicy,
+    pub(crate) request: Option<RequestSnapshot>,
+    pub(crate) metadata: HashMap<String, String>,
+}
+
+impl<T: CacheManager> HttpCache<T> {
+    /// Writes every record in cache to `writer` as a tar archive, returning the number
+    /// of records exported. Requires the manager to implement [`CacheManager::keys`].
+    ///
+    /// Records are read and written one at a time, so large caches are never loaded
+    /// into memory at once, along with their request snapshots and metadata
+    /// annotations. Records removed while exporting are skipped.
+    ///
+    /// The writer is used from a thread of its own, so that writing doesn't block the
+    /// executor, which is why it must be `Send + 'static`: pass an owned writer, such
+    /// as a [`File`](std::fs::File), rather than a borrowed one.
+    #[cfg_attr(docsrs, doc(cfg(feature = "archive")))]
+    pub async fn export_archive<W: Write + Send + 'static>(
+        &self,
+        writer: W,
+    ) -> Result<usize> {
+        let (mut sender, receiver) = mpsc::channel::<Vec<u8>>(1);
+        let write = unblock(move || -> Result<()> {
+            let mut builder = tar::Builder::new(writer);
+            for (n, data) in executor::block_on_stream(receiver).enumerate() {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(data.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append_data(
+                    &mut header,
+                    format!("entries/{n}"),
+                    data.as_slice(),
+                )?;
+            }
+            builder.into_inner()?.flush()?;
+            Ok(())
+        });
+        let read = async move {
+            let mut exported = 0;
+            for key in self.manager.keys().await? {
+                let Some((mut res, policy, request, metadata)) =
+                    self.manager.peek_with_metadata(&key).await?
+                else {
+                    continue;
+                };
+                // Deferred revalidations don't carry over to the imported records
+                res.take_deferred_until();
+                let (parts, body) = res.into_parts();
+                let body = body
+                    .bytes_with_hint(parts.content_length().unwrap_or(0))
+                    .await?;
+                let entry = ArchiveEntry {
+                    key,
+                    parts,
+                    body: body.into(),
+                    policy,
+                    request,
+                    metadata,
+                };
+                // The writer stops on errors, which are returned instead
+                if sender.send(bincode::serialize(&entry)?).await.is_err() {
+                    break;
+                }
+                exported += 1;
+            }
+            Result::Ok(exported)
+        };
+        let (exported, written) = futures::join!(read, write);
+        written??;
+        exported
+    }
+
+    /// Stores every record of a tar archive created by [`HttpCache::export_archive`]
+    /// read from `reader`, returning the number of records imported. Existing records
+    /// with the same keys are overwritten.
+    ///
+    /// The reader is used from a thread of its own, so that reading doesn't block the
+    /// executor, which is why it must be `Send + 'static`: pass an owned reader, such
+    /// as a [`File`](std::fs::File), rather than a borrowed one.
+    #[cfg_attr(docsrs, doc(cfg(feature = "archive")))]
+    pub async fn import_archive<R: Read + Send + 'static>(
+        &self,
+        reader: R,
+    ) -> Result<usize> {
+        let (mut sender, mut receiver) = mpsc::channel::<ArchiveEntry>(1);
+        let read = unblock(move || -> Result<()> {
+            let mut archive = tar::Archive::new(reader);
+            for file in archive.entries()? {
+                let mut data = Vec::new();
+                file?.read_to_end(&mut data)?;
+                let entry = bincode::deserialize(&data)?;
+                // Storing stops on errors, which are returned instead
+                if executor::block_on(sender.send(entry)).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        });
+        let store = async move {
+            let mut imported = 0;
+            while let Some(entry) = receiver.next().await {
+                self.manager
+                    .put_with_metadata(
+                        entry.key,
+                        HttpResponse::from_parts(
+                            entry.parts,
+                            entry.body.into(),
+                        ),
+                        entry.policy,
+                        entry.request,
+                        entry.metadata,
+                    )
+                    .await?;
+                imported += 1;
+            }
+            Result::Ok(imported)
+        };
+        let (read, imported) = futures::join!(read, store);
+        let imported = imported?;
+        read??;
+        Ok(imported)
+    }
+}
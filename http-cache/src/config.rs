@@ -0,0 +1,143 @@
+use crate::{
+    CacheManager, CacheMode, CacheOptions, FetchLimit, HttpCache,
+    HttpCacheOptions, Result,
+};
+
+use std::{str::FromStr, time::Duration};
+
+use serde::Deserialize;
+
+/// The settings of an [`HttpCache`] that can be configured without code, e.g. from
+/// environment variables with [`CacheConfig::from_env`] or from any serde format.
+///
+/// Every field is optional, unset fields keep the default of the matching
+/// [`HttpCacheOptions`] field. The configurable fields are `mode`,
+/// `cache_status_headers`, `store_request`, `shared` (the `shared` flag of
+/// [`HttpCacheOptions::cache_options`]), `key_version`, `miss_status`,
+/// `revalidate_with_head`, `skip_cache_on_set_cookie`, `max_variants_per_url`,
+/// `max_concurrent_fetches`, `stale_grace_secs` and `instance_name`. Fields holding
+/// closures, such as the cache key or the runtime hooks, have to be set in code.
+///
+/// ```no_run
+/// # use http_cache::{CACacheManager, CacheConfig};
+/// # fn run() -> http_cache::Result<()> {
+/// // e.g. with HTTP_CACHE_MODE=no-cache and HTTP_CACHE_KEY_VERSION=2
+/// let cache = CacheConfig::from_env()?.into_cache(CACacheManager::default());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// See [`HttpCache::mode`].
+    pub mode: Option<CacheMode>,
+    /// See [`HttpCacheOptions::cache_status_headers`].
+    pub cache_status_headers: Option<bool>,
+    /// See [`HttpCacheOptions::store_request`].
+    pub store_request: Option<bool>,
+    /// Whether the cache is shared between users, see [`CacheOptions::shared`].
+    pub shared: Option<bool>,
+    /// See [`HttpCacheOptions::key_version`].
+    pub key_version: Option<u32>,
+    /// See [`HttpCacheOptions::miss_status`].
+    pub miss_status: Option<u16>,
+    /// See [`HttpCacheOptions::revalidate_with_head`].
+    pub revalidate_with_head: Option<bool>,
+    /// See [`HttpCacheOptions::skip_cache_on_set_cookie`].
+    pub skip_cache_on_set_cookie: Option<bool>,
+    /// See [`HttpCacheOptions::max_variants_per_url`].
+    pub max_variants_per_url: Option<usize>,
+    /// See [`HttpCacheOptions::max_concurrent_fetches`].
+    pub max_concurrent_fetches: Option<usize>,
+    /// [`HttpCacheOptions::stale_grace`] in seconds.
+    pub stale_grace_secs: Option<u64>,
+    /// See [`HttpCacheOptions::instance_name`].
+    pub instance_name: Option<String>,
+}
+
+impl CacheConfig {
+    /// Reads the configuration from environment variables named after the fields,
+    /// upper-cased and prefixed with `HTTP_CACHE_`, e.g. `HTTP_CACHE_MODE=no-cache`
+    /// or `HTTP_CACHE_KEY_VERSION=2`. Unset variables leave the field unset.
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    pub(crate) fn from_vars(
+        var: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self> {
+        fn parse<T: FromStr>(
+            var: &impl Fn(&str) -> Option<String>,
+            field: &str,
+        ) -> Result<Option<T>>
+        where
+            T::Err: std::fmt::Display,
+        {
+            let name = format!("HTTP_CACHE_{}", field.to_uppercase());
+            var(&name)
+                .map(|value| {
+                    value.trim().parse().map_err(|e| {
+                        format!("Invalid value for {name}: {e}").into()
+                    })
+                })
+                .transpose()
+        }
+        Ok(Self {
+            mode: parse(&var, "mode")?,
+            cache_status_headers: parse(&var, "cache_status_headers")?,
+            store_request: parse(&var, "store_request")?,
+            shared: parse(&var, "shared")?,
+            key_version: parse(&var, "key_version")?,
+            miss_status: parse(&var, "miss_status")?,
+            revalidate_with_head: parse(&var, "revalidate_with_head")?,
+            skip_cache_on_set_cookie: parse(&var, "skip_cache_on_set_cookie")?,
+            max_variants_per_url: parse(&var, "max_variants_per_url")?,
+            max_concurrent_fetches: parse(&var, "max_concurrent_fetches")?,
+            stale_grace_secs: parse(&var, "stale_grace_secs")?,
+            instance_name: parse(&var, "instance_name")?,
+        })
+    }
+
+    /// Builds [`HttpCacheOptions`] from the defaults, overridden by the set fields.
+    #[must_use]
+    pub fn options(&self) -> HttpCacheOptions {
+        let mut options = HttpCacheOptions::default();
+        if let Some(cache_status_headers) = self.cache_status_headers {
+            options.cache_status_headers = cache_status_headers;
+        }
+        if let Some(store_request) = self.store_request {
+            options.store_request = store_request;
+        }
+        if let Some(shared) = self.shared {
+            options.cache_options =
+                Some(CacheOptions { shared, ..Default::default() });
+        }
+        if let Some(key_version) = self.key_version {
+            options.key_version = key_version;
+        }
+        if let Some(miss_status) = self.miss_status {
+            options.miss_status = miss_status;
+        }
+        if let Some(revalidate_with_head) = self.revalidate_with_head {
+            options.revalidate_with_head = revalidate_with_head;
+        }
+        if let Some(skip_cache_on_set_cookie) = self.skip_cache_on_set_cookie {
+            options.skip_cache_on_set_cookie = skip_cache_on_set_cookie;
+        }
+        options.max_variants_per_url = self.max_variants_per_url;
+        options.max_concurrent_fetches =
+            self.max_concurrent_fetches.map(FetchLimit::new);
+        options.stale_grace = self.stale_grace_secs.map(Duration::from_secs);
+        options.instance_name = self.instance_name.clone();
+        options
+    }
+
+    /// Builds an [`HttpCache`] storing responses with `manager`.
+    pub fn into_cache<T: CacheManager>(self, manager: T) -> HttpCache<T> {
+        HttpCache {
+            mode: self.mode.unwrap_or_default(),
+            manager,
+            options: self.options(),
+        }
+    }
+}
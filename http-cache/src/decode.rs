@@ -0,0 +1,62 @@
+use crate::{error::UnsupportedEncoding, Result};
+
+#[cfg(any(
+    feature = "decode-gzip",
+    feature = "decode-br",
+    feature = "decode-zstd"
+))]
+use crate::error::BodyTooLarge;
+
+use bytes::Bytes;
+
+#[cfg(any(
+    feature = "decode-gzip",
+    feature = "decode-br",
+    feature = "decode-zstd"
+))]
+use std::io::Read;
+
+// Removes a single content coding from `bytes`, failing once the output grows past
+// `limit`
+pub(crate) fn decode(
+    coding: &str,
+    bytes: Bytes,
+    #[allow(unused_variables)] limit: usize,
+) -> Result<Bytes> {
+    match coding.to_ascii_lowercase().as_str() {
+        "identity" => Ok(bytes),
+        #[cfg(feature = "decode-gzip")]
+        "gzip" | "x-gzip" => {
+            read_all(flate2::read::GzDecoder::new(&bytes[..]), limit)
+        }
+        #[cfg(feature = "decode-gzip")]
+        "deflate" => {
+            read_all(flate2::read::ZlibDecoder::new(&bytes[..]), limit)
+        }
+        #[cfg(feature = "decode-br")]
+        "br" => read_all(
+            brotli_decompressor::Decompressor::new(&bytes[..], 4096),
+            limit,
+        ),
+        #[cfg(feature = "decode-zstd")]
+        "zstd" => {
+            read_all(zstd::stream::read::Decoder::new(&bytes[..])?, limit)
+        }
+        _ => Err(Box::new(UnsupportedEncoding)),
+    }
+}
+
+#[cfg(any(
+    feature = "decode-gzip",
+    feature = "decode-br",
+    feature = "decode-zstd"
+))]
+fn read_all(decoder: impl Read, limit: usize) -> Result<Bytes> {
+    let mut decoded = Vec::new();
+    // One byte past the limit tells an output of exactly `limit` bytes from a longer one
+    decoder.take((limit as u64).saturating_add(1)).read_to_end(&mut decoded)?;
+    if decoded.len() > limit {
+        return Err(Box::new(BodyTooLarge));
+    }
+    Ok(decoded.into())
+}
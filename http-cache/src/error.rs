@@ -29,3 +29,76 @@ impl fmt::Display for BadHeader {
 }
 
 impl std::error::Error for BadHeader {}
+
+/// Error type for operations that need runtime hooks which haven't been configured
+#[derive(Debug, Default, Copy, Clone)]
+pub struct MissingRuntimeHook;
+
+impl fmt::Display for MissingRuntimeHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("The spawn and sleep runtime hooks must be configured")
+    }
+}
+
+impl std::error::Error for MissingRuntimeHook {}
+
+/// Error type for operations the cache manager doesn't support
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Unsupported;
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("The operation is not supported by this cache manager")
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Error type for unknown cache modes
+#[derive(Debug, Default, Copy, Clone)]
+pub struct BadCacheMode;
+
+impl fmt::Display for BadCacheMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Unknown cache mode")
+    }
+}
+
+impl std::error::Error for BadCacheMode {}
+
+/// Error type for content codings that can't be decoded
+#[derive(Debug, Default, Copy, Clone)]
+pub struct UnsupportedEncoding;
+
+impl fmt::Display for UnsupportedEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("The content encoding is not supported")
+    }
+}
+
+impl std::error::Error for UnsupportedEncoding {}
+
+/// Error type for response bodies that weren't read to the end
+#[derive(Debug, Default, Copy, Clone)]
+pub struct IncompleteBody;
+
+impl fmt::Display for IncompleteBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("The response body was dropped before it was fully read")
+    }
+}
+
+impl std::error::Error for IncompleteBody {}
+
+/// Error type for decoded response bodies longer than
+/// [`HttpCacheOptions::max_cacheable_body_bytes`](crate::HttpCacheOptions::max_cacheable_body_bytes)
+#[derive(Debug, Default, Copy, Clone)]
+pub struct BodyTooLarge;
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("The decoded response body exceeds the size limit of the cache")
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
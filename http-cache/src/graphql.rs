@@ -0,0 +1,78 @@
+use crate::BodyKey;
+
+use std::{collections::HashSet, sync::Arc};
+
+use http::request;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// Builds a [`BodyKey`] caching GraphQL queries sent with `POST`, keyed by their
+/// operation name and a hash of their variables, so that the same operation with
+/// different variables gets its own entry.
+///
+/// Only the operations in the allowlist are cached. Any other request bypasses the
+/// cache, including requests without an `operationName`, batched requests and bodies
+/// that aren't JSON. The query itself isn't part of the key, so an operation name
+/// must always refer to the same query. Since mutations are sent the same way,
+/// leave them out of the allowlist.
+///
+/// ```
+/// use http_cache::{GraphQlKey, HttpCacheOptions};
+///
+/// let options = HttpCacheOptions {
+///     cacheable_methods: vec![http::Method::POST],
+///     body_key: Some(GraphQlKey::new(["GetUser", "ListItems"]).into_body_key()),
+///     ..Default::default()
+/// };
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "graphql")))]
+#[derive(Debug, Clone, Default)]
+pub struct GraphQlKey {
+    operations: HashSet<String>,
+}
+
+#[derive(Deserialize)]
+struct Operation {
+    #[serde(rename = "operationName")]
+    operation_name: Option<String>,
+    #[serde(default)]
+    variables: serde_json::Value,
+}
+
+impl GraphQlKey {
+    /// Creates a key caching the named operations.
+    #[must_use]
+    pub fn new<I, S>(operations: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self { operations: operations.into_iter().map(Into::into).collect() }
+    }
+
+    /// Builds the cache key component for a request body, e.g.
+    /// `operation=GetUser:variables={sha256}`, or `None` if the operation isn't
+    /// cached.
+    #[must_use]
+    pub fn key(&self, body: &[u8]) -> Option<String> {
+        let operation: Operation = serde_json::from_slice(body).ok()?;
+        let name = operation.operation_name?;
+        if !self.operations.contains(&name) {
+            return None;
+        }
+        // Object members are sorted when serialized, so the hash doesn't depend on
+        // the order the client sent them in
+        let variables = serde_json::to_vec(&operation.variables).ok()?;
+        let hash: String = Sha256::digest(variables)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        Some(format!("operation={name}:variables={hash}"))
+    }
+
+    /// Converts the allowlist into a [`BodyKey`] closure.
+    #[must_use]
+    pub fn into_body_key(self) -> BodyKey {
+        Arc::new(move |_: &request::Parts, body: &[u8]| self.key(body))
+    }
+}
@@ -26,30 +26,73 @@
 //! - `cacache-tokio` (disabled): enable [tokio](https://github.com/tokio-rs/tokio) runtime support for cacache.
 //! - `manager-moka` (disabled): enable [moka](https://github.com/moka-rs/moka),
 //! a high-performance in-memory cache, backend manager.
+//! - `manager-blob` (disabled): enable [`BlobManager`], which implements a backend
+//! manager over any storage implementing the [`BlobStore`] trait.
 //! - `with-http-types` (disabled): enable [http-types](https://github.com/http-rs/http-types)
 //! type conversion support
+//! - `encryption` (disabled): enable [`EncryptedManager`], which encrypts cached records
+//! at rest with AES-GCM.
+//! - `archive` (disabled): enable exporting the cache to, and importing it from, a tar
+//! archive with [`HttpCache::export_archive`] and [`HttpCache::import_archive`], and
+//! serving such an archive read-only with [`ArchiveManager`].
+//! - `graphql` (disabled): enable [`GraphQlKey`], which caches GraphQL queries sent
+//! with `POST` by their operation name and variables.
+//! - `decode-gzip`, `decode-br` and `decode-zstd` (disabled): enable decoding `gzip`
+//! and `deflate`, `br` and `zstd` encoded bodies respectively with
+//! [`HttpResponse::decoded_body`].
+#[cfg(feature = "archive")]
+mod archive;
+mod config;
+mod decode;
 mod error;
+#[cfg(feature = "graphql")]
+mod graphql;
 mod managers;
+mod middleware;
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     convert::TryFrom,
     fmt::{self, Debug},
     str::FromStr,
     sync::Arc,
-    time::SystemTime,
+    time::{Duration, Instant, SystemTime},
 };
 
+use async_lock::Semaphore;
 use bytes::{BufMut, Bytes};
-use futures::StreamExt;
-use http::{header::CACHE_CONTROL, request, response, StatusCode};
+use futures::{
+    future::{AbortHandle, BoxFuture},
+    StreamExt,
+};
+#[cfg(feature = "manager-cacache")]
+use http::header::{ETAG, LAST_MODIFIED};
+use http::{
+    header::{
+        HeaderName, HeaderValue, ACCEPT, ACCEPT_ENCODING,
+        ACCESS_CONTROL_MAX_AGE, AGE, CACHE_CONTROL, CONTENT_ENCODING,
+        CONTENT_LENGTH, CONTENT_LOCATION, CONTENT_TYPE, DATE, EXPIRES,
+        IF_MATCH, IF_UNMODIFIED_SINCE, LOCATION, PRAGMA, RETRY_AFTER,
+        SET_COOKIE, VARY,
+    },
+    request, response, StatusCode,
+};
+use http_body::Frame;
 use http_body_util::combinators::BoxBody;
 use http_body_util::{BodyDataStream, BodyExt, Full};
 use http_cache_semantics::{AfterResponse, BeforeRequest, CachePolicy};
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-pub use error::{BadHeader, BadVersion, BoxError, Result};
+pub use config::CacheConfig;
+use middleware::BodyKeyed;
+pub use middleware::{BoxMiddleware, FnMiddleware};
+
+pub use error::{
+    BadCacheMode, BadHeader, BadVersion, BodyTooLarge, BoxError,
+    IncompleteBody, MissingRuntimeHook, Result, Unsupported,
+    UnsupportedEncoding,
+};
 
 #[cfg(feature = "manager-cacache")]
 pub use managers::cacache::CACacheManager;
@@ -57,6 +100,24 @@ pub use managers::cacache::CACacheManager;
 #[cfg(feature = "manager-moka")]
 pub use managers::moka::MokaManager;
 
+#[cfg(feature = "encryption")]
+pub use managers::encrypted::EncryptedManager;
+
+#[cfg(feature = "manager-blob")]
+pub use managers::blob::{BlobManager, BlobStore};
+
+#[cfg(feature = "archive")]
+pub use managers::archive::ArchiveManager;
+
+#[cfg(feature = "graphql")]
+pub use graphql::GraphQlKey;
+
+pub use managers::fallback::FallbackManager;
+pub use managers::logging::{
+    AccessLogger, AccessOutcome, CacheOperation, LoggingManager,
+};
+pub use managers::mirror::MirrorManager;
+
 // Exposing the moka cache for convenience, renaming to avoid naming conflicts
 #[cfg(feature = "manager-moka")]
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
@@ -68,6 +129,15 @@ pub const XCACHE: &str = "x-cache";
 /// `x-cache-lookup` header: Value will be HIT if a response existed in cache, MISS if not
 pub const XCACHELOOKUP: &str = "x-cache-lookup";
 
+// Stored along with an entry whose revalidation is deferred until that date by a
+// `503 Service Unavailable` response carrying `Retry-After`. Moved out of the headers
+// by the built-in managers when reading the entry, see Markers
+const DEFERRED_UNTIL: &str = "x-http-cache-deferred-until";
+
+// The most memory reserved for a body from a size hint before receiving it, since
+// hints such as Content-Length are set by the origin
+const MAX_PREALLOCATED_BYTES: usize = 1024 * 1024;
+
 /// Represents a basic cache status
 /// Used in the custom headers `x-cache` and `x-cache-lookup`
 #[derive(Debug, Copy, Clone)]
@@ -87,6 +157,23 @@ impl fmt::Display for HitOrMiss {
     }
 }
 
+/// Describes how the cache handled a request, attached to responses when
+/// [`HttpCacheOptions::cache_info`] is enabled.
+///
+/// Client integrations insert it into the extensions of the returned response, so it
+/// can be read without parsing the cache status headers.
+#[derive(Debug, Clone)]
+pub struct CacheInfo {
+    /// Whether the response was served from cache
+    pub hit_or_miss: HitOrMiss,
+    /// The key of the cache entry, `None` when the request wasn't cacheable
+    pub key: Option<String>,
+    /// The current age of the response, `None` when it has no cache policy
+    pub age: Option<Duration>,
+    /// How long the response remains fresh, `None` when it has no cache policy
+    pub ttl: Option<Duration>,
+}
+
 /// Represents an HTTP version
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[non_exhaustive]
@@ -121,12 +208,38 @@ impl fmt::Display for HttpVersion {
 }
 
 /// A basic generic type that represents an HTTP response
-#[derive(Debug)]
 pub struct HttpResponse {
     /// HTTP response body
     body: Body,
     /// HTTP response parts
     parts: Parts,
+    /// How the cache handled the response, if enabled
+    cache_info: Option<CacheInfo>,
+    /// State of the stored entry the response was read from
+    markers: Markers,
+    /// Most bytes `decoded_body` may produce, set for the hooks of the cache
+    decode_limit: Option<usize>,
+}
+
+// The markers are left out, they are an implementation detail of the cache
+impl Debug for HttpResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpResponse")
+            .field("body", &self.body)
+            .field("parts", &self.parts)
+            .field("cache_info", &self.cache_info)
+            .finish()
+    }
+}
+
+// State of a stored entry that is only meant for the cache, kept out of the headers
+// of the response returned by a manager
+#[derive(Debug, Default, Clone, Copy)]
+struct Markers {
+    // The policy couldn't be decoded and was replaced by a stand-in, see OnCorrupt
+    rebuilt_policy: bool,
+    // See DEFERRED_UNTIL
+    deferred_until: Option<SystemTime>,
 }
 
 /// HTTP response body.
@@ -169,23 +282,82 @@ impl Body {
 
     /// Get all bytes of the response, collecting data stream if some.
     pub async fn bytes(self) -> Result<Bytes> {
+        self.bytes_with_hint(0).await
+    }
+
+    /// Like [`Body::bytes`], reserving room for `size_hint` bytes up front, e.g. the
+    /// `Content-Length` of the response from [`Parts::content_length`], to avoid
+    /// growing the buffer repeatedly while collecting large streams.
+    ///
+    /// The hint usually comes from the origin, so at most 1 MiB is reserved before
+    /// the body is actually received, larger bodies grow the buffer from there.
+    pub async fn bytes_with_hint(self, size_hint: usize) -> Result<Bytes> {
         Ok(match self.inner {
             BodyInner::Full(bytes) => bytes,
-            BodyInner::Streaming(boxed_body) => boxed_body
-                .into_data_stream()
-                .collect::<Vec<Result<_>>>()
-                .await
-                .into_iter()
-                .collect::<Result<Vec<_>>>()?
-                .into_iter()
-                .fold(bytes::BytesMut::new(), |mut acc, chunk| {
-                    acc.put(chunk);
-                    acc
-                })
-                .freeze(),
+            BodyInner::Streaming(boxed_body) => {
+                let lower = http_body::Body::size_hint(&boxed_body).lower();
+                let capacity =
+                    size_hint.max(usize::try_from(lower).unwrap_or_default());
+                let mut acc = bytes::BytesMut::with_capacity(
+                    capacity.min(MAX_PREALLOCATED_BYTES),
+                );
+                let mut stream = boxed_body.into_data_stream();
+                while let Some(chunk) = stream.next().await {
+                    acc.put(chunk?);
+                }
+                acc.freeze()
+            }
         })
     }
 
+    // Collects the body as long as it doesn't exceed `limit` bytes. Longer bodies
+    // are returned as a stream instead, with the chunks read so far put back in front.
+    async fn collect_up_to(
+        self,
+        size_hint: usize,
+        limit: usize,
+    ) -> Result<std::result::Result<Bytes, Body>> {
+        let boxed_body = match self.inner {
+            BodyInner::Full(bytes) if bytes.len() > limit => {
+                return Ok(Err(bytes.into()))
+            }
+            BodyInner::Full(bytes) => return Ok(Ok(bytes)),
+            BodyInner::Streaming(boxed_body) => boxed_body,
+        };
+        let mut acc = bytes::BytesMut::with_capacity(
+            size_hint.min(limit).min(MAX_PREALLOCATED_BYTES),
+        );
+        let mut stream = boxed_body.into_data_stream();
+        while let Some(chunk) = stream.next().await {
+            acc.put(chunk?);
+            if acc.len() > limit {
+                let read =
+                    futures::stream::once(async move { Ok(acc.freeze()) });
+                let body = BoxBody::new(http_body_util::StreamBody::new(
+                    read.chain(stream).map(|chunk| chunk.map(Frame::data)),
+                ));
+                return Ok(Err(Body { inner: BodyInner::Streaming(body) }));
+            }
+        }
+        Ok(Ok(acc.freeze()))
+    }
+
+    // Splits the body in two, the first yielding the chunks of the original while
+    // copying them to the second. The copy ends with an IncompleteBody error when
+    // the first is dropped before the end of the stream.
+    fn tee(self) -> (Body, Body) {
+        let boxed_body = match self.inner {
+            BodyInner::Full(bytes) => {
+                return (bytes.clone().into(), bytes.into())
+            }
+            BodyInner::Streaming(boxed_body) => boxed_body,
+        };
+        let (copy, copied) = futures::channel::mpsc::unbounded();
+        let tee =
+            Tee { stream: boxed_body.into_data_stream(), copy: Some(copy) };
+        (Body::wrap_stream(tee), Body::wrap_stream(copied))
+    }
+
     /// Into data stream
     pub fn into_data_stream(self) -> BodyDataStream<BoxBody<Bytes, BoxError>> {
         match self.inner {
@@ -197,6 +369,48 @@ impl Body {
     }
 }
 
+// The stream returned by Body::tee
+struct Tee {
+    stream: BodyDataStream<BoxBody<Bytes, BoxError>>,
+    copy: Option<futures::channel::mpsc::UnboundedSender<Result<Bytes>>>,
+}
+
+impl futures::Stream for Tee {
+    type Item = Result<Bytes>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let item = futures::ready!(self.stream.poll_next_unpin(cx));
+        match &item {
+            Some(Ok(chunk)) => {
+                if let Some(copy) = &self.copy {
+                    copy.unbounded_send(Ok(chunk.clone())).ok();
+                }
+            }
+            Some(Err(_)) => self.abort_copy(),
+            // Dropping the sender ends the copy
+            None => self.copy = None,
+        }
+        std::task::Poll::Ready(item)
+    }
+}
+
+impl Tee {
+    fn abort_copy(&mut self) {
+        if let Some(copy) = self.copy.take() {
+            copy.unbounded_send(Err(Box::new(IncompleteBody))).ok();
+        }
+    }
+}
+
+impl Drop for Tee {
+    fn drop(&mut self) {
+        self.abort_copy();
+    }
+}
+
 impl From<Vec<u8>> for Body {
     fn from(value: Vec<u8>) -> Self {
         Self { inner: BodyInner::Full(value.into()) }
@@ -212,18 +426,100 @@ impl From<Bytes> for Body {
 /// HTTP response parts consists of status, version, response URL and headers.
 ///
 /// Serializable alternative to [`http::response::Parts`].
+///
+/// Headers are always serialized sorted by name, so equal parts produce identical
+/// bytes regardless of insertion order. See [`Parts::canonicalize`].
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Parts {
     /// HTTP response headers
+    #[serde(serialize_with = "serialize_sorted")]
     pub headers: HashMap<String, String>,
     /// HTTP response status code
     pub status: u16,
-    /// HTTP response url
+    /// HTTP response url, which is the final url when the client followed redirects
     pub url: Url,
     /// HTTP response version
     pub version: HttpVersion,
 }
 
+fn serialize_sorted<S: serde::Serializer>(
+    headers: &HashMap<String, String>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    headers.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+impl Parts {
+    /// Returns the value of the `Content-Length` header, if present and valid.
+    pub fn content_length(&self) -> Option<usize> {
+        self.headers
+            .iter()
+            .find(|(name, _)| {
+                name.eq_ignore_ascii_case(CONTENT_LENGTH.as_str())
+            })
+            .and_then(|(_, value)| value.trim().parse().ok())
+    }
+
+    /// Returns a canonical copy of the parts, with lowercased header names and
+    /// trimmed header values.
+    ///
+    /// Combined with the sorted serialization of headers, this lets services sharing
+    /// a cache produce byte-identical entries for the same response.
+    #[must_use]
+    pub fn canonicalize(&self) -> Parts {
+        Parts {
+            headers: self
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    (name.to_ascii_lowercase(), value.trim().to_string())
+                })
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// A serializable snapshot of the request that produced a cached response.
+///
+/// Only recorded when [`HttpCacheOptions::store_request`] is enabled. Useful when
+/// debugging `Vary` mismatches or building cache inspection tools. Credential
+/// headers (`authorization`, `proxy-authorization` and `cookie`) are never recorded.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RequestSnapshot {
+    /// HTTP request method
+    pub method: String,
+    /// HTTP request uri
+    pub uri: String,
+    /// HTTP request headers
+    pub headers: HashMap<String, String>,
+}
+
+impl From<&request::Parts> for RequestSnapshot {
+    fn from(parts: &request::Parts) -> Self {
+        let headers = parts
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                *name != http::header::AUTHORIZATION
+                    && *name != http::header::PROXY_AUTHORIZATION
+                    && *name != http::header::COOKIE
+            })
+            .filter_map(|(name, value)| {
+                Some((
+                    name.as_str().to_string(),
+                    value.to_str().ok()?.to_string(),
+                ))
+            })
+            .collect();
+        Self {
+            method: parts.method.as_str().to_string(),
+            uri: parts.uri.to_string(),
+            headers,
+        }
+    }
+}
+
 impl HttpResponse {
     /// Consumes the response returning the head and body parts.
     pub fn into_parts(self) -> (Parts, Body) {
@@ -232,7 +528,78 @@ impl HttpResponse {
 
     /// Creates a new Response with the given head and body.
     pub fn from_parts(parts: Parts, body: Body) -> Self {
-        Self { body, parts }
+        Self {
+            body,
+            parts,
+            cache_info: None,
+            markers: Markers::default(),
+            decode_limit: None,
+        }
+    }
+
+    // Creates a response read back from a manager, moving the markers stored by the
+    // cache out of its headers
+    #[cfg(any(
+        feature = "manager-cacache",
+        feature = "manager-moka",
+        feature = "manager-blob",
+        feature = "encryption"
+    ))]
+    pub(crate) fn from_stored(parts: Parts, body: Body) -> Self {
+        let mut res = Self::from_parts(parts, body);
+        res.hide_markers();
+        res
+    }
+
+    fn hide_markers(&mut self) {
+        if let Some(until) = self.parts.headers.remove(DEFERRED_UNTIL) {
+            self.markers.deferred_until =
+                httpdate::parse_http_date(&until).ok();
+        }
+    }
+
+    // Collects a streaming body in memory, so that it can be inspected
+    async fn buffered(self) -> Result<Self> {
+        let size_hint = self.parts.content_length().unwrap_or(0);
+        let body = self.body.bytes_with_hint(size_hint).await?;
+        Ok(Self { body: body.into(), ..self })
+    }
+
+    /// Returns a copy of the body with the codings listed in its `Content-Encoding`
+    /// header removed, for hooks that need to inspect it. The response itself, and
+    /// therefore what gets stored, is left untouched.
+    ///
+    /// Codings whose `decode-*` feature isn't enabled fail with an
+    /// [`UnsupportedEncoding`] error. Streaming bodies have to be collected first.
+    /// Within [`HttpCacheOptions::metadata_fn`], decoding fails with a [`BodyTooLarge`]
+    /// error once the output exceeds [`HttpCacheOptions::max_cacheable_body_bytes`].
+    pub fn decoded_body(&self) -> Result<Bytes> {
+        let BodyInner::Full(body) = &self.body.inner else {
+            return Err(
+                "Streaming bodies must be collected to be decoded".into()
+            );
+        };
+        let mut body = body.clone();
+        if let Some(encoding) =
+            self.parts.headers.get(CONTENT_ENCODING.as_str())
+        {
+            // Codings are listed in the order they were applied
+            for coding in
+                encoding.rsplit(',').map(str::trim).filter(|c| !c.is_empty())
+            {
+                body = decode::decode(
+                    coding,
+                    body,
+                    self.decode_limit.unwrap_or(usize::MAX),
+                )?;
+            }
+        }
+        Ok(body)
+    }
+
+    /// Returns how the cache handled the response, see [`HttpCacheOptions::cache_info`].
+    pub fn cache_info(&self) -> Option<&CacheInfo> {
+        self.cache_info.as_ref()
     }
 
     /// Returns `http::response::Parts`
@@ -243,8 +610,8 @@ impl HttpResponse {
             let headers = converted.headers_mut();
             for header in &self.parts.headers {
                 headers.insert(
-                    http::header::HeaderName::from_str(header.0.as_str())?,
-                    http::HeaderValue::from_str(header.1.as_str())?,
+                    HeaderName::from_str(header.0.as_str())?,
+                    HeaderValue::from_str(header.1.as_str())?,
                 );
             }
         }
@@ -260,7 +627,22 @@ impl HttpResponse {
     }
 
     /// Adds a warning header to a response
+    ///
+    /// The warn-agent is the host of `url`, or `-` if it doesn't have one. Any
+    /// warning already present is replaced, so warnings never pile up on a response
+    /// served stale repeatedly.
     pub fn add_warning(&mut self, url: &Url, code: usize, message: &str) {
+        self.add_warning_with_agent(url, "-", code, message);
+    }
+
+    // Like add_warning, using `pseudonym` as the warn-agent of hostless urls
+    fn add_warning_with_agent(
+        &mut self,
+        url: &Url,
+        pseudonym: &str,
+        code: usize,
+        message: &str,
+    ) {
         // warning    = "warning" ":" 1#warning-value
         // warning-value = warn-code SP warn-agent SP warn-text [SP warn-date]
         // warn-code  = 3DIGIT
@@ -270,16 +652,40 @@ impl HttpResponse {
         // warn-text  = quoted-string
         // warn-date  = <"> HTTP-date <">
         // (https://tools.ietf.org/html/rfc2616#section-14.46)
-        self.parts.headers.insert(
-            "warning".to_string(),
-            format!(
-                "{} {} {:?} \"{}\"",
-                code,
-                url.host().expect("Invalid URL"),
-                message,
-                httpdate::fmt_http_date(SystemTime::now())
-            ),
+        let warning = format!(
+            "{} {} {:?} \"{}\"",
+            code,
+            // urls without a host, e.g. `data:` urls, use a pseudonym
+            url.host_str().unwrap_or(pseudonym),
+            message,
+            httpdate::fmt_http_date(SystemTime::now())
         );
+        self.retain_warnings(|warn_code| warn_code != code);
+        // The headers hold a single value per name, so warnings are joined
+        let warning = match self.parts.headers.remove("warning") {
+            Some(warnings) => format!("{warnings}, {warning}"),
+            None => warning,
+        };
+        self.parts.headers.insert("warning".to_string(), warning);
+    }
+
+    // Keeps the warning values whose warn-code passes `keep`
+    fn retain_warnings(&mut self, keep: impl Fn(usize) -> bool) {
+        let Some(warnings) = self.parts.headers.remove("warning") else {
+            return;
+        };
+        let kept: Vec<_> = warning_values(&warnings)
+            .into_iter()
+            .filter(|value| {
+                value
+                    .get(..3)
+                    .and_then(|code| code.parse().ok())
+                    .map_or(true, &keep)
+            })
+            .collect();
+        if !kept.is_empty() {
+            self.parts.headers.insert("warning".to_string(), kept.join(", "));
+        }
     }
 
     /// Removes a warning header from a response
@@ -306,11 +712,153 @@ impl HttpResponse {
         })
     }
 
+    /// Checks if the Cache-Control header contains the no-transform directive
+    ///
+    /// Responses carrying this directive must be stored and served without any
+    /// modification to their body, e.g. no compression may be applied.
+    #[must_use]
+    pub fn is_no_transform(&self) -> bool {
+        self.parts.headers.get(CACHE_CONTROL.as_str()).is_some_and(|val| {
+            val.as_str().to_lowercase().contains("no-transform")
+        })
+    }
+
+    // Redirects are stored along with their Location header. The policy only
+    // allows 301 and 308 to be stored without explicit freshness information.
+    fn is_redirect(&self) -> bool {
+        matches!(self.parts.status, 301 | 302 | 303 | 307 | 308)
+            && self.parts.headers.contains_key(LOCATION.as_str())
+    }
+
+    // Whether the response states its own freshness lifetime
+    fn has_explicit_freshness(&self) -> bool {
+        self.parts.headers.contains_key(EXPIRES.as_str())
+            || self.parts.headers.get(CACHE_CONTROL.as_str()).is_some_and(
+                |val| {
+                    val.split(',').any(|directive| {
+                        let name = directive.split('=').next().unwrap_or("");
+                        ["max-age", "s-maxage"]
+                            .contains(&name.trim().to_lowercase().as_str())
+                    })
+                },
+            )
+    }
+
+    // Whether a stored response that is stale at `now` is still worth keeping, as it
+    // carries validators for a conditional request or is within the window of its
+    // stale-while-revalidate or stale-if-error directive
+    #[cfg(feature = "manager-cacache")]
+    pub(crate) fn outlives_freshness(
+        &self,
+        policy: &CachePolicy,
+        now: SystemTime,
+    ) -> bool {
+        let headers = &self.parts.headers;
+        if headers.contains_key(ETAG.as_str())
+            || headers.contains_key(LAST_MODIFIED.as_str())
+        {
+            return true;
+        }
+        let window = headers
+            .get(CACHE_CONTROL.as_str())
+            .into_iter()
+            .flat_map(|val| val.split(','))
+            .filter_map(|directive| {
+                let (name, secs) = directive.split_once('=')?;
+                ["stale-while-revalidate", "stale-if-error"]
+                    .contains(&name.trim().to_lowercase().as_str())
+                    .then(|| secs.trim().trim_matches('"').parse().ok())?
+            })
+            .max();
+        // Either received within the window, or stale for less than it
+        window.is_some_and(|secs| {
+            now.checked_sub(Duration::from_secs(secs)).map_or(true, |since| {
+                policy.age(since) == policy.age(SystemTime::UNIX_EPOCH)
+                    || is_fresh(policy, since)
+            })
+        })
+    }
+
+    // Marks a stored response whose policy couldn't be decoded, returning a stand-in
+    // policy built from its headers, see Markers
+    #[cfg(feature = "manager-cacache")]
+    pub(crate) fn stand_in_policy(&mut self) -> Result<CachePolicy> {
+        self.markers.rebuilt_policy = true;
+        let (req, ()) =
+            http::Request::get(self.parts.url.as_str()).body(())?.into_parts();
+        Ok(CachePolicy::new(&req, &self.parts()?))
+    }
+
+    // Whether the policy was replaced by a stand-in, see stand_in_policy
+    fn take_rebuilt_policy(&mut self) -> bool {
+        std::mem::take(&mut self.markers.rebuilt_policy)
+    }
+
+    // The date revalidation is deferred until, see DEFERRED_UNTIL. Responses of
+    // managers that don't move it out of the headers still carry it there
+    fn take_deferred_until(&mut self) -> Option<SystemTime> {
+        self.hide_markers();
+        self.markers.deferred_until.take()
+    }
+
+    // The point in time a `Retry-After` header, either a date or a number of
+    // seconds, asks clients to wait until
+    fn retry_after(&self, now: SystemTime) -> Option<SystemTime> {
+        let retry_after = self.parts.headers.get(RETRY_AFTER.as_str())?.trim();
+        match retry_after.parse::<u64>() {
+            Ok(secs) => now.checked_add(Duration::from_secs(secs)),
+            Err(_) => httpdate::parse_http_date(retry_after).ok(),
+        }
+    }
+
+    fn varies_on_any(&self) -> bool {
+        self.parts
+            .headers
+            .get(VARY.as_str())
+            .is_some_and(|val| val.split(',').any(|name| name.trim() == "*"))
+    }
+
+    /// Ensures an encoded response varies on `Accept-Encoding`, so that it is
+    /// never served from cache to a client that didn't ask for that encoding.
+    fn vary_on_content_encoding(&mut self) {
+        if !self.parts.headers.contains_key(CONTENT_ENCODING.as_str()) {
+            return;
+        }
+        match self.parts.headers.get_mut(VARY.as_str()) {
+            Some(vary)
+                if vary.split(',').any(|name| {
+                    name.trim().eq_ignore_ascii_case(ACCEPT_ENCODING.as_str())
+                }) => {}
+            Some(vary) => {
+                vary.push_str(", ");
+                vary.push_str(ACCEPT_ENCODING.as_str());
+            }
+            None => {
+                self.parts
+                    .headers
+                    .insert(VARY.to_string(), ACCEPT_ENCODING.to_string());
+            }
+        }
+    }
+
     /// Adds the custom `x-cache` header to the response
     pub fn cache_status(&mut self, hit_or_miss: HitOrMiss) {
         self.parts.headers.insert(XCACHE.to_string(), hit_or_miss.to_string());
     }
 
+    // Like cache_status, naming the instance that handled the request if known
+    fn cache_status_from(
+        &mut self,
+        hit_or_miss: HitOrMiss,
+        instance_name: Option<&str>,
+    ) {
+        let value = match instance_name {
+            Some(name) => format!("{hit_or_miss} from {name}"),
+            None => hit_or_miss.to_string(),
+        };
+        self.parts.headers.insert(XCACHE.to_string(), value);
+    }
+
     /// Adds the custom `x-cache-lookup` header to the response
     pub fn cache_lookup_status(&mut self, hit_or_miss: HitOrMiss) {
         self.parts
@@ -330,6 +878,10 @@ pub trait CacheManager: Send + Sync + 'static {
         cache_key: &str,
     ) -> Result<Option<(HttpResponse, CachePolicy)>>;
     /// Attempts to cache a response and related policy.
+    ///
+    /// If reading the body fails midway, the error must be returned without storing
+    /// anything, so that an incomplete body is never served from cache. The cache
+    /// returns the error to the caller, since the body can't be forwarded either.
     async fn put(
         &self,
         cache_key: String,
@@ -338,6 +890,200 @@ pub trait CacheManager: Send + Sync + 'static {
     ) -> Result<HttpResponse>;
     /// Attempts to remove a record from cache.
     async fn delete(&self, cache_key: &str) -> Result<()>;
+    /// Attempts to extend the life of a record to `new_ttl` from now without refetching it,
+    /// returning whether the record existed.
+    ///
+    /// This allows sliding-expiration policies to be layered over the HTTP semantics.
+    /// "Now" is the system time, as managers don't see [`HttpCacheOptions::clock`], so
+    /// with a custom clock the retention is measured against a different time than the
+    /// one [`HttpCache::spawn_pruner`] passes to [`CacheManager::evict_expired`].
+    /// The default implementation returns an [`Unsupported`] error, managers whose
+    /// backend supports updating the expiration of a record should override it.
+    async fn touch(
+        &self,
+        _cache_key: &str,
+        _new_ttl: Duration,
+    ) -> Result<bool> {
+        Err(Box::new(Unsupported))
+    }
+    /// Attempts to list the keys of all records in cache.
+    ///
+    /// The default implementation returns an [`Unsupported`] error, managers whose
+    /// backend can enumerate its records should override it.
+    async fn keys(&self) -> Result<Vec<String>> {
+        Err(Box::new(Unsupported))
+    }
+    /// Attempts to list the keys of the stored variants of the url identified by `base_key`.
+    ///
+    /// Used along with [`CacheManager::set_variants`] to enforce
+    /// [`HttpCacheOptions::max_variants_per_url`]. The default implementation returns an
+    /// [`Unsupported`] error.
+    async fn variants(&self, _base_key: &str) -> Result<Vec<String>> {
+        Err(Box::new(Unsupported))
+    }
+    /// Attempts to replace the keys of the stored variants of the url identified by `base_key`.
+    ///
+    /// The default implementation returns an [`Unsupported`] error.
+    async fn set_variants(
+        &self,
+        _base_key: &str,
+        _variants: Vec<String>,
+    ) -> Result<()> {
+        Err(Box::new(Unsupported))
+    }
+    /// Attempts to remove all records that are no longer fresh at `now` from cache.
+    ///
+    /// Stale records that could still be revalidated with a conditional request, because
+    /// they carry an `ETag` or `Last-Modified` header, are kept, as are the ones still
+    /// within the window of their `stale-while-revalidate` or `stale-if-error` directive.
+    ///
+    /// The default implementation does nothing, which suits managers that expire
+    /// records on their own.
+    async fn evict_expired(&self, _now: SystemTime) -> Result<()> {
+        Ok(())
+    }
+    /// Attempts to determine when a record was last read from or written to cache.
+    ///
+    /// Managers that track access times update them on each [`CacheManager::get`] hit
+    /// without rewriting the stored body. The default implementation doesn't track
+    /// access times and always returns `None`.
+    async fn last_accessed(
+        &self,
+        _cache_key: &str,
+    ) -> Result<Option<SystemTime>> {
+        Ok(None)
+    }
+    /// Attempts to pull a cached response and related policy from cache without
+    /// counting it as an access, for inspection tools that shouldn't affect
+    /// eviction.
+    ///
+    /// Unlike [`CacheManager::get`], it doesn't update access times, hit statistics
+    /// or recency. The default implementation delegates to [`CacheManager::get`],
+    /// managers that track accesses should override it.
+    async fn peek(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        self.get(cache_key).await
+    }
+    /// Attempts to pull a cached response, related policy, the stored request snapshot
+    /// (if any) and metadata annotations from cache without counting it as an access,
+    /// e.g. to copy every record to another store.
+    ///
+    /// The default implementation delegates to [`CacheManager::peek`] and never returns
+    /// a snapshot or any metadata.
+    async fn peek_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<
+        Option<(
+            HttpResponse,
+            CachePolicy,
+            Option<RequestSnapshot>,
+            HashMap<String, String>,
+        )>,
+    > {
+        Ok(self
+            .peek(cache_key)
+            .await?
+            .map(|(res, policy)| (res, policy, None, HashMap::new())))
+    }
+    /// Attempts to cache a response and related policy along with a snapshot of the request that produced it.
+    ///
+    /// The default implementation discards the snapshot and delegates to [`CacheManager::put`].
+    async fn put_with_request(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+        _request: RequestSnapshot,
+    ) -> Result<HttpResponse> {
+        self.put(cache_key, res, policy).await
+    }
+    /// Attempts to pull a cached response, related policy and the stored request snapshot (if any) from cache.
+    ///
+    /// The default implementation delegates to [`CacheManager::get`] and never returns a snapshot.
+    async fn get_with_request(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, Option<RequestSnapshot>)>>
+    {
+        Ok(self.get(cache_key).await?.map(|(res, policy)| (res, policy, None)))
+    }
+    /// Attempts to cache a response and related policy along with metadata annotations,
+    /// see [`HttpCacheOptions::metadata_fn`], and the snapshot of the request that
+    /// produced it, if any.
+    ///
+    /// The default implementation discards the metadata and delegates to
+    /// [`CacheManager::put_with_request`] or [`CacheManager::put`].
+    async fn put_with_metadata(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+        request: Option<RequestSnapshot>,
+        _metadata: HashMap<String, String>,
+    ) -> Result<HttpResponse> {
+        match request {
+            Some(request) => {
+                self.put_with_request(cache_key, res, policy, request).await
+            }
+            None => self.put(cache_key, res, policy).await,
+        }
+    }
+    /// Attempts to pull a cached response, related policy and the metadata annotations
+    /// stored along with it from cache.
+    ///
+    /// The default implementation delegates to [`CacheManager::get`] and never returns
+    /// any metadata.
+    async fn get_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, HashMap<String, String>)>>
+    {
+        Ok(self
+            .get(cache_key)
+            .await?
+            .map(|(res, policy)| (res, policy, HashMap::new())))
+    }
+    /// Attempts to pull the cached responses and related policies of several keys at
+    /// once, in the order of `cache_keys`.
+    ///
+    /// The default implementation calls [`CacheManager::get`] for each key in turn,
+    /// managers whose backend supports batch reads should override it.
+    async fn get_many(
+        &self,
+        cache_keys: &[String],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        let mut records = Vec::with_capacity(cache_keys.len());
+        for cache_key in cache_keys {
+            records.push(self.get(cache_key).await?);
+        }
+        Ok(records)
+    }
+    /// Attempts to cache several responses and related policies at once, returning the
+    /// responses in the order of `entries`.
+    ///
+    /// The default implementation calls [`CacheManager::put`] for each entry in turn,
+    /// managers whose backend supports batch writes should override it.
+    async fn put_many(
+        &self,
+        entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<Vec<HttpResponse>> {
+        let mut responses = Vec::with_capacity(entries.len());
+        for (cache_key, res, policy) in entries {
+            responses.push(self.put(cache_key, res, policy).await?);
+        }
+        Ok(responses)
+    }
+    /// Attempts to create an owned handle to the same storage, used to store responses
+    /// revalidated in the background, see [`HttpCacheOptions::stale_grace`].
+    ///
+    /// The default implementation returns `None`, in which case stale responses are
+    /// always revalidated before being returned.
+    fn detach(&self) -> Option<Box<dyn CacheManager>> {
+        None
+    }
 }
 
 /// Describes the functionality required for interfacing with HTTP client middleware
@@ -363,13 +1109,55 @@ pub trait Middleware: Send {
     fn update_headers(&mut self, parts: &request::Parts) -> Result<()>;
     /// Attempts to force the "no-cache" directive on the request
     fn force_no_cache(&mut self) -> Result<()>;
+    /// Attempts to change the request method, used to revalidate with a `HEAD` request
+    /// when [`HttpCacheOptions::revalidate_with_head`] is set.
+    ///
+    /// The default implementation returns an [`Unsupported`] error, in which case the
+    /// cache revalidates with the original request instead.
+    fn set_method(&mut self, _method: http::Method) -> Result<()> {
+        Err(Box::new(Unsupported))
+    }
     /// Attempts to construct `http::request::Parts` from the request
     fn parts(&self) -> Result<request::Parts>;
     /// Attempts to determine the requested url
     fn url(&self) -> Result<Url>;
     /// Attempts to determine the request method
     fn method(&self) -> Result<String>;
+    /// Attempts to determine the negotiated HTTP version of the request, returning
+    /// `None` if it isn't known. Used when the cache synthesizes a response.
+    fn version(&self) -> Option<HttpVersion> {
+        None
+    }
+    /// Attempts to provide the request body, returning `None` if there is no body
+    /// or the body cannot be inspected.
+    ///
+    /// Streaming request bodies can only be exposed by buffering them in memory
+    /// first, so implementations should return `Ok(None)` rather than consume a
+    /// body that still needs to be sent upstream.
+    fn request_body(&self) -> Result<Option<Bytes>> {
+        Ok(None)
+    }
+    /// Attempts to create an owned copy of the request that can be sent after the
+    /// current one completes, used to revalidate in the background, see
+    /// [`HttpCacheOptions::stale_grace`].
+    ///
+    /// The default implementation returns `None`, in which case stale responses are
+    /// always revalidated before being returned.
+    fn detach(&self) -> Option<BoxMiddleware> {
+        None
+    }
+    /// Returns the time the response returned by [`Middleware::remote_fetch`] was
+    /// received, if known. The age of the response is computed from it, which is more
+    /// accurate when time passes between receiving and storing the response. Defaults
+    /// to `None`, in which case the time the policy is built is used.
+    fn received_at(&self) -> Option<SystemTime> {
+        None
+    }
     /// Attempts to fetch an upstream resource and return an [`HttpResponse`]
+    ///
+    /// The [`Parts::url`] of the response must be the url it was received from, i.e.
+    /// the final url when the client follows redirects. The cache key is still built
+    /// from the request url, so that later requests for it are served from cache.
     async fn remote_fetch(&mut self) -> Result<HttpResponse>;
 }
 
@@ -402,6 +1190,9 @@ pub enum CacheMode {
     /// not paying attention to staleness. If there was no response,
     /// it returns a network error.
     OnlyIfCached,
+    /// Like [`CacheMode::OnlyIfCached`], but only uses fresh responses.
+    /// If there was no fresh response, it returns a network error.
+    OnlyIfCachedFresh,
     /// Overrides the check that determines if a response can be cached to always return true on 200.
     /// Uses any response in the HTTP cache matching the request,
     /// not paying attention to staleness. If there was no response,
@@ -409,6 +1200,70 @@ pub enum CacheMode {
     IgnoreRules,
 }
 
+impl CacheMode {
+    const ALL: [Self; 8] = [
+        Self::Default,
+        Self::NoStore,
+        Self::Reload,
+        Self::NoCache,
+        Self::ForceCache,
+        Self::OnlyIfCached,
+        Self::OnlyIfCachedFresh,
+        Self::IgnoreRules,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::NoStore => "no-store",
+            Self::Reload => "reload",
+            Self::NoCache => "no-cache",
+            Self::ForceCache => "force-cache",
+            Self::OnlyIfCached => "only-if-cached",
+            Self::OnlyIfCachedFresh => "only-if-cached-fresh",
+            Self::IgnoreRules => "ignore-rules",
+        }
+    }
+}
+
+/// Formats the kebab-case name of the mode, e.g. `no-store`, which [`FromStr`] parses back.
+impl fmt::Display for CacheMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for CacheMode {
+    type Err = BadCacheMode;
+
+    /// Parses the kebab-case name of a mode, ignoring case, e.g. `no-store`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.as_str().eq_ignore_ascii_case(s))
+            .ok_or(BadCacheMode)
+    }
+}
+
+impl Serialize for CacheMode {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CacheMode {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 impl TryFrom<http::Version> for HttpVersion {
     type Error = BoxError;
 
@@ -465,6 +1320,15 @@ impl From<HttpVersion> for http_types::Version {
     }
 }
 
+/// Determines if a stored response described by the given [`CachePolicy`] is fresh at `now`.
+///
+/// Unlike [`CachePolicy::before_request`] this doesn't need the request parts, which makes it
+/// useful for inspecting stored entries outside of the request flow.
+#[must_use]
+pub fn is_fresh(policy: &CachePolicy, now: SystemTime) -> bool {
+    !policy.time_to_live(now).is_zero()
+}
+
 /// Options struct provided by
 /// [`http-cache-semantics`](https://github.com/kornelski/rusty-http-cache-semantics).
 pub use http_cache_semantics::CacheOptions;
@@ -473,31 +1337,596 @@ pub use http_cache_semantics::CacheOptions;
 /// By default, the cache key is a combination of the request method and uri with a colon in between.
 pub type CacheKey = Arc<dyn Fn(&request::Parts) -> String + Send + Sync>;
 
-/// A closure that takes [`http::request::Parts`] and returns a [`CacheMode`]
-pub type CacheModeFn = Arc<dyn Fn(&request::Parts) -> CacheMode + Send + Sync>;
+/// A declarative builder for common [`CacheKey`] shapes.
+///
+/// Each selected component is appended in the order it was added, separated by a colon.
+/// Headers are included as `name=value`, with an empty value when the header is missing.
+/// For anything more involved, provide a [`CacheKey`] closure directly.
+///
+/// ```
+/// use http_cache::KeySpec;
+///
+/// // Produces keys like `GET:example.com:/items:x-api-version=2`, ignoring the query
+/// let cache_key =
+///     KeySpec::new().method().host().path().header("x-api-version").into_cache_key();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct KeySpec {
+    components: Vec<KeyComponent>,
+}
 
-/// A closure that takes [`http::request::Parts`], [`Option<CacheKey>`], the default cache key ([`&str``]) and returns [`Vec<String>`] of keys to bust the cache for.
-/// An empty vector means that no cache busting will be performed.
+#[derive(Debug, Clone)]
+enum KeyComponent {
+    Method,
+    Host,
+    Path,
+    Query,
+    Header(String),
+}
+
+impl KeySpec {
+    /// Creates an empty key specification.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Includes the request method.
+    #[must_use]
+    pub fn method(mut self) -> Self {
+        self.components.push(KeyComponent::Method);
+        self
+    }
+
+    /// Includes the request host, along with the port if present.
+    #[must_use]
+    pub fn host(mut self) -> Self {
+        self.components.push(KeyComponent::Host);
+        self
+    }
+
+    /// Includes the request path.
+    #[must_use]
+    pub fn path(mut self) -> Self {
+        self.components.push(KeyComponent::Path);
+        self
+    }
+
+    /// Includes the request query string.
+    #[must_use]
+    pub fn query(mut self) -> Self {
+        self.components.push(KeyComponent::Query);
+        self
+    }
+
+    /// Includes the value of the named request header.
+    #[must_use]
+    pub fn header(mut self, name: &str) -> Self {
+        self.components.push(KeyComponent::Header(name.to_ascii_lowercase()));
+        self
+    }
+
+    /// Builds the cache key for the given request parts.
+    #[must_use]
+    pub fn key(&self, parts: &request::Parts) -> String {
+        self.components
+            .iter()
+            .map(|component| match component {
+                KeyComponent::Method => parts.method.to_string(),
+                KeyComponent::Host => parts
+                    .uri
+                    .authority()
+                    .map(|authority| authority.as_str())
+                    .or_else(|| {
+                        parts
+                            .headers
+                            .get(http::header::HOST)
+                            .and_then(|host| host.to_str().ok())
+                    })
+                    .unwrap_or_default()
+                    .to_string(),
+                KeyComponent::Path => parts.uri.path().to_string(),
+                KeyComponent::Query => {
+                    parts.uri.query().unwrap_or_default().to_string()
+                }
+                KeyComponent::Header(name) => format!(
+                    "{}={}",
+                    name,
+                    parts
+                        .headers
+                        .get(name.as_str())
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or_default()
+                ),
+            })
+            .collect::<Vec<_>>()
+            .join(":")
+    }
+
+    /// Converts the specification into a [`CacheKey`] closure.
+    #[must_use]
+    pub fn into_cache_key(self) -> CacheKey {
+        Arc::new(move |parts: &request::Parts| self.key(parts))
+    }
+}
+
+/// A closure that takes [`http::request::Parts`] and the request body and returns a
+/// component of the cache key, or `None` if the request must bypass the cache.
+pub type BodyKey =
+    Arc<dyn Fn(&request::Parts, &[u8]) -> Option<String> + Send + Sync>;
+
+// The component of the cache key derived from the request body by
+// HttpCacheOptions::body_key, carried in the request extensions
+#[derive(Debug, Clone)]
+pub(crate) struct BodyKeyComponent(pub(crate) String);
+
+/// A closure that takes [`http::request::Parts`] and returns a [`CacheMode`]
+pub type CacheModeFn = Arc<dyn Fn(&request::Parts) -> CacheMode + Send + Sync>;
+
+/// A closure that takes [`http::request::Parts`] and returns whether the cache status
+/// headers should be added to the response
+pub type CacheStatusHeadersFn =
+    Arc<dyn Fn(&request::Parts) -> bool + Send + Sync>;
+
+/// A closure that takes [`http::request::Parts`] and returns the identity of the user
+/// making the request, if any
+pub type IdentityFn =
+    Arc<dyn Fn(&request::Parts) -> Option<String> + Send + Sync>;
+
+/// A closure that takes [`http::request::Parts`] and the [`HttpResponse`] about to be
+/// stored, and returns the metadata annotations stored along with it
+pub type MetadataFn = Arc<
+    dyn Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>
+        + Send
+        + Sync,
+>;
+
+/// A closure that takes [`http::request::Parts`] and returns the response to answer the
+/// request with when it can be neither served from cache nor fetched, if any
+pub type FallbackFn =
+    Arc<dyn Fn(&request::Parts) -> Option<HttpResponse> + Send + Sync>;
+
+/// A closure that takes the value of an `Accept` request header and returns its
+/// canonical form.
+pub type AcceptNormalizer = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// A closure that takes [`http::request::Parts`] and returns [`CacheOptions`]
+pub type CacheOptionsFn =
+    Arc<dyn Fn(&request::Parts) -> CacheOptions + Send + Sync>;
+
+/// A closure that takes [`http::request::Parts`], [`Option<CacheKey>`], the default cache key ([`&str``]) and returns [`Vec<String>`] of keys to bust the cache for.
+/// An empty vector means that no cache busting will be performed.
 pub type CacheBust = Arc<
     dyn Fn(&request::Parts, &Option<CacheKey>, &str) -> Vec<String>
         + Send
         + Sync,
 >;
 
+/// A closure that takes a request [`Url`] and returns a [`bool`].
+pub type UrlMatcher = Arc<dyn Fn(&Url) -> bool + Send + Sync>;
+
+// The freshness lifetime, in seconds, given to the responses matched by
+// `HttpCacheOptions::heuristic_immutable_matcher`
+const IMMUTABLE_MAX_AGE: u64 = 31_536_000;
+
+/// A closure that spawns the passed future onto an async runtime as a background task.
+pub type SpawnFn = Arc<dyn Fn(BoxFuture<'static, ()>) + Send + Sync>;
+
+/// A closure that returns a future which completes after the passed [`Duration`].
+pub type SleepFn =
+    Arc<dyn Fn(Duration) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// A closure that returns the current time, see [`HttpCacheOptions::clock`].
+pub type ClockFn = Arc<dyn Fn() -> SystemTime + Send + Sync>;
+
+/// A closure that returns whether the network is known to be unreachable, see
+/// [`HttpCacheOptions::is_offline`].
+pub type OfflineFn = Arc<dyn Fn() -> bool + Send + Sync>;
+
+/// A closure that takes the name of a header listed in `Vary`, its stored value and its
+/// requested value, and returns whether they match.
+pub type VaryMatcher =
+    Arc<dyn Fn(&HeaderName, &str, &str) -> bool + Send + Sync>;
+
+/// A closure that modifies an [`HttpResponse`] before it is returned.
+pub type ResponseTransform = Arc<dyn Fn(&mut HttpResponse) + Send + Sync>;
+
+/// A closure that takes an [`HttpResponse`] and returns an [`Option<CachePolicy>`].
+/// Returning `Some` bypasses the policy derived from the response headers.
+pub type PolicyOverride =
+    Arc<dyn Fn(&HttpResponse) -> Option<CachePolicy> + Send + Sync>;
+
+/// Receives notifications about the work done by the cache, e.g. to collect metrics.
+///
+/// All methods default to doing nothing, so implementors only override the ones they
+/// need. They are called inline, so they should return quickly.
+pub trait CacheObserver: Send + Sync {
+    /// Called once a stale response has been revalidated with the origin, whatever the
+    /// outcome, with the time spent on the revalidation request(s). Cold fetches of
+    /// uncached responses are not reported.
+    fn on_revalidation(
+        &self,
+        _cache_key: &str,
+        _revalidation_duration: Duration,
+    ) {
+    }
+    /// Called with the [`CacheMode`] applied to a request to `url`, after taking
+    /// [`Middleware::overridden_cache_mode`], [`HttpCacheOptions::cache_mode_fn`] and
+    /// the request headers into account. Called once per request handled by
+    /// [`HttpCache::run`], and on each call to [`HttpCache::can_cache_request`].
+    fn on_mode_resolved(&self, _url: &Url, _mode: CacheMode) {}
+    /// Called when revalidating the stored response for `cache_key` returned a new
+    /// `200 OK` response, with the `old` response about to be replaced by the `new` one.
+    ///
+    /// Both bodies are buffered in memory to be inspected, so whenever an observer is
+    /// set, revalidations returning new content hold two copies of it at once.
+    fn on_content_changed(
+        &self,
+        _cache_key: &str,
+        _old: &HttpResponse,
+        _new: &HttpResponse,
+    ) {
+    }
+    /// Called when revalidating the stored response for `cache_key` returned a
+    /// `304 Not Modified` carrying a body, which a 304 must not have. Whether the
+    /// body replaces the cached one depends on [`HttpCacheOptions::trust_304_body`].
+    fn on_not_modified_body(&self, _cache_key: &str, _body: &[u8]) {}
+}
+
+/// Limits the number of concurrent requests made to the origin, see
+/// [`HttpCacheOptions::max_concurrent_fetches`].
+///
+/// Clones share the same limit. The limit is enforced with a runtime agnostic
+/// semaphore, so it works with any async runtime.
+#[derive(Clone)]
+pub struct FetchLimit {
+    max: usize,
+    semaphore: Arc<Semaphore>,
+}
+
+impl FetchLimit {
+    /// Create a new limit allowing at most `max` concurrent requests.
+    #[must_use]
+    pub fn new(max: usize) -> Self {
+        Self { max, semaphore: Arc::new(Semaphore::new(max)) }
+    }
+
+    /// Returns the maximum number of concurrent requests.
+    #[must_use]
+    pub fn max(&self) -> usize {
+        self.max
+    }
+}
+
+impl Debug for FetchLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("FetchLimit").field(&self.max).finish()
+    }
+}
+
+/// Controls the layout of the default cache key, see [`HttpCacheOptions::key_format`].
+///
+/// The default produces `{method}:{uri}`. With `explicit_authority` set, the scheme and
+/// authority are written as their own segments, producing
+/// `{method}{separator}{scheme}{separator}{authority}{separator}{path_and_query}`, which
+/// is unambiguous to parse and allows invalidating every key of a host by prefix.
+/// Segments missing from the request uri are left empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyFormat {
+    /// Separates the segments of the key. Defaults to a colon.
+    pub separator: String,
+    /// Writes the scheme and authority of the uri as separate segments.
+    pub explicit_authority: bool,
+}
+
+impl Default for KeyFormat {
+    fn default() -> Self {
+        Self { separator: ":".to_string(), explicit_authority: false }
+    }
+}
+
+impl KeyFormat {
+    fn format(&self, method: &str, uri: &http::Uri) -> String {
+        let sep = &self.separator;
+        if self.explicit_authority {
+            format!(
+                "{method}{sep}{}{sep}{}{sep}{}",
+                uri.scheme_str().unwrap_or_default(),
+                uri.authority().map(|a| a.as_str()).unwrap_or_default(),
+                uri.path_and_query().map(|p| p.as_str()).unwrap_or_default(),
+            )
+        } else {
+            format!("{method}{sep}{uri}")
+        }
+    }
+}
+
+/// What the cache does with a stored response whose cache policy can't be decoded,
+/// e.g. after the serialization format of the policy changed, see
+/// [`HttpCacheOptions::on_corrupt`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OnCorrupt {
+    /// The entry is treated as a miss, and replaced by the response fetched again.
+    #[default]
+    Discard,
+    /// The policy is rebuilt from the stored response headers and the current
+    /// request, so the entry can still be served or revalidated.
+    Rebuild,
+}
+
 /// Can be used to override the default [`CacheOptions`] and cache key.
 /// The cache key is a closure that takes [`http::request::Parts`] and returns a [`String`].
 #[derive(Clone)]
 pub struct HttpCacheOptions {
     /// Override the default cache options.
+    ///
+    /// When the cache is shared (the default), responses to requests carrying an
+    /// `Authorization` header are only stored if they are marked `public`,
+    /// `must-revalidate` or `s-maxage`, as required by
+    /// [RFC 7234](https://tools.ietf.org/html/rfc7234#section-3.2). A shared cache
+    /// also takes the freshness lifetime from `s-maxage` over `max-age`, while a
+    /// private one ignores `s-maxage`.
     pub cache_options: Option<CacheOptions>,
     /// Override the default cache key generator.
     pub cache_key: Option<CacheKey>,
     /// Override the default cache mode.
     pub cache_mode_fn: Option<CacheModeFn>,
+    /// Override the cache options per request, taking precedence over
+    /// [`HttpCacheOptions::cache_options`]. Allows a single cache to act as a shared
+    /// cache for some requests and a private one for others, e.g. to store `private`
+    /// responses only in per-user contexts.
+    pub cache_options_fn: Option<CacheOptionsFn>,
     /// Bust the caches of the returned keys.
     pub cache_bust: Option<CacheBust>,
     /// Determines if the cache status headers should be added to the response.
     pub cache_status_headers: bool,
+    /// Determines if a [`RequestSnapshot`] should be stored alongside each cached response.
+    /// Disabled by default to save space.
+    pub store_request: bool,
+    /// Override the cache policy derived from the response headers.
+    pub policy_override: Option<PolicyOverride>,
+    /// Restricts caching to the request urls for which the closure returns `true`.
+    /// Any other request bypasses the cache as if the mode were [`CacheMode::NoStore`],
+    /// regardless of the mode otherwise configured for it.
+    pub cacheable_url_matcher: Option<UrlMatcher>,
+    /// The HTTP version used for responses synthesized by the cache, such as the
+    /// `504 Gateway Timeout` returned for [`CacheMode::OnlyIfCached`] misses,
+    /// when the middleware can't report the negotiated version.
+    pub default_response_version: HttpVersion,
+    /// Spawns background tasks. Required for features that run work outside of a request,
+    /// such as [`HttpCache::spawn_pruner`].
+    pub spawn: Option<SpawnFn>,
+    /// Provides timers for features that need to wait, such as [`HttpCache::spawn_pruner`].
+    pub sleep: Option<SleepFn>,
+    /// Replaces the cached body with the body of a `304 Not Modified` response, for
+    /// misbehaving origins that send one. A 304 must not carry a body, so by default
+    /// it is ignored and the cached body is kept. Either way, such bodies are reported
+    /// to [`CacheObserver::on_not_modified_body`].
+    pub trust_304_body: bool,
+    /// Prepended to every cache key as `v{key_version}:` when non-zero, including the
+    /// keys returned by [`HttpCacheOptions::cache_bust`]. Incrementing it invalidates
+    /// all existing records at once, e.g. when the schema of the responses changes.
+    ///
+    /// Records stored under a previous version are orphaned rather than deleted, they
+    /// still take up space until the manager evicts them, see
+    /// [`CacheManager::evict_expired`] and [`HttpCache::spawn_pruner`].
+    pub key_version: u32,
+    /// Fails the request when deleting any of the keys returned by
+    /// [`HttpCacheOptions::cache_bust`] fails. By default such failures are ignored,
+    /// as busting is best-effort invalidation.
+    pub strict_cache_bust: bool,
+    /// The status of the response synthesized for [`CacheMode::OnlyIfCached`] and
+    /// [`CacheMode::OnlyIfCachedFresh`] misses.
+    /// Defaults to `504 Gateway Timeout`.
+    pub miss_status: u16,
+    /// Applied to every stale response served from cache, such as when revalidation
+    /// fails or a stale record is used by [`CacheMode::ForceCache`], allowing e.g. a
+    /// custom header to signal staleness downstream.
+    pub stale_response_transform: Option<ResponseTransform>,
+    /// Revalidates stale `GET` responses with a conditional `HEAD` request, saving
+    /// bandwidth for large resources that rarely change. A `304 Not Modified` refreshes
+    /// the stored response as usual, while a `200 OK` triggers a follow-up `GET` to
+    /// fetch the new body. Requires the middleware to implement [`Middleware::set_method`].
+    pub revalidate_with_head: bool,
+    /// Prevents responses containing a `Set-Cookie` header from being stored, as they
+    /// are usually specific to a user. Only applies to shared caches, which is the
+    /// default unless [`HttpCacheOptions::cache_options`] or
+    /// [`HttpCacheOptions::cache_options_fn`] set `shared` to `false`.
+    /// Enabled by default.
+    pub skip_cache_on_set_cookie: bool,
+    /// The method used in the key of the record invalidated when a request bypasses the
+    /// cache, such as an unsafe method or a [`HttpCache::run_no_cache`] call, in place of
+    /// the request method. Defaults to `GET`.
+    pub canonical_cache_method: http::Method,
+    /// The maximum number of variants stored for a single url, which only differ when
+    /// [`HttpCacheOptions::cache_key`] includes request headers in the key. When exceeded,
+    /// the least recently used variant is removed before a new one is stored.
+    /// Only enforced when the manager implements [`CacheManager::variants`] and
+    /// [`CacheManager::set_variants`], other managers store any number of variants.
+    pub max_variants_per_url: Option<usize>,
+    /// Gives responses for the request urls the closure matches, such as versioned assets
+    /// like `/static/app.abc123.js`, a freshness lifetime of one year when the response
+    /// doesn't state its own through `max-age`, `s-maxage` or `Expires`. Only the stored
+    /// policy is adjusted, the response headers are left untouched.
+    pub heuristic_immutable_matcher: Option<UrlMatcher>,
+    /// Receives notifications about the work done by the cache, see [`CacheObserver`].
+    pub observer: Option<Arc<dyn CacheObserver>>,
+    /// Compares the stored and requested values of each header named by the `Vary`
+    /// header of a stored response, allowing content negotiation aware matching, e.g.
+    /// for a request `Accept: text/html, */*` to match a response stored for
+    /// `Accept: */*`. Headers missing from a request are passed as an empty string.
+    /// Only consulted when the values don't match exactly, and requires
+    /// [`HttpCacheOptions::store_request`] since the stored request headers are compared.
+    pub vary_matcher: Option<VaryMatcher>,
+    /// Caps the number of requests made to the origin at once, protecting it from a
+    /// stampede while the cache is cold. Requests over the limit wait for a slot,
+    /// including revalidations. Clones of the options share the same limit.
+    pub max_concurrent_fetches: Option<FetchLimit>,
+    /// Controls the separator and layout of the default cache key, and the separator
+    /// following the [`HttpCacheOptions::key_version`] prefix. Ignored by a custom
+    /// [`HttpCacheOptions::cache_key`].
+    pub key_format: KeyFormat,
+    /// Overrides the current time used when synthesizing a missing `Date` header and
+    /// when checking the freshness of stored responses. Defaults to the system clock.
+    pub clock: Option<ClockFn>,
+    /// The pseudonym used as the warn-agent of `Warning` headers added by the cache for
+    /// urls without a host. Defaults to `-`.
+    pub warning_agent: Option<String>,
+    /// Request methods besides `GET` and `HEAD` whose responses may be cached, e.g.
+    /// `OPTIONS` to reuse CORS preflight responses or read-only WebDAV methods such as
+    /// `PROPFIND` and `REPORT`. Their responses are evaluated as if they answered a
+    /// `GET` request and are stored under a key for their own method, but only when
+    /// they carry explicit freshness information (`max-age`, `s-maxage` or `Expires`)
+    /// rather than a heuristic lifetime. The request body isn't part of the key, use
+    /// [`HttpCacheOptions::cache_key`] to add the headers telling requests to the same
+    /// url apart, such as `Depth`.
+    /// For `OPTIONS`, `204 No Content` responses may be stored as well and
+    /// `Access-Control-Max-Age` is treated as the freshness lifetime of responses
+    /// without explicit freshness information.
+    pub cacheable_methods: Vec<http::Method>,
+    /// Serves stale responses for up to this long past their expiry while revalidating
+    /// them in the background, like `stale-while-revalidate` for every response. Such
+    /// responses carry a `110 Response is stale` warning. Responses marked
+    /// `must-revalidate` and requests with their own `Cache-Control` are revalidated as
+    /// usual.
+    ///
+    /// Requires the [`HttpCacheOptions::spawn`] hook, and both the middleware and the
+    /// manager to support [`Middleware::detach`] and [`CacheManager::detach`], otherwise
+    /// stale responses are revalidated before being returned.
+    pub stale_grace: Option<Duration>,
+    /// Names this cache instance in the `x-cache` header, e.g. `HIT from node-3`, to
+    /// tell which node of a cluster handled a request. Defaults to the bare status.
+    pub instance_name: Option<String>,
+    /// Reports whether the network is known to be unreachable. While it returns `true`,
+    /// stale responses are served without attempting to revalidate them, with a
+    /// `112 Disconnected operation` warning. Responses marked `must-revalidate` are
+    /// still revalidated.
+    pub is_offline: Option<OfflineFn>,
+    /// Honors the `Expires` header of responses that also carry a `max-age` or
+    /// `s-maxage` directive, for legacy origins whose `Expires` is the accurate one.
+    /// Defaults to `false`, letting the directives take precedence as the spec requires.
+    pub prefer_expires: bool,
+    /// Attaches a [`CacheInfo`] describing how the cache handled the request to each
+    /// response, see [`HttpResponse::cache_info`]. Disabled by default.
+    pub cache_info: bool,
+    /// How far the `Date` of responses may be ahead of the local clock before it is
+    /// trusted. Freshness derived from `Expires` counts from the `Date`, so a server
+    /// clock running slightly ahead would make such responses expire early; when
+    /// judging the freshness of a stored response, dates up to this far ahead of the
+    /// time it was received are treated as that time instead. The stored response
+    /// keeps its `Date`. Defaults to zero, trusting the `Date` as-is.
+    pub clock_skew_tolerance: Duration,
+    /// The maximum total size of the header names and values of a stored response.
+    /// Responses exceeding it are returned without being stored, or have headers
+    /// dropped when [`HttpCacheOptions::drop_excess_headers`] is set. Unlimited by default.
+    pub max_stored_header_bytes: Option<usize>,
+    /// The maximum number of headers of a stored response, handled like
+    /// [`HttpCacheOptions::max_stored_header_bytes`]. Unlimited by default.
+    pub max_stored_header_count: Option<usize>,
+    /// Stores responses exceeding the header limits after dropping their largest
+    /// headers until they fit, rather than not storing them at all.
+    pub drop_excess_headers: bool,
+    /// Override [`HttpCacheOptions::cache_status_headers`] per request, e.g. for
+    /// endpoints whose consumers reject unknown headers.
+    pub cache_status_headers_fn: Option<CacheStatusHeadersFn>,
+    /// Partitions the cache by user. The identity returned for a request, such as a
+    /// user id extracted from its token, is appended to its cache key, so that users
+    /// never share entries. Requests without an identity use the plain key.
+    pub identity_fn: Option<IdentityFn>,
+    /// Also stores responses carrying a `Content-Location` header under the url it
+    /// names, when that url has the same origin as the request, so that requesting it
+    /// directly hits the cache.
+    /// (https://tools.ietf.org/html/rfc7231#section-3.1.4.2)
+    pub store_content_location: bool,
+    /// Response statuses that may be stored besides `200 OK`, redirects and `204 No
+    /// Content` responses to cacheable `OPTIONS` requests, e.g. `404` for negative
+    /// caching. Such responses skip the status check, but the cache policy must still
+    /// consider them storable, which it only does for statuses it understands or for
+    /// responses with explicit freshness information:
+    ///
+    /// | Status                 | Storable by the policy | Stored                  |
+    /// |------------------------|------------------------|-------------------------|
+    /// | `200` or configured    | yes                    | yes                     |
+    /// | `200` or configured    | no                     | only with `IgnoreRules` |
+    /// | redirect or preflight  | yes                    | yes                     |
+    /// | redirect or preflight  | no                     | no                      |
+    /// | any other              | yes or no              | no                      |
+    ///
+    /// In every case, nothing is stored in [`CacheMode::NoStore`].
+    pub cacheable_status_codes: Vec<u16>,
+    /// Canonicalizes the `Accept` request header before it is compared against the
+    /// one stored for a response that varies on it, e.g. by collapsing it to its
+    /// preferred media type, so that clients sending equivalent `Accept` headers share
+    /// a cache entry. Requests are still sent to the origin with their own header.
+    pub accept_normalizer: Option<AcceptNormalizer>,
+    /// Computes metadata annotations stored along with each entry, such as the
+    /// originating service or a trace id, for operational tooling to read back with
+    /// [`CacheManager::get_with_metadata`] without adding them to the response
+    /// headers. Requires a manager that stores metadata, such as `CACacheManager` or
+    /// `MokaManager`, others discard it.
+    pub metadata_fn: Option<MetadataFn>,
+    /// Produces a last-resort response, such as a static error page, for requests
+    /// without a usable cached response whose fetch from the origin failed. Returning
+    /// `None` surfaces the fetch error.
+    pub fallback_fn: Option<FallbackFn>,
+    /// Responses whose body is shorter than this many bytes aren't stored, e.g. when
+    /// they're cheaper to fetch again than to keep around.
+    pub min_cacheable_body_bytes: Option<usize>,
+    /// Responses whose body is longer than this many bytes aren't stored. Streaming
+    /// bodies are buffered until they exceed it, and then passed on unstored.
+    pub max_cacheable_body_bytes: Option<usize>,
+    /// How many times a failed revalidation is retried before the stale response is
+    /// served, or the error surfaced for `must-revalidate` responses. Connection errors,
+    /// server errors and `429 Too Many Requests` are retried, except for a
+    /// `503 Service Unavailable` carrying `Retry-After` while
+    /// [`HttpCacheOptions::honor_retry_after`] is set. Requires the
+    /// [`HttpCacheOptions::sleep`] hook to wait between attempts, without one
+    /// revalidation isn't retried.
+    pub revalidation_retries: u32,
+    /// How long to wait before the first retry of a failed revalidation, doubled after
+    /// each attempt. Defaults to 100 milliseconds.
+    pub revalidation_backoff: Duration,
+    /// Defers further revalidations of an entry until the `Retry-After` date of a
+    /// `503 Service Unavailable` response to its revalidation, serving the stale
+    /// response in the meantime, unless it has `must-revalidate`. When unset, such
+    /// responses are handled like any other server error. Defaults to `true`.
+    pub honor_retry_after: bool,
+    /// Handles stored responses whose cache policy can't be decoded, while the rest of
+    /// the entry can. Only managers storing the policy separately from the response
+    /// report such entries, such as `CACacheManager`.
+    pub on_corrupt: OnCorrupt,
+    /// Streams response bodies to the caller while they are written to the cache,
+    /// instead of writing them to the cache before returning them, which lowers the
+    /// latency of large responses. The write runs in a task started with the
+    /// [`HttpCacheOptions::spawn`] hook, and is abandoned if the caller drops the body
+    /// before reading it to the end, so partial bodies are never stored. Requires a
+    /// manager supporting [`CacheManager::detach`], otherwise bodies are written first.
+    pub stream_while_caching: bool,
+    /// Appends the `Content-Type` of requests using one of the
+    /// [`HttpCacheOptions::cacheable_methods`] to the default cache key, e.g. as
+    /// `content-type=application/json`, since the same body can mean different things
+    /// depending on its type. Requests without the header keep the plain key. Enabled
+    /// by default, and ignored by a custom [`HttpCacheOptions::cache_key`].
+    pub content_type_in_key: bool,
+    /// Drops the trailing slash of the path in the default cache key, so that
+    /// `/items/` and `/items` share an entry. The root path `/` is kept as is. This is
+    /// wrong for servers that treat the slash as significant, e.g. serving a
+    /// directory listing and a file under the same name. Ignored by a custom
+    /// [`HttpCacheOptions::cache_key`].
+    pub normalize_trailing_slash: bool,
+    /// Replaces the `Cache-Control` header of responses to cacheable requests before
+    /// the cache policy is built, e.g. `max-age=300` for an origin that sends none.
+    /// Unlike [`HttpCacheOptions::policy_override`], which only changes the policy,
+    /// the header is also changed on the stored and returned responses.
+    pub override_cache_control: Option<String>,
+    /// Appends a component derived from the request body to the cache key of requests
+    /// using one of the [`HttpCacheOptions::cacheable_methods`] other than `GET` and
+    /// `HEAD`, e.g. with a [`GraphQlKey`] when the `graphql` feature is enabled. The
+    /// body is read with [`Middleware::request_body`]. Requests for which the closure
+    /// returns `None`, or whose body isn't available, bypass the cache as if the mode
+    /// were [`CacheMode::NoStore`]. The component is also appended to keys built by a
+    /// custom [`HttpCacheOptions::cache_key`].
+    pub body_key: Option<BodyKey>,
 }
 
 impl Default for HttpCacheOptions {
@@ -506,8 +1935,59 @@ impl Default for HttpCacheOptions {
             cache_options: None,
             cache_key: None,
             cache_mode_fn: None,
+            cache_options_fn: None,
             cache_bust: None,
             cache_status_headers: true,
+            store_request: false,
+            policy_override: None,
+            cacheable_url_matcher: None,
+            default_response_version: HttpVersion::Http11,
+            spawn: None,
+            sleep: None,
+            trust_304_body: false,
+            key_version: 0,
+            strict_cache_bust: false,
+            miss_status: 504,
+            stale_response_transform: None,
+            revalidate_with_head: false,
+            skip_cache_on_set_cookie: true,
+            canonical_cache_method: http::Method::GET,
+            max_variants_per_url: None,
+            heuristic_immutable_matcher: None,
+            observer: None,
+            vary_matcher: None,
+            max_concurrent_fetches: None,
+            key_format: KeyFormat::default(),
+            clock: None,
+            warning_agent: None,
+            cacheable_methods: Vec::new(),
+            stale_grace: None,
+            instance_name: None,
+            is_offline: None,
+            prefer_expires: false,
+            cache_info: false,
+            clock_skew_tolerance: Duration::ZERO,
+            max_stored_header_bytes: None,
+            max_stored_header_count: None,
+            drop_excess_headers: false,
+            cache_status_headers_fn: None,
+            identity_fn: None,
+            store_content_location: false,
+            cacheable_status_codes: Vec::new(),
+            accept_normalizer: None,
+            metadata_fn: None,
+            fallback_fn: None,
+            min_cacheable_body_bytes: None,
+            max_cacheable_body_bytes: None,
+            revalidation_retries: 0,
+            revalidation_backoff: Duration::from_millis(100),
+            honor_retry_after: true,
+            on_corrupt: OnCorrupt::default(),
+            stream_while_caching: false,
+            content_type_in_key: true,
+            normalize_trailing_slash: false,
+            override_cache_control: None,
+            body_key: None,
         }
     }
 }
@@ -518,30 +1998,220 @@ impl Debug for HttpCacheOptions {
             .field("cache_options", &self.cache_options)
             .field("cache_key", &"Fn(&request::Parts) -> String")
             .field("cache_mode_fn", &"Fn(&request::Parts) -> CacheMode")
+            .field("cache_options_fn", &"Fn(&request::Parts) -> CacheOptions")
             .field("cache_bust", &"Fn(&request::Parts) -> Vec<String>")
             .field("cache_status_headers", &self.cache_status_headers)
+            .field("store_request", &self.store_request)
+            .field(
+                "policy_override",
+                &"Fn(&HttpResponse) -> Option<CachePolicy>",
+            )
+            .field("cacheable_url_matcher", &"Fn(&Url) -> bool")
+            .field("default_response_version", &self.default_response_version)
+            .field("spawn", &"Fn(BoxFuture<'static, ()>)")
+            .field("sleep", &"Fn(Duration) -> BoxFuture<'static, ()>")
+            .field("trust_304_body", &self.trust_304_body)
+            .field("key_version", &self.key_version)
+            .field("strict_cache_bust", &self.strict_cache_bust)
+            .field("miss_status", &self.miss_status)
+            .field("stale_response_transform", &"Fn(&mut HttpResponse)")
+            .field("revalidate_with_head", &self.revalidate_with_head)
+            .field("skip_cache_on_set_cookie", &self.skip_cache_on_set_cookie)
+            .field("canonical_cache_method", &self.canonical_cache_method)
+            .field("max_variants_per_url", &self.max_variants_per_url)
+            .field("heuristic_immutable_matcher", &"Fn(&Url) -> bool")
+            .field("observer", &"dyn CacheObserver")
+            .field("vary_matcher", &"Fn(&HeaderName, &str, &str) -> bool")
+            .field("max_concurrent_fetches", &self.max_concurrent_fetches)
+            .field("key_format", &self.key_format)
+            .field("clock", &"Fn() -> SystemTime")
+            .field("warning_agent", &self.warning_agent)
+            .field("cacheable_methods", &self.cacheable_methods)
+            .field("stale_grace", &self.stale_grace)
+            .field("instance_name", &self.instance_name)
+            .field("is_offline", &"Fn() -> bool")
+            .field("prefer_expires", &self.prefer_expires)
+            .field("cache_info", &self.cache_info)
+            .field("clock_skew_tolerance", &self.clock_skew_tolerance)
+            .field("max_stored_header_bytes", &self.max_stored_header_bytes)
+            .field("max_stored_header_count", &self.max_stored_header_count)
+            .field("drop_excess_headers", &self.drop_excess_headers)
+            .field(
+                "cache_status_headers_fn",
+                &self
+                    .cache_status_headers_fn
+                    .as_ref()
+                    .map(|_| "Fn(&request::Parts) -> bool"),
+            )
+            .field("identity_fn", &"Fn(&request::Parts) -> Option<String>")
+            .field("store_content_location", &self.store_content_location)
+            .field("cacheable_status_codes", &self.cacheable_status_codes)
+            .field("accept_normalizer", &"Fn(&str) -> String")
+            .field(
+                "metadata_fn",
+                &"Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>",
+            )
+            .field("fallback_fn", &"Fn(&request::Parts) -> Option<HttpResponse>")
+            .field("min_cacheable_body_bytes", &self.min_cacheable_body_bytes)
+            .field("max_cacheable_body_bytes", &self.max_cacheable_body_bytes)
+            .field("revalidation_retries", &self.revalidation_retries)
+            .field("revalidation_backoff", &self.revalidation_backoff)
+            .field("honor_retry_after", &self.honor_retry_after)
+            .field("on_corrupt", &self.on_corrupt)
+            .field("stream_while_caching", &self.stream_while_caching)
+            .field("content_type_in_key", &self.content_type_in_key)
+            .field("normalize_trailing_slash", &self.normalize_trailing_slash)
+            .field("override_cache_control", &self.override_cache_control)
+            .field("body_key", &"Fn(&request::Parts, &[u8]) -> Option<String>")
             .finish()
     }
 }
 
 impl HttpCacheOptions {
+    fn now(&self) -> SystemTime {
+        self.clock.as_ref().map_or_else(SystemTime::now, |clock| clock())
+    }
+
     fn create_cache_key(
         &self,
         parts: &request::Parts,
         override_method: Option<&str>,
     ) -> String {
-        if let Some(cache_key) = &self.cache_key {
+        self.versioned_key(self.base_cache_key(parts, override_method))
+    }
+
+    fn versioned_key(&self, key: String) -> String {
+        if self.key_version == 0 {
+            key
+        } else {
+            format!("v{}{}{}", self.key_version, self.key_format.separator, key)
+        }
+    }
+
+    // The body_key component of a request, or None if its body isn't part of its
+    // key. Some(None) means the request must bypass the cache.
+    fn body_key_component(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<Option<Option<String>>> {
+        let Some(body_key) = &self.body_key else {
+            return Ok(None);
+        };
+        let method = middleware.method()?;
+        if matches!(method.as_str(), "GET" | "HEAD")
+            || !self.cacheable_methods.iter().any(|m| m.as_str() == method)
+        {
+            return Ok(None);
+        }
+        Ok(Some(match middleware.request_body()? {
+            Some(body) => body_key(&middleware.parts()?, &body),
+            None => None,
+        }))
+    }
+
+    // The Content-Type of a request whose body may be cached along with its method,
+    // see content_type_in_key
+    fn key_content_type<'a>(
+        &self,
+        method: &str,
+        parts: &'a request::Parts,
+    ) -> Option<&'a str> {
+        if !self.content_type_in_key
+            || matches!(method, "GET" | "HEAD")
+            || !self.cacheable_methods.iter().any(|m| m.as_str() == method)
+        {
+            return None;
+        }
+        parts.headers.get(CONTENT_TYPE)?.to_str().ok()
+    }
+
+    // The uri as it appears in the default key, see normalize_trailing_slash
+    fn key_uri(&self, uri: &http::Uri) -> http::Uri {
+        let trimmed = uri.path().strip_suffix('/').filter(|p| !p.is_empty());
+        let (true, Some(path)) = (self.normalize_trailing_slash, trimmed)
+        else {
+            return uri.clone();
+        };
+        let mut parts = uri.clone().into_parts();
+        let path_and_query = match uri.query() {
+            Some(query) => format!("{path}?{query}"),
+            None => path.to_string(),
+        };
+        parts.path_and_query = path_and_query.parse().ok();
+        http::Uri::from_parts(parts).unwrap_or_else(|_| uri.clone())
+    }
+
+    fn base_cache_key(
+        &self,
+        parts: &request::Parts,
+        override_method: Option<&str>,
+    ) -> String {
+        let method = override_method.unwrap_or_else(|| parts.method.as_str());
+        let key = if let Some(cache_key) = &self.cache_key {
             cache_key(parts)
         } else {
-            format!(
-                "{}:{}",
-                override_method.unwrap_or_else(|| parts.method.as_str()),
-                parts.uri
-            )
+            let key = self.key_format.format(method, &self.key_uri(&parts.uri));
+            match self.key_content_type(method, parts) {
+                Some(content_type) => format!(
+                    "{key}{}content-type={content_type}",
+                    self.key_format.separator
+                ),
+                None => key,
+            }
+        };
+        let key = match parts.extensions.get::<BodyKeyComponent>() {
+            Some(BodyKeyComponent(component)) => {
+                format!("{key}{}{component}", self.key_format.separator)
+            }
+            None => key,
+        };
+        self.identified_key(key, parts)
+    }
+
+    // The key shared by all the variants of the url of a request, see
+    // max_variants_per_url. Unlike the keys of the variants themselves, it never
+    // goes through cache_key.
+    fn url_key(&self, parts: &request::Parts) -> String {
+        let key = self
+            .key_format
+            .format(parts.method.as_str(), &self.key_uri(&parts.uri));
+        self.versioned_key(self.identified_key(key, parts))
+    }
+
+    fn identified_key(&self, key: String, parts: &request::Parts) -> String {
+        match self.identity_fn.as_ref().and_then(|identity| identity(parts)) {
+            Some(identity) => {
+                format!("{key}{}{identity}", self.key_format.separator)
+            }
+            None => key,
         }
     }
 }
 
+/// Handle to a background task started by [`HttpCache::spawn_pruner`].
+/// The task is stopped when the handle is dropped.
+#[derive(Debug)]
+pub struct PrunerHandle {
+    abort: AbortHandle,
+}
+
+impl Drop for PrunerHandle {
+    fn drop(&mut self) {
+        self.abort.abort();
+    }
+}
+
+/// The freshness of a stored response, as returned by [`HttpCache::entry_freshness`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreshnessInfo {
+    /// How long the response remains fresh, zero once it is stale
+    pub time_to_live: Duration,
+    /// The current age of the response, including the time spent in upstream caches
+    pub age: Duration,
+    /// Whether the response must be revalidated before being served
+    pub is_stale: bool,
+}
+
 /// Caches requests according to http spec.
 #[derive(Debug, Clone)]
 pub struct HttpCache<T: CacheManager> {
@@ -557,15 +2227,188 @@ pub struct HttpCache<T: CacheManager> {
 
 #[allow(dead_code)]
 impl<T: CacheManager> HttpCache<T> {
+    /// Creates a cache over `manager` using [`CacheMode::Default`] and the default
+    /// [`HttpCacheOptions`]. The fields are public, so they can still be changed
+    /// afterwards or set directly for anything more involved.
+    ///
+    /// ```
+    /// # use http_cache::{CACacheManager, CacheMode, HttpCache};
+    /// let cache = HttpCache::new(CACacheManager::default());
+    /// assert_eq!(cache.mode, CacheMode::Default);
+    /// ```
+    pub fn new(manager: T) -> Self {
+        Self::with_mode(manager, CacheMode::Default)
+    }
+
+    /// Creates a cache over `manager` using `mode` and the default
+    /// [`HttpCacheOptions`].
+    ///
+    /// ```
+    /// # use http_cache::{CACacheManager, CacheMode, HttpCache};
+    /// let cache =
+    ///     HttpCache::with_mode(CACacheManager::default(), CacheMode::ForceCache);
+    /// ```
+    pub fn with_mode(manager: T, mode: CacheMode) -> Self {
+        Self { mode, manager, options: HttpCacheOptions::default() }
+    }
+
+    /// Computes the cache key of an arbitrary request, using the same logic as lookups,
+    /// including any custom [`HttpCacheOptions::cache_key`].
+    ///
+    /// This is the key before [`HttpCacheOptions::key_version`] is applied, which is the
+    /// form expected from [`HttpCacheOptions::cache_bust`] closures. A bust closure can
+    /// capture a copy of the cache to compute the keys of related resources:
+    ///
+    /// ```no_run
+    /// # use std::sync::Arc;
+    /// # use http_cache::{CACacheManager, CacheMode, HttpCache, HttpCacheOptions};
+    /// let keys = HttpCache {
+    ///     mode: CacheMode::Default,
+    ///     manager: CACacheManager::default(),
+    ///     options: HttpCacheOptions::default(),
+    /// };
+    /// let options = HttpCacheOptions {
+    ///     cache_bust: Some(Arc::new(move |parts: &http::request::Parts, _, _| {
+    ///         let (list, ()) = http::Request::get("https://example.com/items")
+    ///             .body(())
+    ///             .unwrap()
+    ///             .into_parts();
+    ///         vec![keys.key_for_parts(&list)]
+    ///     })),
+    ///     ..Default::default()
+    /// };
+    /// ```
+    pub fn key_for_parts(&self, parts: &request::Parts) -> String {
+        self.options.base_cache_key(parts, None)
+    }
+
+    /// Reports the freshness of the response stored under a cache key, as computed by
+    /// [`HttpCache::key_for_parts`], or `None` if nothing is stored.
+    ///
+    /// While the response is fresh, `age + time_to_live` is its total freshness
+    /// lifetime. The entry is read with [`CacheManager::peek`], so monitoring it doesn't
+    /// count as an access.
+    pub async fn entry_freshness(
+        &self,
+        key: &str,
+    ) -> Result<Option<FreshnessInfo>> {
+        let now = self.options.now();
+        Ok(self
+            .manager
+            .peek(&self.options.versioned_key(key.to_string()))
+            .await?
+            // Such entries are looked up as misses, see OnCorrupt
+            .filter(|(res, _)| {
+                !res.markers.rebuilt_policy
+                    || self.options.on_corrupt == OnCorrupt::Rebuild
+            })
+            .map(|(_, policy)| FreshnessInfo {
+                time_to_live: policy.time_to_live(now),
+                age: policy.age(now),
+                is_stale: policy.is_stale(now),
+            }))
+    }
+
     /// Determines if the request should be cached
+    ///
+    /// Only requests for `http` and `https` urls are cached.
     pub fn can_cache_request(
         &self,
         middleware: &impl Middleware,
     ) -> Result<bool> {
         let mode = self.cache_mode(middleware)?;
+        self.can_cache_request_in_mode(middleware, mode)
+    }
 
+    fn can_cache_request_in_mode(
+        &self,
+        middleware: &impl Middleware,
+        mode: CacheMode,
+    ) -> Result<bool> {
         Ok(mode == CacheMode::IgnoreRules
-            || middleware.is_method_get_head() && mode != CacheMode::NoStore)
+            || self.is_cacheable_method(middleware)?
+                && mode != CacheMode::NoStore)
+    }
+
+    fn is_cacheable_method(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<bool> {
+        Ok(middleware.is_method_get_head()
+            || self.extra_cacheable_method(middleware)?.is_some())
+    }
+
+    // The method of the request if it was made cacheable by the cacheable_methods option
+    fn extra_cacheable_method(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<Option<http::Method>> {
+        if self.options.cacheable_methods.is_empty()
+            || middleware.is_method_get_head()
+        {
+            return Ok(None);
+        }
+        let method = http::Method::from_str(&middleware.method()?)?;
+        Ok(self.options.cacheable_methods.contains(&method).then_some(method))
+    }
+
+    // The request parts the cache policy is evaluated against. Requests using one of
+    // the cacheable_methods are evaluated as GET requests, since the policy only
+    // considers responses to GET and HEAD requests storable. The Accept header is
+    // replaced by its canonical form when an accept_normalizer is set, and the uri
+    // by the one in the key, so that the policy matches requests sharing the entry.
+    fn policy_parts(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<request::Parts> {
+        let mut parts = middleware.parts()?;
+        if self.extra_cacheable_method(middleware)?.is_some() {
+            parts.method = http::Method::GET;
+        }
+        // Accept headers that aren't visible ASCII are left as they are
+        if let (Some(normalizer), Some(Ok(accept))) = (
+            &self.options.accept_normalizer,
+            parts.headers.get(ACCEPT).map(HeaderValue::to_str),
+        ) {
+            let accept = normalizer(accept);
+            parts.headers.insert(ACCEPT, HeaderValue::from_str(&accept)?);
+        }
+        parts.uri = self.options.key_uri(&parts.uri);
+        Ok(parts)
+    }
+
+    /// Spawns a background task that calls [`CacheManager::evict_expired`] every `interval`,
+    /// with the time given by [`HttpCacheOptions::clock`].
+    ///
+    /// Requires both the [`HttpCacheOptions::spawn`] and [`HttpCacheOptions::sleep`] hooks
+    /// to be configured for the async runtime in use, otherwise a [`MissingRuntimeHook`]
+    /// error is returned. Errors while evicting are ignored and retried on the next tick.
+    /// This is a no-op for managers that expire records on their own, such as moka.
+    pub fn spawn_pruner(&self, interval: Duration) -> Result<PrunerHandle>
+    where
+        T: Clone,
+    {
+        let (Some(spawn), Some(sleep)) =
+            (&self.options.spawn, &self.options.sleep)
+        else {
+            return Err(Box::new(MissingRuntimeHook));
+        };
+        let manager = self.manager.clone();
+        let sleep = sleep.clone();
+        let clock = self.options.clock.clone();
+        let (task, abort) = futures::future::abortable(async move {
+            loop {
+                sleep(interval).await;
+                let now = clock
+                    .as_ref()
+                    .map_or_else(SystemTime::now, |clock| clock());
+                manager.evict_expired(now).await.ok();
+            }
+        });
+        spawn(Box::pin(async move {
+            task.await.ok();
+        }));
+        Ok(PrunerHandle { abort })
     }
 
     /// Runs the actions to preform when the client middleware is running without the cache
@@ -574,232 +2417,1174 @@ impl<T: CacheManager> HttpCache<T> {
         middleware: &mut impl Middleware,
     ) -> Result<()> {
         self.manager
-            .delete(
-                &self
-                    .options
-                    .create_cache_key(&middleware.parts()?, Some("GET")),
-            )
+            .delete(&self.options.create_cache_key(
+                &middleware.parts()?,
+                Some(self.options.canonical_cache_method.as_str()),
+            ))
             .await
             .ok();
 
-        let cache_key =
-            self.options.create_cache_key(&middleware.parts()?, None);
+        let base_key = self.options.base_cache_key(&middleware.parts()?, None);
 
-        if let Some(cache_bust) = &self.options.cache_bust {
-            for key_to_cache_bust in cache_bust(
-                &middleware.parts()?,
-                &self.options.cache_key,
-                &cache_key,
-            ) {
-                self.manager.delete(&key_to_cache_bust).await?;
-            }
-        }
+        self.bust(middleware, &base_key).await?;
 
         Ok(())
     }
 
     /// Attempts to run the passed middleware along with the cache
     pub async fn run(
+        &self,
+        middleware: impl Middleware,
+    ) -> Result<HttpResponse> {
+        let component = self.options.body_key_component(&middleware)?;
+        let middleware = BodyKeyed::new(middleware, component);
+        // The mode is resolved once, since revalidation may change the headers it
+        // depends on
+        let mode = self.cache_mode(&middleware)?;
+        let status_headers = match &self.options.cache_status_headers_fn {
+            Some(status_headers_fn) => status_headers_fn(&middleware.parts()?),
+            None => self.options.cache_status_headers,
+        };
+        // Boxed, as the future is too large to be kept on the stack of the caller
+        let mut res = Box::pin(self.run_in_mode(middleware, mode)).await?;
+        if !status_headers {
+            res.parts.headers.remove(XCACHE);
+            res.parts.headers.remove(XCACHELOOKUP);
+        }
+        Ok(res)
+    }
+
+    async fn run_in_mode(
         &self,
         mut middleware: impl Middleware,
+        mode: CacheMode,
     ) -> Result<HttpResponse> {
-        let is_cacheable = self.can_cache_request(&middleware)?;
+        let is_cacheable = self.can_cache_request_in_mode(&middleware, mode)?;
         if !is_cacheable {
-            return self.remote_fetch(&mut middleware).await;
+            return self.remote_fetch(&mut middleware, mode).await;
+        }
+        // Preconditions on the current state of the resource can only be evaluated
+        // by the origin, so a stored response must not stand in for it. The
+        // response depends on the preconditions, so it isn't stored either
+        // (https://tools.ietf.org/html/rfc7232#section-5)
+        let headers = middleware.parts()?.headers;
+        if (headers.contains_key(IF_MATCH)
+            || headers.contains_key(IF_UNMODIFIED_SINCE))
+            && !matches!(
+                mode,
+                CacheMode::OnlyIfCached | CacheMode::OnlyIfCachedFresh
+            )
+        {
+            let mut res = self.fetch(&mut middleware).await?;
+            if self.invalidates(&middleware)? {
+                self.invalidate(&middleware, &res).await?;
+            }
+            if self.status_headers() {
+                res.cache_lookup_status(HitOrMiss::MISS);
+            }
+            self.cache_status(&mut res, HitOrMiss::MISS, None);
+            return Ok(res);
         }
 
-        let cache_key =
-            self.options.create_cache_key(&middleware.parts()?, None);
+        let base_key = self.options.base_cache_key(&middleware.parts()?, None);
 
-        if let Some(cache_bust) = &self.options.cache_bust {
-            for key_to_cache_bust in cache_bust(
-                &middleware.parts()?,
-                &self.options.cache_key,
-                &cache_key,
-            ) {
-                self.manager.delete(&key_to_cache_bust).await?;
-            }
+        self.bust(&middleware, &base_key).await?;
+
+        let cache_key = self.options.versioned_key(base_key);
+        let mut res = self.lookup(middleware, mode, &cache_key).await?;
+        if let Some(info) = &mut res.cache_info {
+            info.key = Some(cache_key);
         }
+        Ok(res)
+    }
 
-        if let Some(store) = self.manager.get(&cache_key).await? {
-            let (mut res, policy) = store;
-            if self.options.cache_status_headers {
-                res.cache_lookup_status(HitOrMiss::HIT);
-            }
-            if let Some(warning_code) = res.warning_code() {
-                // https://tools.ietf.org/html/rfc7234#section-4.3.4
-                //
-                // If a stored response is selected for update, the cache MUST:
-                //
-                // * delete any warning header fields in the stored response with
-                //   warn-code 1xx (see Section 5.5);
-                //
-                // * retain any warning header fields in the stored response with
-                //   warn-code 2xx;
-                //
-                if (100..200).contains(&warning_code) {
-                    res.remove_warning();
+    async fn lookup(
+        &self,
+        mut middleware: impl Middleware,
+        mode: CacheMode,
+        cache_key: &str,
+    ) -> Result<HttpResponse> {
+        let store = if self.options.vary_matcher.is_some() {
+            self.manager.get_with_request(cache_key).await?
+        } else {
+            self.manager
+                .get(cache_key)
+                .await?
+                .map(|(res, policy)| (res, policy, None))
+        };
+        // Entries whose policy couldn't be decoded come with a stand-in policy
+        let store = match store {
+            Some((mut res, policy, stored_request)) => {
+                if !res.take_rebuilt_policy() {
+                    Some((res, policy, stored_request))
+                } else if self.options.on_corrupt == OnCorrupt::Rebuild {
+                    let policy = self.policy(&middleware, &res)?;
+                    Some((res, policy, stored_request))
+                } else {
+                    None
                 }
             }
-
-            match self.cache_mode(&middleware)? {
+            None => None,
+        };
+        if let Some(store) = store {
+            let (mut res, policy, stored_request) = store;
+            let deferred_until = res.take_deferred_until();
+            if self.status_headers() {
+                res.cache_lookup_status(HitOrMiss::HIT);
+            }
+            // https://tools.ietf.org/html/rfc7234#section-4.3.4
+            //
+            // If a stored response is selected for update, the cache MUST:
+            //
+            // * delete any warning header fields in the stored response with
+            //   warn-code 1xx (see Section 5.5);
+            //
+            // * retain any warning header fields in the stored response with
+            //   warn-code 2xx;
+            //
+            res.retain_warnings(|warn_code| !(100..200).contains(&warn_code));
+
+            match mode {
                 CacheMode::Default => {
-                    self.conditional_fetch(middleware, res, policy).await
+                    self.conditional_fetch(
+                        middleware,
+                        res,
+                        policy,
+                        stored_request,
+                        deferred_until,
+                    )
+                    .await
                 }
                 CacheMode::NoCache => {
                     middleware.force_no_cache()?;
-                    let mut res = self.remote_fetch(&mut middleware).await?;
-                    if self.options.cache_status_headers {
+                    match self.remote_fetch(&mut middleware, mode).await {
+                        Ok(mut fresh_res) => {
+                            if self.status_headers() {
+                                fresh_res.cache_lookup_status(HitOrMiss::HIT);
+                            }
+                            Ok(fresh_res)
+                        }
+                        // Same handling as a failed revalidation in Default
+                        Err(e) if res.must_revalidate() => {
+                            self.fallback(&middleware, e)
+                        }
+                        Err(_) => Ok(self.revalidation_failed(res, &policy)),
+                    }
+                }
+                CacheMode::OnlyIfCachedFresh
+                    if !is_fresh(&policy, self.options.now()) =>
+                {
+                    let mut res = self.not_cached(&middleware)?;
+                    if self.status_headers() {
                         res.cache_lookup_status(HitOrMiss::HIT);
                     }
                     Ok(res)
                 }
                 CacheMode::ForceCache
                 | CacheMode::OnlyIfCached
+                | CacheMode::OnlyIfCachedFresh
                 | CacheMode::IgnoreRules => {
                     //   112 Disconnected operation
                     // SHOULD be included if the cache is intentionally disconnected from
                     // the rest of the network for a period of time.
                     // (https://tools.ietf.org/html/rfc2616#section-14.46)
-                    res.add_warning(
-                        &res.parts.url.clone(),
-                        112,
-                        "Disconnected operation",
-                    );
-                    if self.options.cache_status_headers {
-                        res.cache_status(HitOrMiss::HIT);
+                    self.add_warning(&mut res, 112, "Disconnected operation");
+                    self.cache_status(&mut res, HitOrMiss::HIT, Some(&policy));
+                    if !is_fresh(&policy, self.options.now()) {
+                        self.transform_stale(&mut res);
                     }
                     Ok(res)
                 }
-                _ => self.remote_fetch(&mut middleware).await,
+                _ => self.remote_fetch(&mut middleware, mode).await,
             }
         } else {
-            match self.cache_mode(&middleware)? {
-                CacheMode::OnlyIfCached => {
-                    // ENOTCACHED
-                    let mut res = HttpResponse {
-                        body: b"GatewayTimeout".to_vec().into(),
-                        parts: Parts {
-                            headers: HashMap::default(),
-                            status: 504,
-                            url: middleware.url()?,
-                            version: HttpVersion::Http11,
-                        },
-                    };
-                    if self.options.cache_status_headers {
-                        res.cache_status(HitOrMiss::MISS);
+            match mode {
+                CacheMode::OnlyIfCached | CacheMode::OnlyIfCachedFresh => {
+                    let mut res = self.not_cached(&middleware)?;
+                    if self.status_headers() {
                         res.cache_lookup_status(HitOrMiss::MISS);
                     }
                     Ok(res)
                 }
-                _ => self.remote_fetch(&mut middleware).await,
+                _ => match self.remote_fetch(&mut middleware, mode).await {
+                    Err(e) => self.fallback(&middleware, e),
+                    res => res,
+                },
             }
         }
     }
 
-    fn cache_mode(&self, middleware: &impl Middleware) -> Result<CacheMode> {
-        Ok(if let Some(mode) = middleware.overridden_cache_mode() {
-            mode
-        } else if let Some(cache_mode_fn) = &self.options.cache_mode_fn {
-            cache_mode_fn(&middleware.parts()?)
-        } else {
-            self.mode
-        })
-    }
+    // Answers a request without a usable cached response whose fetch failed with
+    // the response of the fallback_fn, if any
+    fn fallback(
+        &self,
+        middleware: &impl Middleware,
+        error: BoxError,
+    ) -> Result<HttpResponse> {
+        let Some(fallback_fn) = &self.options.fallback_fn else {
+            return Err(error);
+        };
+        let Some(mut res) = fallback_fn(&middleware.parts()?) else {
+            return Err(error);
+        };
+        self.cache_status(&mut res, HitOrMiss::MISS, None);
+        Ok(res)
+    }
+
+    // ENOTCACHED
+    fn not_cached(&self, middleware: &impl Middleware) -> Result<HttpResponse> {
+        let mut res = HttpResponse {
+            body: b"GatewayTimeout".to_vec().into(),
+            parts: Parts {
+                headers: HashMap::default(),
+                status: self.options.miss_status,
+                url: middleware.url()?,
+                version: middleware
+                    .version()
+                    .unwrap_or(self.options.default_response_version),
+            },
+            cache_info: None,
+            markers: Markers::default(),
+            decode_limit: None,
+        };
+        self.cache_status(&mut res, HitOrMiss::MISS, None);
+        Ok(res)
+    }
+
+    fn cache_mode(&self, middleware: &impl Middleware) -> Result<CacheMode> {
+        let mode = self.resolve_cache_mode(middleware)?;
+        if let Some(observer) = &self.options.observer {
+            observer.on_mode_resolved(&middleware.url()?, mode);
+        }
+        Ok(mode)
+    }
+
+    fn resolve_cache_mode(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<CacheMode> {
+        let url = middleware.url()?;
+        // Only network resources are worth caching, e.g. not `data:` or `file:` urls
+        if !matches!(url.scheme(), "http" | "https") {
+            return Ok(CacheMode::NoStore);
+        }
+        if let Some(matcher) = &self.options.cacheable_url_matcher {
+            if !matcher(&url) {
+                return Ok(CacheMode::NoStore);
+            }
+        }
+        let mode = if let Some(mode) = middleware.overridden_cache_mode() {
+            mode
+        } else if let Some(cache_mode_fn) = &self.options.cache_mode_fn {
+            cache_mode_fn(&middleware.parts()?)
+        } else {
+            self.mode
+        };
+        // HTTP/1.0 clients send the no-cache pragma, which must be treated like
+        // "Cache-Control: no-cache" when that header is absent.
+        // (https://tools.ietf.org/html/rfc7234#section-5.4)
+        if mode == CacheMode::Default {
+            let headers = middleware.parts()?.headers;
+            if !headers.contains_key(CACHE_CONTROL)
+                && headers.get(PRAGMA).is_some_and(|pragma| {
+                    pragma.to_str().is_ok_and(|p| p.contains("no-cache"))
+                })
+            {
+                return Ok(CacheMode::NoCache);
+            }
+        }
+        Ok(mode)
+    }
 
     async fn remote_fetch(
         &self,
         middleware: &mut impl Middleware,
+        mode: CacheMode,
     ) -> Result<HttpResponse> {
-        let mut res = middleware.remote_fetch().await?;
-        if self.options.cache_status_headers {
-            res.cache_status(HitOrMiss::MISS);
+        let mut res = self.fetch(middleware).await?;
+        if self.status_headers() {
             res.cache_lookup_status(HitOrMiss::MISS);
         }
-        let policy = match self.options.cache_options {
-            Some(options) => middleware.policy_with_options(&res, options)?,
-            None => middleware.policy(&res)?,
-        };
-        let is_get_head = middleware.is_method_get_head();
-        let mode = self.cache_mode(middleware)?;
+        res.vary_on_content_encoding();
+        let is_get_head = self.is_cacheable_method(middleware)?;
+        if is_get_head {
+            self.override_cache_control(&mut res);
+        }
+        let policy = self.policy(middleware, &res)?;
+        self.cache_status(&mut res, HitOrMiss::MISS, Some(&policy));
+        let extra_method = self.extra_cacheable_method(middleware)?;
+        let is_options = extra_method == Some(http::Method::OPTIONS);
+        // Preflight responses usually come as 204 No Content
+        let is_preflight = res.parts.status == 204 && is_options;
+        // Responses to the cacheable_methods are only stored when they state their
+        // own freshness lifetime, never based on heuristics
+        let is_explicit = extra_method.is_none()
+            || res.has_explicit_freshness()
+            || is_options
+                && res
+                    .parts
+                    .headers
+                    .contains_key(ACCESS_CONTROL_MAX_AGE.as_str());
+        // Configured statuses bypass the status check but not the policy, which
+        // only IgnoreRules overrides
+        let is_configured_status =
+            self.options.cacheable_status_codes.contains(&res.parts.status);
         let mut is_cacheable = is_get_head
             && mode != CacheMode::NoStore
-            && res.parts.status == 200
+            && (res.parts.status == 200
+                || res.is_redirect()
+                || is_preflight
+                || is_configured_status)
+            && is_explicit
             && policy.is_storable();
-        if mode == CacheMode::IgnoreRules && res.parts.status == 200 {
+        if mode == CacheMode::IgnoreRules
+            && (res.parts.status == 200 || is_configured_status)
+        {
             is_cacheable = true;
         }
+        if self.invalidates(middleware)? {
+            self.invalidate(middleware, &res).await?;
+        }
         if is_cacheable {
-            Ok(self
-                .manager
-                .put(
-                    self.options.create_cache_key(&middleware.parts()?, None),
-                    res,
-                    policy,
-                )
-                .await?)
-        } else if !is_get_head {
-            self.manager
-                .delete(
-                    &self
-                        .options
-                        .create_cache_key(&middleware.parts()?, Some("GET")),
-                )
-                .await
-                .ok();
-            Ok(res)
+            self.put(
+                middleware,
+                self.options.create_cache_key(&middleware.parts()?, None),
+                res,
+                policy,
+            )
+            .await
         } else {
             Ok(res)
         }
     }
 
+    // Responses to unsafe methods invalidate the stored responses for their url,
+    // including those of the cacheable_methods such as POST
+    fn invalidates(&self, middleware: &impl Middleware) -> Result<bool> {
+        Ok(!self.is_cacheable_method(middleware)?
+            || self
+                .extra_cacheable_method(middleware)?
+                .is_some_and(|method| !is_safe(&method)))
+    }
+
+    async fn invalidate(
+        &self,
+        middleware: &impl Middleware,
+        res: &HttpResponse,
+    ) -> Result<()> {
+        let mut parts = middleware.parts()?;
+        // The stored responses for the url are keyed without a request body
+        parts.extensions.remove::<BodyKeyComponent>();
+        let method = Some(self.options.canonical_cache_method.as_str());
+        self.manager
+            .delete(&self.options.create_cache_key(&parts, method))
+            .await
+            .ok();
+        // A successful unsafe request also invalidates the resources named by
+        // the Location and Content-Location headers of its response
+        // (https://tools.ietf.org/html/rfc7234#section-4.4)
+        if (200..400).contains(&res.parts.status) {
+            let url = middleware.url()?;
+            for name in [LOCATION, CONTENT_LOCATION] {
+                let Some(target) = res
+                    .parts
+                    .headers
+                    .get(name.as_str())
+                    .and_then(|target| same_origin_url(&url, target))
+                else {
+                    continue;
+                };
+                parts.uri = target.as_str().parse()?;
+                self.manager
+                    .delete(&self.options.create_cache_key(&parts, method))
+                    .await
+                    .ok();
+            }
+        }
+        Ok(())
+    }
+
+    // Busting is best-effort invalidation, so failed deletes are ignored unless
+    // strict_cache_bust is set
+    async fn bust(
+        &self,
+        middleware: &impl Middleware,
+        base_key: &str,
+    ) -> Result<()> {
+        if let Some(cache_bust) = &self.options.cache_bust {
+            for key_to_cache_bust in cache_bust(
+                &middleware.parts()?,
+                &self.options.cache_key,
+                base_key,
+            ) {
+                let deleted = self
+                    .manager
+                    .delete(&self.options.versioned_key(key_to_cache_bust))
+                    .await;
+                if self.options.strict_cache_bust {
+                    deleted?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn fetch(
+        &self,
+        middleware: &mut impl Middleware,
+    ) -> Result<HttpResponse> {
+        let _permit = match &self.options.max_concurrent_fetches {
+            Some(limit) => Some(limit.semaphore.acquire().await),
+            None => None,
+        };
+        let mut res = middleware.remote_fetch().await?;
+        // Age is computed from the Date header, so one is synthesized at receipt
+        // time when the origin omits it
+        // (https://tools.ietf.org/html/rfc7231#section-7.1.1.2)
+        res.parts
+            .headers
+            .entry(DATE.as_str().to_string())
+            .or_insert_with(|| httpdate::fmt_http_date(self.options.now()));
+        Ok(res)
+    }
+
+    async fn revalidate(
+        &self,
+        middleware: &mut impl Middleware,
+    ) -> Result<HttpResponse> {
+        if !self.options.revalidate_with_head
+            || middleware.method()? != http::Method::GET.as_str()
+            || middleware.set_method(http::Method::HEAD).is_err()
+        {
+            return self.fetch(middleware).await;
+        }
+        let res = self.fetch(middleware).await;
+        middleware.set_method(http::Method::GET)?;
+        match res {
+            // The resource changed, so its new body has to be fetched
+            Ok(res) if res.parts.status == 200 => self.fetch(middleware).await,
+            res => res,
+        }
+    }
+
+    // Revalidates, retrying failed attempts as configured by revalidation_retries
+    async fn revalidate_with_retries(
+        &self,
+        middleware: &mut impl Middleware,
+        now: SystemTime,
+    ) -> Result<HttpResponse> {
+        let mut backoff = self.options.revalidation_backoff;
+        for _ in 0..self.options.revalidation_retries {
+            let Some(sleep) = &self.options.sleep else {
+                break;
+            };
+            let res = self.revalidate(middleware).await;
+            let failed = match &res {
+                Ok(res) => {
+                    let status = StatusCode::from_u16(res.parts.status)?;
+                    (status.is_server_error()
+                        || status == StatusCode::TOO_MANY_REQUESTS)
+                        && (status != StatusCode::SERVICE_UNAVAILABLE
+                            || !self.options.honor_retry_after
+                            || res.retry_after(now).is_none())
+                }
+                Err(_) => true,
+            };
+            if !failed {
+                return res;
+            }
+            sleep(backoff).await;
+            backoff = backoff.saturating_mul(2);
+        }
+        self.revalidate(middleware).await
+    }
+
+    fn cache_options(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<Option<CacheOptions>> {
+        Ok(match &self.options.cache_options_fn {
+            Some(cache_options_fn) => {
+                Some(cache_options_fn(&middleware.parts()?))
+            }
+            None => self.options.cache_options,
+        })
+    }
+
+    // Makes room for `cache_key` among the variants of its url, evicting the least
+    // recently used ones
+    async fn limit_variants(
+        &self,
+        middleware: &impl Middleware,
+        cache_key: &str,
+        max_variants: usize,
+    ) -> Result<()> {
+        let base_key = self.options.url_key(&middleware.parts()?);
+        // Managers that can't track variants leave the number of variants unlimited
+        let mut variants = match self.manager.variants(&base_key).await {
+            Ok(variants) => variants,
+            Err(err) if err.is::<Unsupported>() => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        variants.retain(|key| key != cache_key);
+        while !variants.is_empty() && variants.len() >= max_variants {
+            let mut lru = 0;
+            let mut lru_accessed = None;
+            for (i, key) in variants.iter().enumerate() {
+                let accessed = self.manager.last_accessed(key).await?;
+                if i == 0 || accessed < lru_accessed {
+                    lru = i;
+                    lru_accessed = accessed;
+                }
+            }
+            self.manager.delete(&variants.remove(lru)).await?;
+        }
+        variants.push(cache_key.to_string());
+        self.manager.set_variants(&base_key, variants).await
+    }
+
+    // When every header named by Vary is accepted by the vary_matcher, returns the
+    // request parts with those headers set to their stored values, so that the
+    // policy considers the stored response a match
+    fn vary_matched_parts(
+        &self,
+        middleware: &impl Middleware,
+        cached_res: &HttpResponse,
+        stored_request: Option<&RequestSnapshot>,
+    ) -> Result<Option<request::Parts>> {
+        let (Some(matcher), Some(stored_request), Some(vary)) = (
+            &self.options.vary_matcher,
+            stored_request,
+            cached_res.parts.headers.get(VARY.as_str()),
+        ) else {
+            return Ok(None);
+        };
+        let mut parts = middleware.parts()?;
+        for name in vary.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let name = HeaderName::from_str(&name.to_lowercase())?;
+            let stored = stored_request
+                .headers
+                .get(name.as_str())
+                .map_or("", String::as_str);
+            let requested = match parts.headers.get(&name) {
+                Some(value) => value.to_str()?,
+                None => "",
+            };
+            if !matcher(&name, stored, requested) {
+                return Ok(None);
+            }
+            if stored.is_empty() {
+                parts.headers.remove(&name);
+            } else {
+                parts.headers.insert(name, HeaderValue::from_str(stored)?);
+            }
+        }
+        Ok(Some(parts))
+    }
+
+    fn within_stale_grace(
+        &self,
+        middleware: &impl Middleware,
+        cached_res: &HttpResponse,
+        policy: &CachePolicy,
+        now: SystemTime,
+    ) -> Result<bool> {
+        let Some(grace) = self.options.stale_grace else {
+            return Ok(false);
+        };
+        if cached_res.must_revalidate()
+            || middleware.parts()?.headers.contains_key(CACHE_CONTROL)
+        {
+            return Ok(false);
+        }
+        // The response expired less than `grace` ago if it was still fresh back then
+        Ok(policy.is_stale(now)
+            && now
+                .checked_sub(grace)
+                .is_some_and(|then| !policy.time_to_live(then).is_zero()))
+    }
+
+    // Spawns a revalidation of the stored response, returning whether that was possible
+    fn revalidate_in_background(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<bool> {
+        let (Some(spawn), Some(detached), Some(manager)) =
+            (&self.options.spawn, middleware.detach(), self.manager.detach())
+        else {
+            return Ok(false);
+        };
+        let cache = HttpCache {
+            mode: self.mode,
+            manager,
+            options: HttpCacheOptions {
+                stale_grace: None,
+                ..self.options.clone()
+            },
+        };
+        let cache_key =
+            self.options.create_cache_key(&middleware.parts()?, None);
+        spawn(background_revalidation(cache, cache_key, detached));
+        Ok(true)
+    }
+
+    // The headers are added whenever some request may want them, and removed from
+    // the responses to the others at the end of `run`
+    fn status_headers(&self) -> bool {
+        self.options.cache_status_headers
+            || self.options.cache_status_headers_fn.is_some()
+    }
+
+    fn cache_status(
+        &self,
+        res: &mut HttpResponse,
+        hit_or_miss: HitOrMiss,
+        policy: Option<&CachePolicy>,
+    ) {
+        if self.status_headers() {
+            res.cache_status_from(
+                hit_or_miss,
+                self.options.instance_name.as_deref(),
+            );
+        }
+        if self.options.cache_info {
+            let now = self.options.now();
+            res.cache_info = Some(CacheInfo {
+                hit_or_miss,
+                key: None,
+                age: policy.map(|policy| policy.age(now)),
+                ttl: policy.map(|policy| policy.time_to_live(now)),
+            });
+        }
+    }
+
+    // Warns about a stored response, naming the host it was received from, which
+    // is the final host when the client followed redirects
+    fn add_warning(&self, res: &mut HttpResponse, code: usize, message: &str) {
+        let pseudonym = self.options.warning_agent.as_deref().unwrap_or("-");
+        let url = res.parts.url.clone();
+        res.add_warning_with_agent(&url, pseudonym, code, message);
+    }
+
+    fn override_cache_control(&self, res: &mut HttpResponse) {
+        if let Some(cache_control) = &self.options.override_cache_control {
+            res.parts
+                .headers
+                .insert(CACHE_CONTROL.to_string(), cache_control.clone());
+        }
+    }
+
+    fn transform_stale(&self, res: &mut HttpResponse) {
+        if let Some(transform) = &self.options.stale_response_transform {
+            transform(res);
+        }
+    }
+
+    fn policy(
+        &self,
+        middleware: &impl Middleware,
+        res: &HttpResponse,
+    ) -> Result<CachePolicy> {
+        self.policy_for_uri(middleware, res, None)
+    }
+
+    // Like policy, for a copy of `res` stored under `uri` instead of the request uri
+    fn policy_for_uri(
+        &self,
+        middleware: &impl Middleware,
+        res: &HttpResponse,
+        uri: Option<&http::Uri>,
+    ) -> Result<CachePolicy> {
+        if let Some(policy) =
+            self.options.policy_override.as_ref().and_then(|f| f(res))
+        {
+            return Ok(policy);
+        }
+        let extra_method = self.extra_cacheable_method(middleware)?;
+        let implied_max_age = if res.has_explicit_freshness() {
+            None
+        } else if extra_method == Some(http::Method::OPTIONS) {
+            res.parts
+                .headers
+                .get(ACCESS_CONTROL_MAX_AGE.as_str())
+                .and_then(|max_age| max_age.trim().parse::<u64>().ok())
+        } else {
+            match &self.options.heuristic_immutable_matcher {
+                Some(matcher) if matcher(&middleware.url()?) => {
+                    Some(IMMUTABLE_MAX_AGE)
+                }
+                _ => None,
+            }
+        };
+        let cache_control = res.parts.headers.get(CACHE_CONTROL.as_str());
+        let adjusted_cache_control = match (implied_max_age, cache_control) {
+            (Some(max_age), Some(cc)) => {
+                Some(format!("{cc}, max-age={max_age}"))
+            }
+            (Some(max_age), None) => Some(format!("max-age={max_age}")),
+            (None, Some(cc))
+                if self.options.prefer_expires
+                    && res.parts.headers.contains_key(EXPIRES.as_str()) =>
+            {
+                Some(without_max_age(cc)).filter(|adjusted| adjusted != cc)
+            }
+            (None, _) => None,
+        };
+        let adjusted;
+        let res = if let Some(cache_control) = adjusted_cache_control {
+            let mut parts = res.parts.clone();
+            parts.headers.insert(CACHE_CONTROL.to_string(), cache_control);
+            // the body doesn't take part in the policy
+            adjusted = HttpResponse::from_parts(parts, Bytes::new().into());
+            &adjusted
+        } else {
+            res
+        };
+        let received_at = middleware.received_at();
+        if uri.is_some()
+            || extra_method.is_some()
+            || received_at.is_some()
+            || self.options.clock.is_some()
+            || self.options.accept_normalizer.is_some()
+            || self.options.normalize_trailing_slash
+        {
+            let mut parts = self.policy_parts(middleware)?;
+            if let Some(uri) = uri {
+                parts.uri = self.options.key_uri(uri);
+            }
+            return Ok(CachePolicy::new_options(
+                &parts,
+                &res.parts()?,
+                received_at.unwrap_or_else(|| self.options.now()),
+                self.cache_options(middleware)?.unwrap_or_default(),
+            ));
+        }
+        match self.cache_options(middleware)? {
+            Some(options) => middleware.policy_with_options(res, options),
+            None => middleware.policy(res),
+        }
+    }
+
+    // Stores a copy of `res` under the same-origin url named by its Content-Location,
+    // with a policy built the same way as for the request url
+    async fn put_content_location(
+        &self,
+        middleware: &impl Middleware,
+        mut res: HttpResponse,
+        request: Option<RequestSnapshot>,
+        metadata: Option<HashMap<String, String>>,
+    ) -> Result<HttpResponse> {
+        let url = middleware.url()?;
+        let Some(location) = res
+            .parts
+            .headers
+            .get(CONTENT_LOCATION.as_str())
+            .and_then(|location| same_origin_url(&url, location))
+            .filter(|location| *location != url)
+        else {
+            return Ok(res);
+        };
+        let mut parts = middleware.parts()?;
+        parts.uri = location.as_str().parse()?;
+        let cache_info = res.cache_info.take();
+        let (res_parts, body) = res.into_parts();
+        let body = body
+            .bytes_with_hint(res_parts.content_length().unwrap_or(0))
+            .await?;
+        let copy = HttpResponse::from_parts(
+            Parts { url: location, ..res_parts.clone() },
+            body.clone().into(),
+        );
+        let policy =
+            self.policy_for_uri(middleware, &copy, Some(&parts.uri))?;
+        if policy.is_storable() {
+            let cache_key = self.options.create_cache_key(&parts, None);
+            store(&self.manager, cache_key, copy, policy, request, metadata)
+                .await?;
+        }
+        let mut res = HttpResponse::from_parts(res_parts, body.into());
+        res.cache_info = cache_info;
+        Ok(res)
+    }
+
+    // Buffers the body to check its length against the size band, unless the
+    // Content-Length already tells it doesn't fit
+    async fn body_within_limits(&self, res: &mut HttpResponse) -> Result<bool> {
+        let min = self.options.min_cacheable_body_bytes.unwrap_or(0);
+        let max = self.options.max_cacheable_body_bytes.unwrap_or(usize::MAX);
+        let content_length = res.parts.content_length();
+        if content_length.is_some_and(|len| len > max) {
+            return Ok(false);
+        }
+        let body = std::mem::replace(&mut res.body, Bytes::new().into());
+        match body.collect_up_to(content_length.unwrap_or(0), max).await? {
+            Ok(bytes) => {
+                let fits = bytes.len() >= min;
+                res.body = bytes.into();
+                Ok(fits)
+            }
+            Err(body) => {
+                res.body = body;
+                Ok(false)
+            }
+        }
+    }
+
+    fn headers_within_limits(&self, parts: &Parts) -> bool {
+        let bytes: usize = parts
+            .headers
+            .iter()
+            .map(|(name, value)| name.len() + value.len())
+            .sum();
+        self.options.max_stored_header_bytes.map_or(true, |max| bytes <= max)
+            && self
+                .options
+                .max_stored_header_count
+                .map_or(true, |max| parts.headers.len() <= max)
+    }
+
+    // Drops the largest headers first, as they are the likeliest to be junk
+    fn drop_excess_headers(&self, parts: &mut Parts) {
+        let mut headers: Vec<_> = parts.headers.drain().collect();
+        headers.sort_by_key(|(name, value)| {
+            (name.len() + value.len(), name.clone())
+        });
+        let mut bytes = 0;
+        for (name, value) in headers {
+            bytes += name.len() + value.len();
+            if self
+                .options
+                .max_stored_header_bytes
+                .is_some_and(|max| bytes > max)
+                || self
+                    .options
+                    .max_stored_header_count
+                    .is_some_and(|max| parts.headers.len() >= max)
+            {
+                break;
+            }
+            parts.headers.insert(name, value);
+        }
+    }
+
+    // The policy of a stored response whose Date was ahead of the local clock when
+    // it was received, judged as if the Date was pulled back by up to the tolerance.
+    // The stored response and policy keep the Date sent by the origin.
+    fn skew_adjusted_policy(
+        &self,
+        middleware: &impl Middleware,
+        cached_res: &HttpResponse,
+        policy: &CachePolicy,
+        now: SystemTime,
+    ) -> Result<Option<CachePolicy>> {
+        let tolerance = self.options.clock_skew_tolerance;
+        let headers = &cached_res.parts.headers;
+        let date = headers
+            .get(DATE.as_str())
+            .and_then(|date| httpdate::parse_http_date(date).ok());
+        let age_header = headers
+            .get(AGE.as_str())
+            .and_then(|age| age.trim().parse().ok())
+            .map_or(Duration::ZERO, Duration::from_secs);
+        // The age of the policy is the Age header plus the time since it was
+        // received
+        let received =
+            now.checked_sub(policy.age(now).saturating_sub(age_header));
+        let (false, Some(date), Some(received)) =
+            (tolerance.is_zero(), date, received)
+        else {
+            return Ok(None);
+        };
+        if date <= received {
+            return Ok(None);
+        }
+        let adjusted =
+            date.checked_sub(tolerance).map_or(received, |d| d.max(received));
+        let mut parts = cached_res.parts.clone();
+        parts
+            .headers
+            .insert(DATE.to_string(), httpdate::fmt_http_date(adjusted));
+        // the body doesn't take part in the policy
+        let res = HttpResponse::from_parts(parts, Bytes::new().into());
+        Ok(Some(CachePolicy::new_options(
+            &self.policy_parts(middleware)?,
+            &res.parts()?,
+            received,
+            self.cache_options(middleware)?.unwrap_or_default(),
+        )))
+    }
+
+    async fn put(
+        &self,
+        middleware: &impl Middleware,
+        cache_key: String,
+        mut res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        // A Vary header field-value of "*" always fails to match a subsequent
+        // request, so such a response must never be stored.
+        // (https://tools.ietf.org/html/rfc7231#section-7.1.4)
+        if res.varies_on_any() {
+            return Ok(res);
+        }
+        // Set-Cookie responses are usually specific to a user, so they are
+        // unsafe to store in a shared cache
+        if self.options.skip_cache_on_set_cookie
+            && self
+                .cache_options(middleware)?
+                .map_or(true, |options| options.shared)
+            && res.parts.headers.contains_key(SET_COOKIE.as_str())
+        {
+            return Ok(res);
+        }
+        // Guards the cache against responses with huge numbers of headers
+        if !self.headers_within_limits(&res.parts) {
+            if !self.options.drop_excess_headers {
+                return Ok(res);
+            }
+            self.drop_excess_headers(&mut res.parts);
+        }
+        if (self.options.min_cacheable_body_bytes.is_some()
+            || self.options.max_cacheable_body_bytes.is_some())
+            && !self.body_within_limits(&mut res).await?
+        {
+            return Ok(res);
+        }
+        if let Some(max_variants) = self.options.max_variants_per_url {
+            self.limit_variants(middleware, &cache_key, max_variants).await?;
+        }
+        let parts = middleware.parts()?;
+        res.decode_limit = self.options.max_cacheable_body_bytes;
+        let metadata =
+            self.options.metadata_fn.as_ref().map(|f| f(&parts, &res));
+        res.decode_limit = None;
+        let request =
+            self.options.store_request.then(|| RequestSnapshot::from(&parts));
+        if self.options.store_content_location {
+            res = self
+                .put_content_location(
+                    middleware,
+                    res,
+                    request.clone(),
+                    metadata.clone(),
+                )
+                .await?;
+        }
+        // Managers rebuild the response they return, dropping the cache info
+        let cache_info = res.cache_info.take();
+        if self.options.stream_while_caching && res.body.as_bytes().is_none() {
+            if let (Some(spawn), Some(manager)) =
+                (&self.options.spawn, self.manager.detach())
+            {
+                let (parts, body) = res.into_parts();
+                let (forwarded, copy) = body.tee();
+                let copy = HttpResponse::from_parts(parts.clone(), copy);
+                spawn(Box::pin(async move {
+                    store(&manager, cache_key, copy, policy, request, metadata)
+                        .await
+                        .ok();
+                }));
+                let mut forwarded = HttpResponse::from_parts(parts, forwarded);
+                forwarded.cache_info = cache_info;
+                return Ok(forwarded);
+            }
+        }
+        let mut res =
+            store(&self.manager, cache_key, res, policy, request, metadata)
+                .await?;
+        res.cache_info = cache_info;
+        Ok(res)
+    }
+
     async fn conditional_fetch(
         &self,
         mut middleware: impl Middleware,
         mut cached_res: HttpResponse,
         mut policy: CachePolicy,
+        stored_request: Option<RequestSnapshot>,
+        deferred_until: Option<SystemTime>,
     ) -> Result<HttpResponse> {
-        let before_req =
-            policy.before_request(&middleware.parts()?, SystemTime::now());
+        let now = self.options.now();
+        let mut before_req =
+            policy.before_request(&self.policy_parts(&middleware)?, now);
+        if let BeforeRequest::Stale { matches: false, .. } = before_req {
+            if let Some(parts) = self.vary_matched_parts(
+                &middleware,
+                &cached_res,
+                stored_request.as_ref(),
+            )? {
+                if let fresh @ BeforeRequest::Fresh(_) =
+                    policy.before_request(&parts, now)
+                {
+                    before_req = fresh;
+                }
+            }
+        }
+        // Only responses that otherwise match the request are judged again, see
+        // clock_skew_tolerance
+        if let BeforeRequest::Stale { matches: true, .. } = before_req {
+            if let Some(adjusted) = self.skew_adjusted_policy(
+                &middleware,
+                &cached_res,
+                &policy,
+                now,
+            )? {
+                if let BeforeRequest::Fresh(mut parts) = adjusted
+                    .before_request(&self.policy_parts(&middleware)?, now)
+                {
+                    // The response keeps the Date sent by the origin
+                    parts.headers.remove(DATE);
+                    before_req = BeforeRequest::Fresh(parts);
+                    policy = adjusted;
+                }
+            }
+        }
         match before_req {
             BeforeRequest::Fresh(parts) => {
                 cached_res.update_headers(&parts)?;
-                if self.options.cache_status_headers {
-                    cached_res.cache_status(HitOrMiss::HIT);
+                self.cache_status(
+                    &mut cached_res,
+                    HitOrMiss::HIT,
+                    Some(&policy),
+                );
+                if self.status_headers() {
                     cached_res.cache_lookup_status(HitOrMiss::HIT);
                 }
+                // A stale response can be used without revalidation when the
+                // request allows it with the max-stale directive
+                if policy.is_stale(now) {
+                    //   110 Response is stale
+                    //   MUST be included whenever the returned response
+                    //   is stale.
+                    // (https://tools.ietf.org/html/rfc2616#section-14.46)
+                    self.add_warning(&mut cached_res, 110, "Response is stale");
+                    self.transform_stale(&mut cached_res);
+                }
                 return Ok(cached_res);
             }
-            BeforeRequest::Stale { request: parts, matches } => {
+            BeforeRequest::Stale { request: mut parts, matches } => {
                 if matches {
+                    if self.within_stale_grace(
+                        &middleware,
+                        &cached_res,
+                        &policy,
+                        now,
+                    )? && self.revalidate_in_background(&middleware)?
+                    {
+                        //   110 Response is stale
+                        //   MUST be included whenever the returned response
+                        //   is stale.
+                        // (https://tools.ietf.org/html/rfc2616#section-14.46)
+                        self.add_warning(
+                            &mut cached_res,
+                            110,
+                            "Response is stale",
+                        );
+                        self.cache_status(
+                            &mut cached_res,
+                            HitOrMiss::HIT,
+                            Some(&policy),
+                        );
+                        if self.status_headers() {
+                            cached_res.cache_lookup_status(HitOrMiss::HIT);
+                        }
+                        self.transform_stale(&mut cached_res);
+                        return Ok(cached_res);
+                    }
+                    // Revalidate with the Accept header sent by the client
+                    if self.options.accept_normalizer.is_some() {
+                        if let Some(accept) =
+                            middleware.parts()?.headers.get(ACCEPT)
+                        {
+                            parts.headers.insert(ACCEPT, accept.clone());
+                        }
+                    }
                     middleware.update_headers(&parts)?;
                 }
             }
         }
-        let req_url = middleware.url()?;
-        match middleware.remote_fetch().await {
+        if !cached_res.must_revalidate()
+            && self.options.is_offline.as_ref().is_some_and(|offline| offline())
+        {
+            //   112 Disconnected operation
+            // SHOULD be included if the cache is intentionally disconnected from
+            // the rest of the network for a period of time.
+            // (https://tools.ietf.org/html/rfc2616#section-14.46)
+            self.add_warning(&mut cached_res, 112, "Disconnected operation");
+            self.cache_status(&mut cached_res, HitOrMiss::HIT, Some(&policy));
+            self.transform_stale(&mut cached_res);
+            return Ok(cached_res);
+        }
+        // The origin asked not to be contacted again before Retry-After, unless the
+        // response must not be served stale
+        if self.options.honor_retry_after
+            && !cached_res.must_revalidate()
+            && deferred_until.is_some_and(|until| until > now)
+        {
+            return Ok(self.revalidation_failed(cached_res, &policy));
+        }
+        let started = Instant::now();
+        let revalidated =
+            self.revalidate_with_retries(&mut middleware, now).await;
+        if let Some(observer) = &self.options.observer {
+            observer.on_revalidation(
+                &self.options.create_cache_key(&middleware.parts()?, None),
+                started.elapsed(),
+            );
+        }
+        match revalidated {
             Ok(mut cond_res) => {
                 let status = StatusCode::from_u16(cond_res.parts.status)?;
-                if status.is_server_error() && cached_res.must_revalidate() {
+                // The stale response keeps being served until Retry-After
+                if status == StatusCode::SERVICE_UNAVAILABLE
+                    && self.options.honor_retry_after
+                    && !cached_res.must_revalidate()
+                {
+                    if let Some(until) = cond_res.retry_after(now) {
+                        cached_res = self
+                            .defer_revalidation(
+                                &middleware,
+                                cached_res,
+                                &policy,
+                                until,
+                            )
+                            .await?;
+                    }
+                }
+                // 429 Too Many Requests asks us to back off, so it is treated
+                // like a server error when deciding to serve the stale response
+                let is_error = status.is_server_error()
+                    || status == StatusCode::TOO_MANY_REQUESTS;
+                if is_error && cached_res.must_revalidate() {
                     //   111 Revalidation failed
                     //   MUST be included if a cache returns a stale response
                     //   because an attempt to revalidate the response failed,
                     //   due to an inability to reach the server.
                     // (https://tools.ietf.org/html/rfc2616#section-14.46)
-                    cached_res.add_warning(
-                        &req_url,
+                    self.add_warning(
+                        &mut cached_res,
                         111,
                         "Revalidation failed",
                     );
-                    if self.options.cache_status_headers {
-                        cached_res.cache_status(HitOrMiss::HIT);
-                    }
+                    self.cache_status(
+                        &mut cached_res,
+                        HitOrMiss::HIT,
+                        Some(&policy),
+                    );
+                    self.transform_stale(&mut cached_res);
                     Ok(cached_res)
                 } else if cond_res.parts.status == 304 {
+                    // A 304 can't change the stored body, so headers describing it
+                    // are never taken from a misbehaving origin's response
+                    let mut cond_parts = cond_res.parts()?;
+                    for name in [CONTENT_TYPE, CONTENT_LENGTH, CONTENT_ENCODING]
+                    {
+                        cond_parts.headers.remove(name);
+                    }
                     let after_res = policy.after_response(
-                        &middleware.parts()?,
-                        &cond_res.parts()?,
-                        SystemTime::now(),
+                        &self.policy_parts(&middleware)?,
+                        &cond_parts,
+                        self.options.now(),
                     );
                     match after_res {
                         AfterResponse::Modified(new_policy, parts)
@@ -808,69 +3593,251 @@ impl<T: CacheManager> HttpCache<T> {
                             cached_res.update_headers(&parts)?;
                         }
                     }
-                    if self.options.cache_status_headers {
-                        cached_res.cache_status(HitOrMiss::HIT);
+                    if self.options.trust_304_body
+                        || self.options.observer.is_some()
+                    {
+                        let body = cond_res.into_parts().1.bytes().await?;
+                        if let (Some(observer), false) =
+                            (&self.options.observer, body.is_empty())
+                        {
+                            observer.on_not_modified_body(
+                                &self.options.create_cache_key(
+                                    &middleware.parts()?,
+                                    None,
+                                ),
+                                &body,
+                            );
+                        }
+                        if self.options.trust_304_body && !body.is_empty() {
+                            let (mut parts, _) = cached_res.into_parts();
+                            if let Some(len) =
+                                parts.headers.get_mut(CONTENT_LENGTH.as_str())
+                            {
+                                *len = body.len().to_string();
+                            }
+                            cached_res =
+                                HttpResponse::from_parts(parts, body.into());
+                        }
+                    }
+                    self.cache_status(
+                        &mut cached_res,
+                        HitOrMiss::HIT,
+                        Some(&policy),
+                    );
+                    if self.status_headers() {
                         cached_res.cache_lookup_status(HitOrMiss::HIT);
                     }
-                    let res = self
-                        .manager
-                        .put(
-                            self.options
-                                .create_cache_key(&middleware.parts()?, None),
-                            cached_res,
-                            policy,
-                        )
-                        .await?;
-                    Ok(res)
+                    self.put(
+                        &middleware,
+                        self.options
+                            .create_cache_key(&middleware.parts()?, None),
+                        cached_res,
+                        policy,
+                    )
+                    .await
                 } else if cond_res.parts.status == 200 {
-                    let policy = match self.options.cache_options {
-                        Some(options) => middleware
-                            .policy_with_options(&cond_res, options)?,
-                        None => middleware.policy(&cond_res)?,
-                    };
-                    if self.options.cache_status_headers {
-                        cond_res.cache_status(HitOrMiss::MISS);
+                    cond_res.vary_on_content_encoding();
+                    self.override_cache_control(&mut cond_res);
+                    let policy = self.policy(&middleware, &cond_res)?;
+                    self.cache_status(
+                        &mut cond_res,
+                        HitOrMiss::MISS,
+                        Some(&policy),
+                    );
+                    if self.status_headers() {
                         cond_res.cache_lookup_status(HitOrMiss::HIT);
                     }
-                    let res = self
-                        .manager
-                        .put(
-                            self.options
-                                .create_cache_key(&middleware.parts()?, None),
-                            cond_res,
-                            policy,
-                        )
-                        .await?;
-                    Ok(res)
-                } else {
-                    if self.options.cache_status_headers {
-                        cached_res.cache_status(HitOrMiss::HIT);
+                    let cache_key = self
+                        .options
+                        .create_cache_key(&middleware.parts()?, None);
+                    if let Some(observer) = &self.options.observer {
+                        let old = cached_res.buffered().await?;
+                        cond_res = cond_res.buffered().await?;
+                        observer
+                            .on_content_changed(&cache_key, &old, &cond_res);
                     }
+                    self.put(&middleware, cache_key, cond_res, policy).await
+                } else {
+                    self.cache_status(
+                        &mut cached_res,
+                        HitOrMiss::HIT,
+                        Some(&policy),
+                    );
+                    self.transform_stale(&mut cached_res);
                     Ok(cached_res)
                 }
             }
             Err(e) => {
                 if cached_res.must_revalidate() {
-                    Err(e)
+                    self.fallback(&middleware, e)
                 } else {
-                    //   111 Revalidation failed
-                    //   MUST be included if a cache returns a stale response
-                    //   because an attempt to revalidate the response failed,
-                    //   due to an inability to reach the server.
-                    // (https://tools.ietf.org/html/rfc2616#section-14.46)
-                    cached_res.add_warning(
-                        &req_url,
-                        111,
-                        "Revalidation failed",
-                    );
-                    if self.options.cache_status_headers {
-                        cached_res.cache_status(HitOrMiss::HIT);
-                    }
-                    Ok(cached_res)
+                    Ok(self.revalidation_failed(cached_res, &policy))
                 }
             }
         }
     }
+
+    // Stores the entry again along with the date its revalidation is deferred until
+    async fn defer_revalidation(
+        &self,
+        middleware: &impl Middleware,
+        cached_res: HttpResponse,
+        policy: &CachePolicy,
+        until: SystemTime,
+    ) -> Result<HttpResponse> {
+        let (parts, body) = cached_res.into_parts();
+        let body =
+            body.bytes_with_hint(parts.content_length().unwrap_or(0)).await?;
+        let mut deferred =
+            HttpResponse::from_parts(parts.clone(), body.clone().into());
+        deferred
+            .parts
+            .headers
+            .insert(DEFERRED_UNTIL.to_string(), httpdate::fmt_http_date(until));
+        self.put(
+            middleware,
+            self.options.create_cache_key(&middleware.parts()?, None),
+            deferred,
+            policy.clone(),
+        )
+        .await?;
+        Ok(HttpResponse::from_parts(parts, body.into()))
+    }
+
+    fn revalidation_failed(
+        &self,
+        mut cached_res: HttpResponse,
+        policy: &CachePolicy,
+    ) -> HttpResponse {
+        //   111 Revalidation failed
+        //   MUST be included if a cache returns a stale response
+        //   because an attempt to revalidate the response failed,
+        //   due to an inability to reach the server.
+        // (https://tools.ietf.org/html/rfc2616#section-14.46)
+        self.add_warning(&mut cached_res, 111, "Revalidation failed");
+        self.cache_status(&mut cached_res, HitOrMiss::HIT, Some(policy));
+        self.transform_stale(&mut cached_res);
+        cached_res
+    }
+}
+
+// Splits a Warning header into its values, at the commas outside of the quoted
+// warn-text and warn-date
+fn warning_values(warnings: &str) -> Vec<&str> {
+    let mut values = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in warnings.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                values.push(warnings[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    values.push(warnings[start..].trim());
+    values.retain(|value| !value.is_empty());
+    values
+}
+
+// Whether requests using `method` leave the resource unchanged, counting the
+// read-only methods of WebDAV, which may be among the cacheable_methods
+// (https://tools.ietf.org/html/rfc4918#section-9.1)
+fn is_safe(method: &http::Method) -> bool {
+    method.is_safe() || matches!(method.as_str(), "PROPFIND" | "REPORT")
+}
+
+// Resolves a url reference found in a response to `url`, if it has the same origin
+fn same_origin_url(url: &Url, reference: &str) -> Option<Url> {
+    url.join(reference).ok().filter(|target| target.origin() == url.origin())
+}
+
+// Removes the max-age and s-maxage directives, which take precedence over Expires
+fn without_max_age(cache_control: &str) -> String {
+    cache_control
+        .split(',')
+        .map(str::trim)
+        .filter(|directive| {
+            let name = directive.split('=').next().unwrap_or_default();
+            !name.trim().eq_ignore_ascii_case("max-age")
+                && !name.trim().eq_ignore_ascii_case("s-maxage")
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Runs blocking work, such as file system access, on a thread of its own, since the
+// async runtime in use, and with it any pool for blocking work, isn't known
+#[cfg(any(feature = "manager-cacache", feature = "archive"))]
+async fn unblock<T, F>(work: F) -> Result<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let (sender, receiver) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        sender.send(work()).ok();
+    });
+    Ok(receiver.await?)
+}
+
+// Writes a response with the manager method matching the extra data to store
+async fn store<M: CacheManager + ?Sized>(
+    manager: &M,
+    cache_key: String,
+    res: HttpResponse,
+    policy: CachePolicy,
+    request: Option<RequestSnapshot>,
+    metadata: Option<HashMap<String, String>>,
+) -> Result<HttpResponse> {
+    match (request, metadata) {
+        (request, Some(metadata)) => {
+            manager
+                .put_with_metadata(cache_key, res, policy, request, metadata)
+                .await
+        }
+        (Some(request), None) => {
+            manager.put_with_request(cache_key, res, policy, request).await
+        }
+        (None, None) => manager.put(cache_key, res, policy).await,
+    }
+}
+
+// Nobody awaits the revalidation, so its errors are ignored
+fn background_revalidation(
+    cache: HttpCache<Box<dyn CacheManager>>,
+    cache_key: String,
+    middleware: BoxMiddleware,
+) -> BoxFuture<'static, ()> {
+    Box::pin(async move {
+        if let Ok(Some((mut res, policy, stored_request))) =
+            cache.manager.get_with_request(&cache_key).await
+        {
+            let deferred_until = res.take_deferred_until();
+            // Only reached for such entries when they are rebuilt, see OnCorrupt
+            let policy = if res.take_rebuilt_policy() {
+                let Ok(policy) = cache.policy(&middleware, &res) else {
+                    return;
+                };
+                policy
+            } else {
+                policy
+            };
+            cache
+                .conditional_fetch(
+                    middleware,
+                    res,
+                    policy,
+                    stored_request,
+                    deferred_until,
+                )
+                .await
+                .ok();
+        }
+    })
 }
 
 #[cfg(test)]
@@ -0,0 +1,176 @@
+use crate::{
+    archive::ArchiveEntry, CacheManager, HttpResponse, RequestSnapshot, Result,
+    Unsupported,
+};
+
+use std::{collections::HashMap, fs::File, io, path::PathBuf, sync::Arc};
+
+use http_cache_semantics::CachePolicy;
+
+/// Serves the records of a tar archive created by [`HttpCache::export_archive`], e.g.
+/// to ship a pre-warmed cache bundle along with an application.
+///
+/// The archive is scanned once when opened to find the position of each record, and
+/// records are only read from the file when requested, with positioned reads on a
+/// handle shared by all clones of the manager.
+/// The bundle is read-only: storing or removing records returns an [`Unsupported`]
+/// error, so it is best used with
+/// [`CacheMode::OnlyIfCached`](crate::CacheMode::OnlyIfCached), which never stores
+/// responses.
+///
+/// [`HttpCache::export_archive`]: crate::HttpCache::export_archive
+///
+/// ```no_run
+/// # use http_cache::{ArchiveManager, CacheMode, HttpCache, HttpCacheOptions};
+/// # fn run() -> http_cache::Result<()> {
+/// let cache = HttpCache {
+///     mode: CacheMode::OnlyIfCached,
+///     manager: ArchiveManager::open("./bundle.tar")?,
+///     options: HttpCacheOptions::default(),
+/// };
+/// # Ok(())
+/// # }
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "archive")))]
+#[derive(Debug, Clone)]
+pub struct ArchiveManager {
+    file: Arc<File>,
+    // The position and size of the record stored under each key
+    index: Arc<HashMap<String, (u64, u64)>>,
+}
+
+impl ArchiveManager {
+    /// Opens the archive at `path`, indexing its records by key. When a key appears
+    /// more than once, the last record wins, as with [`HttpCache::import_archive`].
+    ///
+    /// [`HttpCache::import_archive`]: crate::HttpCache::import_archive
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let file = File::open(path.into())?;
+        let mut archive = tar::Archive::new(&file);
+        let mut index = HashMap::new();
+        for file in archive.entries()? {
+            let mut file = file?;
+            let position = file.raw_file_position();
+            let size = file.size();
+            // The key is encoded first, so the rest of the record is skipped
+            let key: String = bincode::deserialize_from(&mut file)?;
+            index.insert(key, (position, size));
+        }
+        Ok(Self { file: Arc::new(file), index: Arc::new(index) })
+    }
+
+    // Positioned reads leave the offset of the shared handle alone, so concurrent
+    // reads need neither a lock nor a handle of their own
+    fn read(&self, cache_key: &str) -> Result<Option<ArchiveEntry>> {
+        let Some(&(position, size)) = self.index.get(cache_key) else {
+            return Ok(None);
+        };
+        let mut data = vec![0; usize::try_from(size)?];
+        read_exact_at(&self.file, &mut data, position)?;
+        Ok(Some(bincode::deserialize(&data)?))
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(
+    file: &File,
+    mut buf: &mut [u8],
+    mut offset: u64,
+) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            read => {
+                buf = &mut buf[read..];
+                offset += read as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl CacheManager for ArchiveManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        Ok(self.read(cache_key)?.map(|entry| {
+            (
+                HttpResponse::from_parts(entry.parts, entry.body.into()),
+                entry.policy,
+            )
+        }))
+    }
+
+    async fn get_with_request(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, Option<RequestSnapshot>)>>
+    {
+        Ok(self
+            .peek_with_metadata(cache_key)
+            .await?
+            .map(|(res, policy, request, _)| (res, policy, request)))
+    }
+
+    async fn get_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, HashMap<String, String>)>>
+    {
+        Ok(self
+            .peek_with_metadata(cache_key)
+            .await?
+            .map(|(res, policy, _, metadata)| (res, policy, metadata)))
+    }
+
+    async fn peek_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<
+        Option<(
+            HttpResponse,
+            CachePolicy,
+            Option<RequestSnapshot>,
+            HashMap<String, String>,
+        )>,
+    > {
+        Ok(self.read(cache_key)?.map(|entry| {
+            (
+                HttpResponse::from_parts(entry.parts, entry.body.into()),
+                entry.policy,
+                entry.request,
+                entry.metadata,
+            )
+        }))
+    }
+
+    async fn put(
+        &self,
+        _cache_key: String,
+        _res: HttpResponse,
+        _policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        Err(Box::new(Unsupported))
+    }
+
+    async fn delete(&self, _cache_key: &str) -> Result<()> {
+        Err(Box::new(Unsupported))
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        Ok(self.index.keys().cloned().collect())
+    }
+
+    fn detach(&self) -> Option<Box<dyn CacheManager>> {
+        Some(Box::new(self.clone()))
+    }
+}
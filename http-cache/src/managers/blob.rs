@@ -0,0 +1,170 @@
+use crate::{CacheManager, HttpResponse, Parts, RequestSnapshot, Result};
+
+use std::collections::HashMap;
+
+use bytes::Bytes;
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+
+/// A minimal key-value storage backend, turned into a full [`CacheManager`] by
+/// [`BlobManager`].
+///
+/// Implementations only move opaque bytes around, the encoding of the cached
+/// records is handled by the [`BlobManager`].
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-blob")))]
+#[async_trait::async_trait]
+pub trait BlobStore: Send + Sync + 'static {
+    /// Attempts to read the bytes stored under `key`.
+    async fn read(&self, key: &str) -> Result<Option<Bytes>>;
+    /// Attempts to store `bytes` under `key`, replacing any previous value.
+    async fn write(&self, key: &str, bytes: Bytes) -> Result<()>;
+    /// Attempts to remove the bytes stored under `key`.
+    async fn remove(&self, key: &str) -> Result<()>;
+}
+
+/// Implements [`CacheManager`] over any [`BlobStore`].
+///
+/// The response parts, body, cache policy and request snapshot (if any) are
+/// serialized together into a single blob per cache key. Blobs that can't be
+/// decoded, for example because they were written by another version, are
+/// treated as a miss.
+///
+/// ```
+/// # use http_cache::{BlobManager, BlobStore, Result};
+/// # use bytes::Bytes;
+/// use std::{collections::HashMap, sync::Mutex};
+///
+/// #[derive(Default)]
+/// struct MapStore(Mutex<HashMap<String, Bytes>>);
+///
+/// #[async_trait::async_trait]
+/// impl BlobStore for MapStore {
+///     async fn read(&self, key: &str) -> Result<Option<Bytes>> {
+///         Ok(self.0.lock().unwrap().get(key).cloned())
+///     }
+///
+///     async fn write(&self, key: &str, bytes: Bytes) -> Result<()> {
+///         self.0.lock().unwrap().insert(key.to_string(), bytes);
+///         Ok(())
+///     }
+///
+///     async fn remove(&self, key: &str) -> Result<()> {
+///         self.0.lock().unwrap().remove(key);
+///         Ok(())
+///     }
+/// }
+///
+/// let manager = BlobManager::new(MapStore::default());
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-blob")))]
+#[derive(Debug, Clone)]
+pub struct BlobManager<B> {
+    /// The store that holds the serialized records
+    pub store: B,
+}
+
+impl<B: BlobStore> BlobManager<B> {
+    /// Create a new manager storing records in `store`.
+    pub fn new(store: B) -> Self {
+        Self { store }
+    }
+
+    async fn read(&self, cache_key: &str) -> Result<Option<Record>> {
+        Ok(self
+            .store
+            .read(cache_key)
+            .await?
+            .and_then(|bytes| bincode::deserialize(&bytes).ok()))
+    }
+
+    async fn write(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+        request: Option<RequestSnapshot>,
+    ) -> Result<HttpResponse> {
+        let (parts, body) = res.into_parts();
+        let body =
+            body.bytes_with_hint(parts.content_length().unwrap_or(0)).await?;
+        let record = Record { parts, body: body.into(), policy, request };
+        self.store
+            .write(&cache_key, bincode::serialize(&record)?.into())
+            .await?;
+        Ok(HttpResponse::from_parts(record.parts, record.body.into()))
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Record {
+    parts: Parts,
+    body: Vec<u8>,
+    policy: CachePolicy,
+    request: Option<RequestSnapshot>,
+}
+
+#[async_trait::async_trait]
+impl<B: BlobStore> CacheManager for BlobManager<B> {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        Ok(self
+            .get_with_request(cache_key)
+            .await?
+            .map(|(res, policy, _)| (res, policy)))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.write(cache_key, res, policy, None).await
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.store.remove(cache_key).await
+    }
+
+    async fn put_with_request(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+        request: RequestSnapshot,
+    ) -> Result<HttpResponse> {
+        self.write(cache_key, res, policy, Some(request)).await
+    }
+
+    async fn get_with_request(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, Option<RequestSnapshot>)>>
+    {
+        Ok(self.read(cache_key).await?.map(|record| {
+            (
+                HttpResponse::from_stored(record.parts, record.body.into()),
+                record.policy,
+                record.request,
+            )
+        }))
+    }
+
+    async fn peek_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<
+        Option<(
+            HttpResponse,
+            CachePolicy,
+            Option<RequestSnapshot>,
+            HashMap<String, String>,
+        )>,
+    > {
+        Ok(self.get_with_request(cache_key).await?.map(
+            |(res, policy, request)| (res, policy, request, HashMap::new()),
+        ))
+    }
+}
@@ -1,10 +1,12 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::result::Result as StdResult;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::{Body, CacheManager, HttpResponse, Parts, Result};
+use crate::{Body, CacheManager, HttpResponse, Parts, RequestSnapshot, Result};
 
 use bytes::Bytes;
-use cacache::{Reader, Writer};
+use cacache::{Reader, WriteOpts, Writer};
 use futures::{Stream, StreamExt};
 use http_body_util::combinators::BoxBody;
 use http_body_util::{BodyExt, StreamBody};
@@ -17,6 +19,11 @@ use futures::{AsyncReadExt, AsyncWriteExt};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 /// Implements [`CacheManager`] with [`cacache`](https://github.com/zkat/cacache-rs) as the backend.
+///
+/// Reads record the time of the access for [`CacheManager::last_accessed`] at most
+/// once a minute per entry, by appending an index entry that points at the stored
+/// content. [`CacheManager::touch`] keeps the date until which an entry is retained
+/// in its index entry as well.
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-cacache")))]
 #[derive(Debug, Clone)]
 pub struct CACacheManager {
@@ -33,6 +40,12 @@ impl Default for CACacheManager {
 // Cache binary value layout:
 // [u32 - size of the NoBodyStore][Store][response body bytes]
 // bincode works with pre-defined slice of bytes, so we need this u32 in front.
+// The policy is serialized on its own within the Store, so that a policy which
+// can't be decoded doesn't take the rest of the entry with it.
+
+// How recent the recorded access time of an entry must be for a read not to
+// record a new one
+const ACCESS_RESOLUTION: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Deserialize, Serialize)]
 enum BodyKind {
@@ -43,8 +56,18 @@ enum BodyKind {
 #[derive(Debug, Deserialize, Serialize)]
 struct Store {
     parts: Parts,
-    policy: CachePolicy,
+    policy: Vec<u8>,
     body_kind: BodyKind,
+    request: Option<RequestSnapshot>,
+    metadata: HashMap<String, String>,
+}
+
+// An entry read back from the cache
+struct Record {
+    res: HttpResponse,
+    policy: CachePolicy,
+    request: Option<RequestSnapshot>,
+    metadata: HashMap<String, String>,
 }
 
 #[allow(dead_code)]
@@ -135,34 +158,91 @@ mod cacache_stream {
 
 use cacache_stream::CACacheReaderStream;
 
-#[async_trait::async_trait]
-impl CacheManager for CACacheManager {
-    async fn get(
-        &self,
-        cache_key: &str,
-    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+impl CACacheManager {
+    // Variant lists are kept in a nested cache, so they never show up as records
+    fn variants_path(&self) -> PathBuf {
+        self.path.join("variants")
+    }
+
+    // Lists the index entries of the records, which cacache only does synchronously
+    async fn list(&self) -> Result<Vec<cacache::Metadata>> {
+        let path = self.path.clone();
+        crate::unblock(move || {
+            if !path.exists() {
+                return Ok(Vec::new());
+            }
+            Ok(cacache::list_sync(&path).collect::<StdResult<_, _>>()?)
+        })
+        .await?
+    }
+
+    // Appends a fresh index entry pointing at the existing content, which
+    // records the access time without rewriting the stored body. Accesses within
+    // ACCESS_RESOLUTION of the recorded one are skipped, so that most reads don't
+    // write, and failures are ignored, since they must not fail the read.
+    async fn record_access(&self, cache_key: &str) {
+        self.append_access(cache_key).await.ok();
+    }
+
+    async fn append_access(&self, cache_key: &str) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        if let Some(metadata) = cacache::metadata(&self.path, cache_key).await?
+        {
+            if now.saturating_sub(metadata.time) < ACCESS_RESOLUTION.as_millis()
+            {
+                return Ok(());
+            }
+            let mut opts = WriteOpts::new()
+                .integrity(metadata.integrity)
+                .size(metadata.size)
+                .metadata(metadata.metadata);
+            if let Some(raw_metadata) = metadata.raw_metadata {
+                opts = opts.raw_metadata(raw_metadata);
+            }
+            cacache::index::insert_async(&self.path, cache_key, opts).await?;
+        }
+        Ok(())
+    }
+
+    async fn read(&self, cache_key: &str) -> Result<Option<Record>> {
+        // Entries whose content is missing, truncated or corrupt, e.g. after a crash
+        // mid-write, are treated as a miss rather than an error.
         let mut reader = match Reader::open(&self.path, cache_key).await {
             Ok(reader) => reader,
             Err(err) => match err {
                 cacache::Error::EntryNotFound(..) => return Ok(None),
+                cacache::Error::IoError(e, _)
+                    if e.kind() == std::io::ErrorKind::NotFound =>
+                {
+                    return Ok(None)
+                }
                 _ => return Err(err.into()),
             },
         };
 
         // Reading "head" part length
         let mut buf = [0u8; 4];
-        reader.read_exact(&mut buf).await?;
+        if reader.read_exact(&mut buf).await.is_err() {
+            return Ok(None);
+        }
         let store_len = u32::from_le_bytes(buf);
 
         // Reading "head" part
         let mut buf = vec![0; store_len as usize];
-        reader.read_exact(buf.as_mut_slice()).await?;
-        let store: Store = bincode::deserialize(&buf)?;
+        if reader.read_exact(buf.as_mut_slice()).await.is_err() {
+            return Ok(None);
+        }
+        let Ok(store) = bincode::deserialize::<Store>(&buf) else {
+            return Ok(None);
+        };
 
         let body = match store.body_kind {
             BodyKind::Full => {
                 let mut body = Vec::new();
                 reader.read_to_end(&mut body).await?;
+                if reader.check().is_err() {
+                    return Ok(None);
+                }
                 Body { inner: crate::BodyInner::Full(body.into()) }
             }
             BodyKind::Streaming => Body {
@@ -172,14 +252,26 @@ impl CacheManager for CACacheManager {
                 )),
             },
         };
-        Ok(Some((HttpResponse::from_parts(store.parts, body), store.policy)))
+        let mut res = HttpResponse::from_stored(store.parts, body);
+        let policy = match bincode::deserialize(&store.policy) {
+            Ok(policy) => policy,
+            Err(_) => res.stand_in_policy()?,
+        };
+        Ok(Some(Record {
+            res,
+            policy,
+            request: store.request,
+            metadata: store.metadata,
+        }))
     }
 
-    async fn put(
+    async fn write(
         &self,
         cache_key: String,
         response: HttpResponse,
         policy: CachePolicy,
+        request: Option<RequestSnapshot>,
+        metadata: HashMap<String, String>,
     ) -> Result<HttpResponse> {
         let mut writer = Writer::create(&self.path, &cache_key).await?;
         let (parts, body) = response.into_parts();
@@ -187,7 +279,8 @@ impl CacheManager for CACacheManager {
             crate::BodyInner::Full(_) => BodyKind::Full,
             crate::BodyInner::Streaming(_) => BodyKind::Streaming,
         };
-        let data = Store { parts, policy, body_kind };
+        let policy = bincode::serialize(&policy)?;
+        let data = Store { parts, policy, body_kind, request, metadata };
         let bytes = bincode::serialize(&data)?;
         let store_len = (bytes.len() as u32).to_le_bytes();
 
@@ -210,17 +303,208 @@ impl CacheManager for CACacheManager {
                 }
             }
         }
+        // The parts, policy and body are written as a single content blob, and the
+        // index entry referencing it is only inserted once the content is committed,
+        // so a partial write never becomes visible to `get`.
         writer.commit().await?;
 
         // Safety: at this point we successfully created this cache entry,
         // so it is safe to unwrap here (cacache::Error::EntryNotFound should be impossible).
         // FIXME: does it make sense to return error here instead of unwrapping? If yes, then which error?
-        let (response, _) = self.get(&cache_key).await?.unwrap();
+        Ok(self.read(&cache_key).await?.unwrap().res)
+    }
+}
 
-        Ok(response)
+#[async_trait::async_trait]
+impl CacheManager for CACacheManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let entry = self.read(cache_key).await?;
+        if entry.is_some() {
+            self.record_access(cache_key).await;
+        }
+        Ok(entry.map(|record| (record.res, record.policy)))
+    }
+
+    async fn peek(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        Ok(self
+            .read(cache_key)
+            .await?
+            .map(|record| (record.res, record.policy)))
+    }
+
+    async fn peek_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<
+        Option<(
+            HttpResponse,
+            CachePolicy,
+            Option<RequestSnapshot>,
+            HashMap<String, String>,
+        )>,
+    > {
+        Ok(self.read(cache_key).await?.map(|record| {
+            (record.res, record.policy, record.request, record.metadata)
+        }))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.write(cache_key, response, policy, None, HashMap::new()).await
+    }
+
+    async fn put_with_request(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+        request: RequestSnapshot,
+    ) -> Result<HttpResponse> {
+        self.write(cache_key, response, policy, Some(request), HashMap::new())
+            .await
+    }
+
+    async fn put_with_metadata(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+        request: Option<RequestSnapshot>,
+        metadata: HashMap<String, String>,
+    ) -> Result<HttpResponse> {
+        self.write(cache_key, response, policy, request, metadata).await
+    }
+
+    async fn get_with_request(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, Option<RequestSnapshot>)>>
+    {
+        let entry = self.read(cache_key).await?;
+        if entry.is_some() {
+            self.record_access(cache_key).await;
+        }
+        Ok(entry.map(|record| (record.res, record.policy, record.request)))
+    }
+
+    async fn get_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, HashMap<String, String>)>>
+    {
+        let entry = self.read(cache_key).await?;
+        if entry.is_some() {
+            self.record_access(cache_key).await;
+        }
+        Ok(entry.map(|record| (record.res, record.policy, record.metadata)))
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        Ok(self
+            .list()
+            .await?
+            .into_iter()
+            .map(|metadata| metadata.key)
+            .collect())
+    }
+
+    async fn variants(&self, base_key: &str) -> Result<Vec<String>> {
+        let path = self.variants_path();
+        if cacache::metadata(&path, base_key).await?.is_none() {
+            return Ok(Vec::new());
+        }
+        Ok(bincode::deserialize(&cacache::read(&path, base_key).await?)?)
+    }
+
+    async fn set_variants(
+        &self,
+        base_key: &str,
+        variants: Vec<String>,
+    ) -> Result<()> {
+        cacache::write(
+            self.variants_path(),
+            base_key,
+            bincode::serialize(&variants)?,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn evict_expired(&self, now: SystemTime) -> Result<()> {
+        for metadata in self.list().await? {
+            if retained(&metadata, now) {
+                continue;
+            }
+            let key = metadata.key;
+            if let Some(record) = self.read(&key).await? {
+                if !crate::is_fresh(&record.policy, now)
+                    && !record.res.outlives_freshness(&record.policy, now)
+                {
+                    self.delete(&key).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Keeps the record from being removed by [`CacheManager::evict_expired`] for
+    /// `new_ttl` from now, even once its policy considers it stale. Lookups still
+    /// revalidate stale records as usual, and storing the record again resets it.
+    /// The retention is stored as a date of the system clock, which `evict_expired`
+    /// compares with the time it is given.
+    async fn touch(&self, cache_key: &str, new_ttl: Duration) -> Result<bool> {
+        let Some(metadata) = cacache::metadata(&self.path, cache_key).await?
+        else {
+            return Ok(false);
+        };
+        let retain_until =
+            (SystemTime::now() + new_ttl).duration_since(UNIX_EPOCH)?;
+        let opts = WriteOpts::new()
+            .integrity(metadata.integrity)
+            .size(metadata.size)
+            .metadata(metadata.metadata)
+            .raw_metadata(
+                (retain_until.as_millis() as u64).to_le_bytes().into(),
+            );
+        cacache::index::insert_async(&self.path, cache_key, opts).await?;
+        Ok(true)
+    }
+
+    async fn last_accessed(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<SystemTime>> {
+        Ok(cacache::metadata(&self.path, cache_key).await?.map(|metadata| {
+            UNIX_EPOCH + Duration::from_millis(metadata.time as u64)
+        }))
     }
 
     async fn delete(&self, cache_key: &str) -> Result<()> {
         Ok(cacache::remove(&self.path, cache_key).await?)
     }
+
+    fn detach(&self) -> Option<Box<dyn CacheManager>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+// Whether the entry was touched until a date past `now`, see CacheManager::touch
+fn retained(metadata: &cacache::Metadata, now: SystemTime) -> bool {
+    let retain_until = metadata
+        .raw_metadata
+        .as_deref()
+        .and_then(|raw| raw.try_into().ok())
+        .map(u64::from_le_bytes);
+    retain_until
+        .is_some_and(|until| UNIX_EPOCH + Duration::from_millis(until) > now)
 }
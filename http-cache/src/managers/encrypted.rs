@@ -0,0 +1,333 @@
+use crate::{Body, CacheManager, HttpResponse, Parts, RequestSnapshot, Result};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+
+// Stored body layout:
+// [12 bytes - random nonce][AES-256-GCM ciphertext of the bincode encoded Sealed]
+
+const NONCE_LEN: usize = 12;
+
+/// Wraps any [`CacheManager`], encrypting records at rest with AES-256-GCM.
+///
+/// The response parts, body and cache policy are serialized together with the request
+/// snapshot and metadata annotations, if any, and encrypted with a random nonce per record, which is stored in front of the ciphertext. The
+/// wrapped manager only sees a placeholder response carrying the ciphertext as its
+/// body, along with a placeholder policy that expires at the same time as the real
+/// one, so that expiration keeps working. Records that can't be decrypted, for
+/// example because they are corrupt or were written with another key, are treated
+/// as a miss.
+///
+/// The cache key, response url and the lists of variant keys are stored as-is by the
+/// wrapped manager.
+#[cfg_attr(docsrs, doc(cfg(feature = "encryption")))]
+#[derive(Clone)]
+pub struct EncryptedManager<M> {
+    /// The wrapped manager that stores the encrypted records
+    pub manager: M,
+    cipher: Aes256Gcm,
+}
+
+impl<M: fmt::Debug> fmt::Debug for EncryptedManager<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the key must never end up in logs
+        f.debug_struct("EncryptedManager")
+            .field("manager", &self.manager)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Sealed {
+    parts: Parts,
+    body: Vec<u8>,
+    policy: CachePolicy,
+    request: Option<RequestSnapshot>,
+    metadata: HashMap<String, String>,
+}
+
+impl Sealed {
+    fn into_record(self) -> (HttpResponse, CachePolicy) {
+        (HttpResponse::from_stored(self.parts, self.body.into()), self.policy)
+    }
+}
+
+impl<M: CacheManager> EncryptedManager<M> {
+    /// Create a new manager wrapping `manager`, encrypting records with the 256-bit `key`.
+    pub fn new(manager: M, key: &[u8; 32]) -> Self {
+        Self {
+            manager,
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        }
+    }
+
+    fn seal(&self, sealed: &Sealed) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, bincode::serialize(sealed)?.as_slice())
+            .map_err(|e| e.to_string())?;
+        let mut bytes = nonce.to_vec();
+        bytes.extend(ciphertext);
+        Ok(bytes)
+    }
+
+    async fn unseal(
+        &self,
+        record: Option<(HttpResponse, CachePolicy)>,
+    ) -> Result<Option<Sealed>> {
+        let Some((res, _)) = record else {
+            return Ok(None);
+        };
+        let bytes = res.into_parts().1.bytes().await?;
+        Ok(self.open(&bytes))
+    }
+
+    // Seals the response along with what's stored next to it, returning the
+    // placeholder record for the wrapped manager and the response for the caller
+    async fn placeholder(
+        &self,
+        response: HttpResponse,
+        policy: CachePolicy,
+        request: Option<RequestSnapshot>,
+        metadata: HashMap<String, String>,
+    ) -> Result<((HttpResponse, CachePolicy), HttpResponse)> {
+        let (parts, body) = response.into_parts();
+        let body =
+            body.bytes_with_hint(parts.content_length().unwrap_or(0)).await?;
+        let sealed =
+            Sealed { parts, body: body.into(), policy, request, metadata };
+        let placeholder = HttpResponse::from_parts(
+            Parts {
+                headers: Default::default(),
+                status: 200,
+                url: sealed.parts.url.clone(),
+                version: sealed.parts.version,
+            },
+            Body::from(self.seal(&sealed)?),
+        );
+        let placeholder_policy =
+            placeholder_policy(&sealed.parts, &sealed.policy)?;
+        Ok((
+            (placeholder, placeholder_policy),
+            HttpResponse::from_parts(sealed.parts, sealed.body.into()),
+        ))
+    }
+
+    async fn store(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+        request: Option<RequestSnapshot>,
+        metadata: HashMap<String, String>,
+    ) -> Result<HttpResponse> {
+        let ((placeholder, placeholder_policy), response) =
+            self.placeholder(response, policy, request, metadata).await?;
+        self.manager.put(cache_key, placeholder, placeholder_policy).await?;
+        Ok(response)
+    }
+
+    fn open(&self, bytes: &[u8]) -> Option<Sealed> {
+        if bytes.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext =
+            self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext).ok()?;
+        bincode::deserialize(&plaintext).ok()
+    }
+}
+
+// A policy that carries none of the stored headers but expires along with `policy`
+fn placeholder_policy(
+    parts: &Parts,
+    policy: &CachePolicy,
+) -> Result<CachePolicy> {
+    let ttl = policy.time_to_live(SystemTime::now()).as_secs();
+    let req = http::Request::get(parts.url.as_str()).body(())?.into_parts().0;
+    let res = http::Response::builder()
+        .status(200)
+        .header(http::header::CACHE_CONTROL, format!("max-age={ttl}"))
+        .body(())?
+        .into_parts()
+        .0;
+    Ok(CachePolicy::new(&req, &res))
+}
+
+#[async_trait::async_trait]
+impl<M: CacheManager> CacheManager for EncryptedManager<M> {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let sealed = self.unseal(self.manager.get(cache_key).await?).await?;
+        Ok(sealed.map(Sealed::into_record))
+    }
+
+    async fn peek(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let sealed = self.unseal(self.manager.peek(cache_key).await?).await?;
+        Ok(sealed.map(Sealed::into_record))
+    }
+
+    async fn peek_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<
+        Option<(
+            HttpResponse,
+            CachePolicy,
+            Option<RequestSnapshot>,
+            HashMap<String, String>,
+        )>,
+    > {
+        let sealed = self.unseal(self.manager.peek(cache_key).await?).await?;
+        Ok(sealed.map(|mut sealed| {
+            let request = sealed.request.take();
+            let metadata = std::mem::take(&mut sealed.metadata);
+            let (res, policy) = sealed.into_record();
+            (res, policy, request, metadata)
+        }))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.store(cache_key, response, policy, None, HashMap::new()).await
+    }
+
+    async fn put_with_request(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+        request: RequestSnapshot,
+    ) -> Result<HttpResponse> {
+        self.store(cache_key, response, policy, Some(request), HashMap::new())
+            .await
+    }
+
+    async fn put_with_metadata(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+        request: Option<RequestSnapshot>,
+        metadata: HashMap<String, String>,
+    ) -> Result<HttpResponse> {
+        self.store(cache_key, response, policy, request, metadata).await
+    }
+
+    async fn put_many(
+        &self,
+        entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<Vec<HttpResponse>> {
+        let mut placeholders = Vec::with_capacity(entries.len());
+        let mut responses = Vec::with_capacity(entries.len());
+        for (cache_key, response, policy) in entries {
+            let ((placeholder, placeholder_policy), response) = self
+                .placeholder(response, policy, None, HashMap::new())
+                .await?;
+            placeholders.push((cache_key, placeholder, placeholder_policy));
+            responses.push(response);
+        }
+        self.manager.put_many(placeholders).await?;
+        Ok(responses)
+    }
+
+    async fn get_with_request(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, Option<RequestSnapshot>)>>
+    {
+        let sealed = self.unseal(self.manager.get(cache_key).await?).await?;
+        Ok(sealed.map(|mut sealed| {
+            let request = sealed.request.take();
+            let (res, policy) = sealed.into_record();
+            (res, policy, request)
+        }))
+    }
+
+    async fn get_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, HashMap<String, String>)>>
+    {
+        let sealed = self.unseal(self.manager.get(cache_key).await?).await?;
+        Ok(sealed.map(|mut sealed| {
+            let metadata = std::mem::take(&mut sealed.metadata);
+            let (res, policy) = sealed.into_record();
+            (res, policy, metadata)
+        }))
+    }
+
+    async fn get_many(
+        &self,
+        cache_keys: &[String],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        let mut records = Vec::with_capacity(cache_keys.len());
+        for record in self.manager.get_many(cache_keys).await? {
+            let sealed = self.unseal(record).await?;
+            records.push(sealed.map(Sealed::into_record));
+        }
+        Ok(records)
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        self.manager.keys().await
+    }
+
+    async fn variants(&self, base_key: &str) -> Result<Vec<String>> {
+        self.manager.variants(base_key).await
+    }
+
+    async fn set_variants(
+        &self,
+        base_key: &str,
+        variants: Vec<String>,
+    ) -> Result<()> {
+        self.manager.set_variants(base_key, variants).await
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.manager.delete(cache_key).await
+    }
+
+    async fn touch(&self, cache_key: &str, new_ttl: Duration) -> Result<bool> {
+        self.manager.touch(cache_key, new_ttl).await
+    }
+
+    async fn evict_expired(&self, now: SystemTime) -> Result<()> {
+        self.manager.evict_expired(now).await
+    }
+
+    async fn last_accessed(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<SystemTime>> {
+        self.manager.last_accessed(cache_key).await
+    }
+
+    fn detach(&self) -> Option<Box<dyn CacheManager>> {
+        Some(Box::new(EncryptedManager {
+            manager: self.manager.detach()?,
+            cipher: self.cipher.clone(),
+        }))
+    }
+}
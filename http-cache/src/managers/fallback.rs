@@ -0,0 +1,301 @@
+use crate::{CacheManager, HttpResponse, Parts, RequestSnapshot, Result};
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use bytes::Bytes;
+use http_cache_semantics::CachePolicy;
+
+/// Combines two [`CacheManager`]s for read resilience.
+///
+/// Reads go to the `primary` manager and only fall back to the `secondary` when the
+/// primary returns an error, for example when a remote store is unreachable. A miss in
+/// the primary is returned as-is. Writes, deletes and the other operations changing
+/// the stored records go to both managers on a best-effort basis, failing only when
+/// both fail.
+///
+/// The response body is buffered in memory when storing, so both managers receive a
+/// copy.
+#[derive(Debug, Clone)]
+pub struct FallbackManager<P, S> {
+    /// The manager that serves reads while it is healthy
+    pub primary: P,
+    /// The manager that serves reads when the primary fails
+    pub secondary: S,
+}
+
+impl<P: CacheManager, S: CacheManager> FallbackManager<P, S> {
+    /// Create a new manager reading from `primary`, falling back to `secondary` on error.
+    pub fn new(primary: P, secondary: S) -> Self {
+        Self { primary, secondary }
+    }
+}
+
+// Buffers the body, so that both managers get their own copy
+async fn buffered(response: HttpResponse) -> Result<(Parts, Bytes)> {
+    let (parts, body) = response.into_parts();
+    let body =
+        body.bytes_with_hint(parts.content_length().unwrap_or(0)).await?;
+    Ok((parts, body))
+}
+
+fn copy(parts: &Parts, body: &Bytes) -> HttpResponse {
+    HttpResponse::from_parts(parts.clone(), body.clone().into())
+}
+
+// Keeps the first error when both results failed
+fn either<T, U>(first: Result<T>, second: Result<U>) -> Result<()> {
+    match (first, second) {
+        (Err(e), Err(_)) => Err(e),
+        _ => Ok(()),
+    }
+}
+
+// Prefers the result of the primary, unless it failed
+fn preferred<T>(primary: Result<T>, secondary: Result<T>) -> Result<T> {
+    match (primary, secondary) {
+        (Ok(value), _) | (Err(_), Ok(value)) => Ok(value),
+        (Err(e), Err(_)) => Err(e),
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: CacheManager, S: CacheManager> CacheManager for FallbackManager<P, S> {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        match self.primary.get(cache_key).await {
+            Ok(record) => Ok(record),
+            Err(_) => self.secondary.get(cache_key).await,
+        }
+    }
+
+    async fn peek(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        match self.primary.peek(cache_key).await {
+            Ok(record) => Ok(record),
+            Err(_) => self.secondary.peek(cache_key).await,
+        }
+    }
+
+    async fn peek_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<
+        Option<(
+            HttpResponse,
+            CachePolicy,
+            Option<RequestSnapshot>,
+            HashMap<String, String>,
+        )>,
+    > {
+        match self.primary.peek_with_metadata(cache_key).await {
+            Ok(record) => Ok(record),
+            Err(_) => self.secondary.peek_with_metadata(cache_key).await,
+        }
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let (parts, body) = buffered(response).await?;
+        let primary = self
+            .primary
+            .put(cache_key.clone(), copy(&parts, &body), policy.clone())
+            .await;
+        let secondary =
+            self.secondary.put(cache_key, copy(&parts, &body), policy).await;
+        either(primary, secondary)?;
+        Ok(HttpResponse::from_parts(parts, body.into()))
+    }
+
+    async fn put_with_request(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+        request: RequestSnapshot,
+    ) -> Result<HttpResponse> {
+        let (parts, body) = buffered(response).await?;
+        let primary = self
+            .primary
+            .put_with_request(
+                cache_key.clone(),
+                copy(&parts, &body),
+                policy.clone(),
+                request.clone(),
+            )
+            .await;
+        let secondary = self
+            .secondary
+            .put_with_request(cache_key, copy(&parts, &body), policy, request)
+            .await;
+        either(primary, secondary)?;
+        Ok(HttpResponse::from_parts(parts, body.into()))
+    }
+
+    async fn put_with_metadata(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+        request: Option<RequestSnapshot>,
+        metadata: HashMap<String, String>,
+    ) -> Result<HttpResponse> {
+        let (parts, body) = buffered(response).await?;
+        let primary = self
+            .primary
+            .put_with_metadata(
+                cache_key.clone(),
+                copy(&parts, &body),
+                policy.clone(),
+                request.clone(),
+                metadata.clone(),
+            )
+            .await;
+        let secondary = self
+            .secondary
+            .put_with_metadata(
+                cache_key,
+                copy(&parts, &body),
+                policy,
+                request,
+                metadata,
+            )
+            .await;
+        either(primary, secondary)?;
+        Ok(HttpResponse::from_parts(parts, body.into()))
+    }
+
+    async fn put_many(
+        &self,
+        entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<Vec<HttpResponse>> {
+        let mut buffered_entries = Vec::with_capacity(entries.len());
+        for (cache_key, response, policy) in entries {
+            let (parts, body) = buffered(response).await?;
+            buffered_entries.push((cache_key, parts, body, policy));
+        }
+        let copies = || {
+            buffered_entries
+                .iter()
+                .map(|(cache_key, parts, body, policy)| {
+                    (cache_key.clone(), copy(parts, body), policy.clone())
+                })
+                .collect()
+        };
+        either(
+            self.primary.put_many(copies()).await,
+            self.secondary.put_many(copies()).await,
+        )?;
+        Ok(buffered_entries
+            .into_iter()
+            .map(|(_, parts, body, _)| {
+                HttpResponse::from_parts(parts, body.into())
+            })
+            .collect())
+    }
+
+    async fn get_with_request(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, Option<RequestSnapshot>)>>
+    {
+        match self.primary.get_with_request(cache_key).await {
+            Ok(record) => Ok(record),
+            Err(_) => self.secondary.get_with_request(cache_key).await,
+        }
+    }
+
+    async fn get_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, HashMap<String, String>)>>
+    {
+        match self.primary.get_with_metadata(cache_key).await {
+            Ok(record) => Ok(record),
+            Err(_) => self.secondary.get_with_metadata(cache_key).await,
+        }
+    }
+
+    async fn get_many(
+        &self,
+        cache_keys: &[String],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        match self.primary.get_many(cache_keys).await {
+            Ok(records) => Ok(records),
+            Err(_) => self.secondary.get_many(cache_keys).await,
+        }
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        match self.primary.keys().await {
+            Ok(keys) => Ok(keys),
+            Err(_) => self.secondary.keys().await,
+        }
+    }
+
+    async fn variants(&self, base_key: &str) -> Result<Vec<String>> {
+        match self.primary.variants(base_key).await {
+            Ok(variants) => Ok(variants),
+            Err(_) => self.secondary.variants(base_key).await,
+        }
+    }
+
+    async fn set_variants(
+        &self,
+        base_key: &str,
+        variants: Vec<String>,
+    ) -> Result<()> {
+        either(
+            self.primary.set_variants(base_key, variants.clone()).await,
+            self.secondary.set_variants(base_key, variants).await,
+        )
+    }
+
+    async fn last_accessed(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<SystemTime>> {
+        match self.primary.last_accessed(cache_key).await {
+            Ok(accessed) => Ok(accessed),
+            Err(_) => self.secondary.last_accessed(cache_key).await,
+        }
+    }
+
+    async fn touch(&self, cache_key: &str, new_ttl: Duration) -> Result<bool> {
+        preferred(
+            self.primary.touch(cache_key, new_ttl).await,
+            self.secondary.touch(cache_key, new_ttl).await,
+        )
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        either(
+            self.primary.delete(cache_key).await,
+            self.secondary.delete(cache_key).await,
+        )
+    }
+
+    async fn evict_expired(&self, now: SystemTime) -> Result<()> {
+        either(
+            self.primary.evict_expired(now).await,
+            self.secondary.evict_expired(now).await,
+        )
+    }
+
+    fn detach(&self) -> Option<Box<dyn CacheManager>> {
+        Some(Box::new(FallbackManager::new(
+            self.primary.detach()?,
+            self.secondary.detach()?,
+        )))
+    }
+}
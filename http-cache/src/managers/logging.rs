@@ -0,0 +1,264 @@
+use crate::{BoxError, CacheManager, HttpResponse, RequestSnapshot, Result};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use http_cache_semantics::CachePolicy;
+
+/// A cache operation recorded by a [`LoggingManager`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheOperation {
+    /// A record was read
+    Get,
+    /// A record was stored
+    Put,
+    /// A record was removed
+    Delete,
+}
+
+/// The outcome of a cache operation recorded by a [`LoggingManager`].
+#[derive(Debug)]
+pub enum AccessOutcome<'a> {
+    /// A record was found by a [`CacheOperation::Get`]
+    Hit,
+    /// No record was found by a [`CacheOperation::Get`]
+    Miss,
+    /// A [`CacheOperation::Put`] or [`CacheOperation::Delete`] succeeded
+    Done,
+    /// The operation failed
+    Failed(&'a BoxError),
+}
+
+/// A closure receiving each operation, cache key and outcome recorded by a [`LoggingManager`].
+pub type AccessLogger =
+    Arc<dyn Fn(CacheOperation, &str, AccessOutcome<'_>) + Send + Sync>;
+
+/// Wraps any [`CacheManager`], reporting every get, put and delete to a logger.
+///
+/// Operations are passed through to the wrapped manager unchanged and reported once
+/// they complete, along with their cache key and outcome. Other operations are passed
+/// through without being reported.
+///
+/// ```
+/// # use http_cache::{CACacheManager, LoggingManager};
+/// let manager = LoggingManager::new(CACacheManager::default(), |op, key, outcome| {
+///     eprintln!("{op:?} {key}: {outcome:?}");
+/// });
+/// ```
+#[derive(Clone)]
+pub struct LoggingManager<M> {
+    /// The wrapped manager that stores the records
+    pub manager: M,
+    logger: AccessLogger,
+}
+
+impl<M: fmt::Debug> fmt::Debug for LoggingManager<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LoggingManager")
+            .field("manager", &self.manager)
+            .field("logger", &"Fn(CacheOperation, &str, AccessOutcome)")
+            .finish()
+    }
+}
+
+impl<M: CacheManager> LoggingManager<M> {
+    /// Create a new manager wrapping `manager`, reporting operations to `logger`.
+    pub fn new(
+        manager: M,
+        logger: impl Fn(CacheOperation, &str, AccessOutcome<'_>)
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Self { manager, logger: Arc::new(logger) }
+    }
+
+    fn log<T>(
+        &self,
+        operation: CacheOperation,
+        cache_key: &str,
+        result: &Result<T>,
+        outcome: impl FnOnce(&T) -> AccessOutcome<'static>,
+    ) {
+        let outcome = match result {
+            Ok(value) => outcome(value),
+            Err(e) => AccessOutcome::Failed(e),
+        };
+        (self.logger)(operation, cache_key, outcome);
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: CacheManager> CacheManager for LoggingManager<M> {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let result = self.manager.get(cache_key).await;
+        self.log(CacheOperation::Get, cache_key, &result, |record| {
+            if record.is_some() {
+                AccessOutcome::Hit
+            } else {
+                AccessOutcome::Miss
+            }
+        });
+        result
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let result = self.manager.put(cache_key.clone(), res, policy).await;
+        self.log(CacheOperation::Put, &cache_key, &result, |_| {
+            AccessOutcome::Done
+        });
+        result
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        let result = self.manager.delete(cache_key).await;
+        self.log(CacheOperation::Delete, cache_key, &result, |_| {
+            AccessOutcome::Done
+        });
+        result
+    }
+
+    async fn peek(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        self.manager.peek(cache_key).await
+    }
+
+    async fn peek_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<
+        Option<(
+            HttpResponse,
+            CachePolicy,
+            Option<RequestSnapshot>,
+            HashMap<String, String>,
+        )>,
+    > {
+        self.manager.peek_with_metadata(cache_key).await
+    }
+
+    async fn touch(&self, cache_key: &str, new_ttl: Duration) -> Result<bool> {
+        self.manager.touch(cache_key, new_ttl).await
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        self.manager.keys().await
+    }
+
+    async fn variants(&self, base_key: &str) -> Result<Vec<String>> {
+        self.manager.variants(base_key).await
+    }
+
+    async fn set_variants(
+        &self,
+        base_key: &str,
+        variants: Vec<String>,
+    ) -> Result<()> {
+        self.manager.set_variants(base_key, variants).await
+    }
+
+    async fn evict_expired(&self, now: SystemTime) -> Result<()> {
+        self.manager.evict_expired(now).await
+    }
+
+    async fn last_accessed(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<SystemTime>> {
+        self.manager.last_accessed(cache_key).await
+    }
+
+    async fn put_with_request(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+        request: RequestSnapshot,
+    ) -> Result<HttpResponse> {
+        let result = self
+            .manager
+            .put_with_request(cache_key.clone(), res, policy, request)
+            .await;
+        self.log(CacheOperation::Put, &cache_key, &result, |_| {
+            AccessOutcome::Done
+        });
+        result
+    }
+
+    async fn get_with_request(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, Option<RequestSnapshot>)>>
+    {
+        let result = self.manager.get_with_request(cache_key).await;
+        self.log(CacheOperation::Get, cache_key, &result, |record| {
+            if record.is_some() {
+                AccessOutcome::Hit
+            } else {
+                AccessOutcome::Miss
+            }
+        });
+        result
+    }
+
+    async fn put_with_metadata(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+        request: Option<RequestSnapshot>,
+        metadata: HashMap<String, String>,
+    ) -> Result<HttpResponse> {
+        let result = self
+            .manager
+            .put_with_metadata(
+                cache_key.clone(),
+                res,
+                policy,
+                request,
+                metadata,
+            )
+            .await;
+        self.log(CacheOperation::Put, &cache_key, &result, |_| {
+            AccessOutcome::Done
+        });
+        result
+    }
+
+    async fn get_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, HashMap<String, String>)>>
+    {
+        let result = self.manager.get_with_metadata(cache_key).await;
+        self.log(CacheOperation::Get, cache_key, &result, |record| {
+            if record.is_some() {
+                AccessOutcome::Hit
+            } else {
+                AccessOutcome::Miss
+            }
+        });
+        result
+    }
+
+    fn detach(&self) -> Option<Box<dyn CacheManager>> {
+        Some(Box::new(LoggingManager {
+            manager: self.manager.detach()?,
+            logger: self.logger.clone(),
+        }))
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{CacheManager, HttpResponse, RequestSnapshot, Result, SpawnFn};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use futures::future::BoxFuture;
+use http_cache_semantics::CachePolicy;
+
+/// Mirrors the writes of a [`CacheManager`] to a second one in the background, e.g.
+/// to feed a read replica or a shadow cache while validating a migration.
+///
+/// Reads, writes and deletes go to the `primary` manager, which alone decides the
+/// outcome of each operation. Once a write or delete succeeds, it is repeated on the
+/// `secondary` manager in a task started with the `spawn` hook, so the secondary
+/// never slows down the primary. Failures of the secondary are ignored, wrap it in a
+/// [`LoggingManager`](crate::LoggingManager) to report them.
+///
+/// The response body is buffered in memory when storing, so both managers receive a
+/// copy.
+#[derive(Clone)]
+pub struct MirrorManager<P, S> {
+    /// The manager that serves every operation
+    pub primary: P,
+    /// The manager that receives a copy of the writes
+    pub secondary: Arc<S>,
+    spawn: SpawnFn,
+}
+
+impl<P: fmt::Debug, S: fmt::Debug> fmt::Debug for MirrorManager<P, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MirrorManager")
+            .field("primary", &self.primary)
+            .field("secondary", &self.secondary)
+            .field("spawn", &"Fn(BoxFuture<'static, ()>)")
+            .finish()
+    }
+}
+
+impl<P: CacheManager, S: CacheManager> MirrorManager<P, S> {
+    /// Create a new manager mirroring the writes to `primary` to `secondary`, in tasks
+    /// started with `spawn`.
+    pub fn new(primary: P, secondary: S, spawn: SpawnFn) -> Self {
+        Self { primary, secondary: Arc::new(secondary), spawn }
+    }
+
+    // Repeats an operation on the secondary manager without waiting for it
+    fn mirror<T: 'static>(
+        &self,
+        operation: impl FnOnce(Arc<S>) -> BoxFuture<'static, Result<T>>,
+    ) {
+        let operation = operation(self.secondary.clone());
+        (self.spawn)(Box::pin(async move {
+            operation.await.ok();
+        }));
+    }
+
+    // Buffers the body, so that the secondary manager gets its own copy
+    async fn split(
+        response: HttpResponse,
+    ) -> Result<(HttpResponse, HttpResponse)> {
+        let (parts, body) = response.into_parts();
+        let body =
+            body.bytes_with_hint(parts.content_length().unwrap_or(0)).await?;
+        Ok((
+            HttpResponse::from_parts(parts.clone(), body.clone().into()),
+            HttpResponse::from_parts(parts, body.into()),
+        ))
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: CacheManager, S: CacheManager> CacheManager for MirrorManager<P, S> {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        self.primary.get(cache_key).await
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let (response, copy) = Self::split(response).await?;
+        let res = self
+            .primary
+            .put(cache_key.clone(), response, policy.clone())
+            .await?;
+        self.mirror(move |secondary| {
+            Box::pin(
+                async move { secondary.put(cache_key, copy, policy).await },
+            )
+        });
+        Ok(res)
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.primary.delete(cache_key).await?;
+        let cache_key = cache_key.to_string();
+        self.mirror(move |secondary| {
+            Box::pin(async move { secondary.delete(&cache_key).await })
+        });
+        Ok(())
+    }
+
+    async fn touch(&self, cache_key: &str, new_ttl: Duration) -> Result<bool> {
+        self.primary.touch(cache_key, new_ttl).await
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        self.primary.keys().await
+    }
+
+    async fn variants(&self, base_key: &str) -> Result<Vec<String>> {
+        self.primary.variants(base_key).await
+    }
+
+    async fn set_variants(
+        &self,
+        base_key: &str,
+        variants: Vec<String>,
+    ) -> Result<()> {
+        self.primary.set_variants(base_key, variants).await
+    }
+
+    async fn evict_expired(&self, now: SystemTime) -> Result<()> {
+        self.primary.evict_expired(now).await
+    }
+
+    async fn last_accessed(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<SystemTime>> {
+        self.primary.last_accessed(cache_key).await
+    }
+
+    async fn peek(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        self.primary.peek(cache_key).await
+    }
+
+    async fn peek_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<
+        Option<(
+            HttpResponse,
+            CachePolicy,
+            Option<RequestSnapshot>,
+            HashMap<String, String>,
+        )>,
+    > {
+        self.primary.peek_with_metadata(cache_key).await
+    }
+
+    async fn put_with_request(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+        request: RequestSnapshot,
+    ) -> Result<HttpResponse> {
+        let (response, copy) = Self::split(response).await?;
+        let res = self
+            .primary
+            .put_with_request(
+                cache_key.clone(),
+                response,
+                policy.clone(),
+                request.clone(),
+            )
+            .await?;
+        self.mirror(move |secondary| {
+            Box::pin(async move {
+                secondary
+                    .put_with_request(cache_key, copy, policy, request)
+                    .await
+            })
+        });
+        Ok(res)
+    }
+
+    async fn get_with_request(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, Option<RequestSnapshot>)>>
+    {
+        self.primary.get_with_request(cache_key).await
+    }
+
+    async fn put_with_metadata(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+        request: Option<RequestSnapshot>,
+        metadata: HashMap<String, String>,
+    ) -> Result<HttpResponse> {
+        let (response, copy) = Self::split(response).await?;
+        let res = self
+            .primary
+            .put_with_metadata(
+                cache_key.clone(),
+                response,
+                policy.clone(),
+                request.clone(),
+                metadata.clone(),
+            )
+            .await?;
+        self.mirror(move |secondary| {
+            Box::pin(async move {
+                secondary
+                    .put_with_metadata(
+                        cache_key, copy, policy, request, metadata,
+                    )
+                    .await
+            })
+        });
+        Ok(res)
+    }
+
+    async fn get_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, HashMap<String, String>)>>
+    {
+        self.primary.get_with_metadata(cache_key).await
+    }
+
+    fn detach(&self) -> Option<Box<dyn CacheManager>> {
+        Some(Box::new(MirrorManager {
+            primary: self.primary.detach()?,
+            secondary: self.secondary.clone(),
+            spawn: self.spawn.clone(),
+        }))
+    }
+}
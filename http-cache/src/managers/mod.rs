@@ -3,3 +3,157 @@ pub mod cacache;
 
 #[cfg(feature = "manager-moka")]
 pub mod moka;
+
+#[cfg(feature = "encryption")]
+pub mod encrypted;
+
+#[cfg(feature = "manager-blob")]
+pub mod blob;
+
+#[cfg(feature = "archive")]
+pub mod archive;
+
+pub mod fallback;
+pub mod logging;
+pub mod mirror;
+
+use crate::{CacheManager, HttpResponse, RequestSnapshot, Result};
+
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use http_cache_semantics::CachePolicy;
+
+#[async_trait::async_trait]
+impl<M: CacheManager + ?Sized> CacheManager for Box<M> {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        (**self).get(cache_key).await
+    }
+
+    async fn put_with_metadata(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+        request: Option<RequestSnapshot>,
+        metadata: HashMap<String, String>,
+    ) -> Result<HttpResponse> {
+        (**self)
+            .put_with_metadata(cache_key, res, policy, request, metadata)
+            .await
+    }
+
+    async fn get_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, HashMap<String, String>)>>
+    {
+        (**self).get_with_metadata(cache_key).await
+    }
+
+    async fn peek(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        (**self).peek(cache_key).await
+    }
+
+    async fn peek_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<
+        Option<(
+            HttpResponse,
+            CachePolicy,
+            Option<RequestSnapshot>,
+            HashMap<String, String>,
+        )>,
+    > {
+        (**self).peek_with_metadata(cache_key).await
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        (**self).put(cache_key, res, policy).await
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        (**self).delete(cache_key).await
+    }
+
+    async fn touch(&self, cache_key: &str, new_ttl: Duration) -> Result<bool> {
+        (**self).touch(cache_key, new_ttl).await
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        (**self).keys().await
+    }
+
+    async fn variants(&self, base_key: &str) -> Result<Vec<String>> {
+        (**self).variants(base_key).await
+    }
+
+    async fn set_variants(
+        &self,
+        base_key: &str,
+        variants: Vec<String>,
+    ) -> Result<()> {
+        (**self).set_variants(base_key, variants).await
+    }
+
+    async fn evict_expired(&self, now: SystemTime) -> Result<()> {
+        (**self).evict_expired(now).await
+    }
+
+    async fn last_accessed(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<SystemTime>> {
+        (**self).last_accessed(cache_key).await
+    }
+
+    async fn put_with_request(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+        request: RequestSnapshot,
+    ) -> Result<HttpResponse> {
+        (**self).put_with_request(cache_key, res, policy, request).await
+    }
+
+    async fn get_with_request(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, Option<RequestSnapshot>)>>
+    {
+        (**self).get_with_request(cache_key).await
+    }
+
+    async fn get_many(
+        &self,
+        cache_keys: &[String],
+    ) -> Result<Vec<Option<(HttpResponse, CachePolicy)>>> {
+        (**self).get_many(cache_keys).await
+    }
+
+    async fn put_many(
+        &self,
+        entries: Vec<(String, HttpResponse, CachePolicy)>,
+    ) -> Result<Vec<HttpResponse>> {
+        (**self).put_many(entries).await
+    }
+
+    fn detach(&self) -> Option<Box<dyn CacheManager>> {
+        (**self).detach()
+    }
+}
@@ -1,6 +1,6 @@
-use crate::{CacheManager, HttpResponse, Result};
+use crate::{CacheManager, HttpResponse, RequestSnapshot, Result};
 
-use std::{fmt, sync::Arc};
+use std::{collections::HashMap, fmt, sync::Arc};
 
 use http_cache_semantics::CachePolicy;
 use moka::future::Cache;
@@ -31,6 +31,15 @@ impl Default for MokaManager {
 struct Store {
     response: HttpResponse,
     policy: CachePolicy,
+    metadata: HashMap<String, String>,
+}
+
+impl Store {
+    // The stored response, without the markers stored by the cache in its headers
+    fn response(self) -> (HttpResponse, CachePolicy, HashMap<String, String>) {
+        let (parts, body) = self.response.into_parts();
+        (HttpResponse::from_stored(parts, body), self.policy, self.metadata)
+    }
 }
 
 impl MokaManager {
@@ -56,7 +65,48 @@ impl CacheManager for MokaManager {
             Some(d) => bincode::deserialize(&d)?,
             None => return Ok(None),
         };
-        Ok(Some((store.response, store.policy)))
+        let (response, policy, _) = store.response();
+        Ok(Some((response, policy)))
+    }
+
+    /// Scans the entries of the `moka` cache, which unlike a lookup doesn't update
+    /// their popularity or reset their idle timers, so it takes time proportional to
+    /// the number of entries.
+    async fn peek(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let Some((_, d)) =
+            self.cache.iter().find(|(key, _)| key.as_str() == cache_key)
+        else {
+            return Ok(None);
+        };
+        let store: Store = bincode::deserialize(&d)?;
+        let (response, policy, _) = store.response();
+        Ok(Some((response, policy)))
+    }
+
+    /// Scans the entries of the `moka` cache like [`CacheManager::peek`]. Request
+    /// snapshots aren't stored.
+    async fn peek_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<
+        Option<(
+            HttpResponse,
+            CachePolicy,
+            Option<RequestSnapshot>,
+            HashMap<String, String>,
+        )>,
+    > {
+        let Some((_, d)) =
+            self.cache.iter().find(|(key, _)| key.as_str() == cache_key)
+        else {
+            return Ok(None);
+        };
+        let store: Store = bincode::deserialize(&d)?;
+        let (response, policy, metadata) = store.response();
+        Ok(Some((response, policy, None, metadata)))
     }
 
     async fn put(
@@ -65,16 +115,50 @@ impl CacheManager for MokaManager {
         response: HttpResponse,
         policy: CachePolicy,
     ) -> Result<HttpResponse> {
-        let data = Store { response: response.clone(), policy };
+        self.put_with_metadata(
+            cache_key,
+            response,
+            policy,
+            None,
+            HashMap::new(),
+        )
+        .await
+    }
+
+    async fn put_with_metadata(
+        &self,
+        cache_key: String,
+        response: HttpResponse,
+        policy: CachePolicy,
+        _request: Option<RequestSnapshot>,
+        metadata: HashMap<String, String>,
+    ) -> Result<HttpResponse> {
+        let data = Store { response: response.clone(), policy, metadata };
         let bytes = bincode::serialize(&data)?;
         self.cache.insert(cache_key, Arc::new(bytes)).await;
         self.cache.run_pending_tasks().await;
         Ok(response)
     }
 
+    async fn get_with_metadata(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy, HashMap<String, String>)>>
+    {
+        let store: Store = match self.cache.get(cache_key).await {
+            Some(d) => bincode::deserialize(&d)?,
+            None => return Ok(None),
+        };
+        Ok(Some(store.response()))
+    }
+
     async fn delete(&self, cache_key: &str) -> Result<()> {
         self.cache.invalidate(cache_key).await;
         self.cache.run_pending_tasks().await;
         Ok(())
     }
+
+    fn detach(&self) -> Option<Box<dyn CacheManager>> {
+        Some(Box::new(self.clone()))
+    }
 }
@@ -0,0 +1,275 @@
+use crate::{
+    Body, BodyKeyComponent, CacheMode, HttpResponse, HttpVersion, Middleware,
+    Parts, Result,
+};
+
+use std::{collections::HashMap, fmt, future::Future, time::SystemTime};
+
+use bytes::Bytes;
+use http::request;
+use http_cache_semantics::{CacheOptions, CachePolicy};
+use url::Url;
+
+/// Implements [`Middleware`] over a request and a closure that fetches it, so that any
+/// client able to send an [`http::Request`] can be used with the cache without writing
+/// a full middleware.
+///
+/// The closure receives the request parts, including any headers added by the cache
+/// such as validators, and returns the [`http::Response`] from the origin. When the
+/// client followed redirects, the closure should insert the final [`Url`] into the
+/// extensions of the response, so that it is recorded as the url of the response.
+///
+/// ```no_run
+/// # use http_cache::{CACacheManager, FnMiddleware, HttpCache, HttpCacheOptions, CacheMode};
+/// # async fn run() -> http_cache::Result<()> {
+/// let cache = HttpCache {
+///     mode: CacheMode::Default,
+///     manager: CACacheManager::default(),
+///     options: HttpCacheOptions::default(),
+/// };
+/// let (parts, ()) =
+///     http::Request::get("https://example.com/").body(())?.into_parts();
+/// let res = cache
+///     .run(FnMiddleware::new(parts, |parts: http::request::Parts| async move {
+///         // send the request with any client here
+///         Ok(http::Response::new(format!("fetched {}", parts.uri).into_bytes()))
+///     }))
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FnMiddleware<F> {
+    parts: request::Parts,
+    fetch: F,
+}
+
+impl<F> fmt::Debug for FnMiddleware<F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FnMiddleware")
+            .field("parts", &self.parts)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F> FnMiddleware<F> {
+    /// Create a new middleware for the request described by `parts`, fetched by `fetch`.
+    pub fn new(parts: request::Parts, fetch: F) -> Self {
+        Self { parts, fetch }
+    }
+}
+
+#[async_trait::async_trait]
+impl<F, Fut, B> Middleware for FnMiddleware<F>
+where
+    F: FnMut(request::Parts) -> Fut + Send,
+    Fut: Future<Output = Result<http::Response<B>>> + Send,
+    B: Into<Body>,
+{
+    fn is_method_get_head(&self) -> bool {
+        self.parts.method == http::Method::GET
+            || self.parts.method == http::Method::HEAD
+    }
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+        Ok(CachePolicy::new(&self.parts, &response.parts()?))
+    }
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        Ok(CachePolicy::new_options(
+            &self.parts,
+            &response.parts()?,
+            SystemTime::now(),
+            options,
+        ))
+    }
+    fn update_headers(&mut self, parts: &request::Parts) -> Result<()> {
+        for header in parts.headers.iter() {
+            self.parts.headers.insert(header.0.clone(), header.1.clone());
+        }
+        Ok(())
+    }
+    fn force_no_cache(&mut self) -> Result<()> {
+        self.parts.headers.insert(
+            http::header::CACHE_CONTROL,
+            http::HeaderValue::from_static("no-cache"),
+        );
+        Ok(())
+    }
+    fn set_method(&mut self, method: http::Method) -> Result<()> {
+        self.parts.method = method;
+        Ok(())
+    }
+    fn parts(&self) -> Result<request::Parts> {
+        Ok(self.parts.clone())
+    }
+    fn url(&self) -> Result<Url> {
+        Ok(Url::parse(&self.parts.uri.to_string())?)
+    }
+    fn method(&self) -> Result<String> {
+        Ok(self.parts.method.to_string())
+    }
+    async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+        let (mut parts, body) =
+            (self.fetch)(self.parts.clone()).await?.into_parts();
+        let url = match parts.extensions.remove::<Url>() {
+            Some(url) => url,
+            None => self.url()?,
+        };
+        let mut headers = HashMap::new();
+        for header in &parts.headers {
+            headers.insert(
+                header.0.as_str().to_owned(),
+                header.1.to_str()?.to_owned(),
+            );
+        }
+        Ok(HttpResponse::from_parts(
+            Parts {
+                headers,
+                status: parts.status.into(),
+                url,
+                version: parts.version.try_into()?,
+            },
+            body.into(),
+        ))
+    }
+}
+
+/// A [`Middleware`] chosen at runtime, for when the concrete type isn't known
+/// statically or middlewares of different types need to be stored together.
+///
+/// Any boxed middleware implements [`Middleware`] itself, so it can be passed to
+/// [`HttpCache::run`](crate::HttpCache::run) like any other. The middleware must be
+/// `Sync` so that the futures running it are `Send`.
+pub type BoxMiddleware = Box<dyn Middleware + Sync>;
+
+#[async_trait::async_trait]
+impl<M: Middleware + ?Sized> Middleware for Box<M> {
+    fn overridden_cache_mode(&self) -> Option<CacheMode> {
+        (**self).overridden_cache_mode()
+    }
+    fn is_method_get_head(&self) -> bool {
+        (**self).is_method_get_head()
+    }
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+        (**self).policy(response)
+    }
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        (**self).policy_with_options(response, options)
+    }
+    fn update_headers(&mut self, parts: &request::Parts) -> Result<()> {
+        (**self).update_headers(parts)
+    }
+    fn force_no_cache(&mut self) -> Result<()> {
+        (**self).force_no_cache()
+    }
+    fn set_method(&mut self, method: http::Method) -> Result<()> {
+        (**self).set_method(method)
+    }
+    fn parts(&self) -> Result<request::Parts> {
+        (**self).parts()
+    }
+    fn url(&self) -> Result<Url> {
+        (**self).url()
+    }
+    fn method(&self) -> Result<String> {
+        (**self).method()
+    }
+    fn version(&self) -> Option<HttpVersion> {
+        (**self).version()
+    }
+    fn request_body(&self) -> Result<Option<Bytes>> {
+        (**self).request_body()
+    }
+    fn detach(&self) -> Option<BoxMiddleware> {
+        (**self).detach()
+    }
+    fn received_at(&self) -> Option<SystemTime> {
+        (**self).received_at()
+    }
+    async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+        (**self).remote_fetch().await
+    }
+}
+
+// Carries the component of the cache key derived from the request body in the
+// extensions of the request parts, see HttpCacheOptions::body_key. `None` leaves
+// the request as is, while `Some(None)` makes it bypass the cache.
+pub(crate) struct BodyKeyed<M> {
+    inner: M,
+    component: Option<Option<String>>,
+}
+
+impl<M> BodyKeyed<M> {
+    pub(crate) fn new(inner: M, component: Option<Option<String>>) -> Self {
+        Self { inner, component }
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: Middleware> Middleware for BodyKeyed<M> {
+    fn overridden_cache_mode(&self) -> Option<CacheMode> {
+        match self.component {
+            Some(None) => Some(CacheMode::NoStore),
+            _ => self.inner.overridden_cache_mode(),
+        }
+    }
+    fn is_method_get_head(&self) -> bool {
+        self.inner.is_method_get_head()
+    }
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+        self.inner.policy(response)
+    }
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        self.inner.policy_with_options(response, options)
+    }
+    fn update_headers(&mut self, parts: &request::Parts) -> Result<()> {
+        self.inner.update_headers(parts)
+    }
+    fn force_no_cache(&mut self) -> Result<()> {
+        self.inner.force_no_cache()
+    }
+    fn set_method(&mut self, method: http::Method) -> Result<()> {
+        self.inner.set_method(method)
+    }
+    fn parts(&self) -> Result<request::Parts> {
+        let mut parts = self.inner.parts()?;
+        if let Some(Some(component)) = &self.component {
+            parts.extensions.insert(BodyKeyComponent(component.clone()));
+        }
+        Ok(parts)
+    }
+    fn url(&self) -> Result<Url> {
+        self.inner.url()
+    }
+    fn method(&self) -> Result<String> {
+        self.inner.method()
+    }
+    fn version(&self) -> Option<HttpVersion> {
+        self.inner.version()
+    }
+    fn request_body(&self) -> Result<Option<Bytes>> {
+        self.inner.request_body()
+    }
+    fn detach(&self) -> Option<BoxMiddleware> {
+        Some(Box::new(BodyKeyed {
+            inner: self.inner.detach()?,
+            component: self.component.clone(),
+        }))
+    }
+    fn received_at(&self) -> Option<SystemTime> {
+        self.inner.received_at()
+    }
+    async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+        self.inner.remote_fetch().await
+    }
+}
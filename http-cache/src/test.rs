@@ -1,15 +1,234 @@
 use crate::{
-    error, CacheMode, HitOrMiss, HttpCacheOptions, HttpResponse, HttpVersion,
-    Parts, Result,
+    error, CacheConfig, CacheManager, CacheMode, CacheOptions, HitOrMiss,
+    HttpCacheOptions, HttpResponse, HttpVersion, KeySpec, Markers, Parts,
+    RequestSnapshot, Result,
 };
-use http::{header::CACHE_CONTROL, StatusCode};
-use http_cache_semantics::CacheOptions;
+#[cfg(any(feature = "manager-cacache", feature = "manager-blob"))]
+use crate::{BoxMiddleware, Middleware};
+use http::{header::CACHE_CONTROL, request, StatusCode};
+use http_cache_semantics::CachePolicy;
 use url::Url;
 
-use std::{collections::HashMap, str::FromStr};
+#[cfg(any(feature = "manager-cacache", feature = "manager-blob"))]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
 
 const GET: &str = "GET";
 const TEST_BODY: &[u8] = b"test";
+#[cfg(any(feature = "manager-cacache", feature = "manager-blob"))]
+const CACHEABLE_PUBLIC: &str = "max-age=86400, public";
+
+#[cfg(any(feature = "manager-cacache", feature = "manager-blob"))]
+type Responder =
+    Arc<Mutex<dyn FnMut(&request::Parts) -> Result<HttpResponse> + Send>>;
+
+/// Implements [`Middleware`] over a fixed request and a closure producing the upstream response
+#[cfg(any(feature = "manager-cacache", feature = "manager-blob"))]
+struct TestMiddleware {
+    parts: request::Parts,
+    respond: Responder,
+    fetches: Arc<AtomicUsize>,
+    received_at: Option<SystemTime>,
+    body: Option<bytes::Bytes>,
+}
+
+#[cfg(any(feature = "manager-cacache", feature = "manager-blob"))]
+impl TestMiddleware {
+    fn new(
+        req: http::Request<()>,
+        fetches: Arc<AtomicUsize>,
+        respond: impl FnMut(&request::Parts) -> Result<HttpResponse>
+            + Send
+            + 'static,
+    ) -> Self {
+        Self {
+            parts: req.into_parts().0,
+            respond: Arc::new(Mutex::new(respond)),
+            fetches,
+            received_at: None,
+            body: None,
+        }
+    }
+}
+
+#[cfg(any(feature = "manager-cacache", feature = "manager-blob"))]
+#[async_trait::async_trait]
+impl Middleware for TestMiddleware {
+    fn is_method_get_head(&self) -> bool {
+        self.parts.method == http::Method::GET
+            || self.parts.method == http::Method::HEAD
+    }
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+        Ok(CachePolicy::new(&self.parts, &response.parts()?))
+    }
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        Ok(CachePolicy::new_options(
+            &self.parts,
+            &response.parts()?,
+            SystemTime::now(),
+            options,
+        ))
+    }
+    fn update_headers(&mut self, parts: &request::Parts) -> Result<()> {
+        for header in parts.headers.iter() {
+            self.parts.headers.insert(header.0.clone(), header.1.clone());
+        }
+        Ok(())
+    }
+    fn force_no_cache(&mut self) -> Result<()> {
+        self.parts
+            .headers
+            .insert(CACHE_CONTROL, http::HeaderValue::from_static("no-cache"));
+        Ok(())
+    }
+    fn set_method(&mut self, method: http::Method) -> Result<()> {
+        self.parts.method = method;
+        Ok(())
+    }
+    fn parts(&self) -> Result<request::Parts> {
+        Ok(self.parts.clone())
+    }
+    fn url(&self) -> Result<Url> {
+        Ok(Url::parse(&self.parts.uri.to_string())?)
+    }
+    fn method(&self) -> Result<String> {
+        Ok(self.parts.method.to_string())
+    }
+    fn detach(&self) -> Option<BoxMiddleware> {
+        Some(Box::new(TestMiddleware {
+            parts: self.parts.clone(),
+            respond: self.respond.clone(),
+            fetches: self.fetches.clone(),
+            received_at: self.received_at,
+            body: self.body.clone(),
+        }))
+    }
+    fn request_body(&self) -> Result<Option<bytes::Bytes>> {
+        Ok(self.body.clone())
+    }
+    fn received_at(&self) -> Option<SystemTime> {
+        self.received_at
+    }
+    async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+        self.fetches.fetch_add(1, Ordering::SeqCst);
+        (self.respond.lock().unwrap())(&self.parts)
+    }
+}
+
+fn build_response(
+    url: &str,
+    status: u16,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<HttpResponse> {
+    Ok(HttpResponse::from_parts(
+        Parts {
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            status,
+            url: Url::parse(url)?,
+            version: HttpVersion::Http11,
+        },
+        body.to_vec().into(),
+    ))
+}
+
+/// Implements [`CacheManager`] over a map in memory, for exercising the manager
+/// operations without a backend
+#[derive(Debug, Default)]
+pub(crate) struct MemoryManager {
+    records: Mutex<HashMap<String, (Parts, bytes::Bytes, CachePolicy)>>,
+}
+
+#[async_trait::async_trait]
+impl CacheManager for MemoryManager {
+    async fn get(
+        &self,
+        cache_key: &str,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let records = self.records.lock().unwrap();
+        Ok(records.get(cache_key).map(|(parts, body, policy)| {
+            (
+                HttpResponse::from_parts(parts.clone(), body.clone().into()),
+                policy.clone(),
+            )
+        }))
+    }
+
+    async fn put(
+        &self,
+        cache_key: String,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let (parts, body) = res.into_parts();
+        let body = body.bytes().await?;
+        self.records
+            .lock()
+            .unwrap()
+            .insert(cache_key, (parts.clone(), body.clone(), policy));
+        Ok(HttpResponse::from_parts(parts, body.into()))
+    }
+
+    async fn delete(&self, cache_key: &str) -> Result<()> {
+        self.records.lock().unwrap().remove(cache_key);
+        Ok(())
+    }
+
+    async fn keys(&self) -> Result<Vec<String>> {
+        Ok(self.records.lock().unwrap().keys().cloned().collect())
+    }
+}
+
+/// Generates a cacheable response for `url` with a body of `size` bytes, along with
+/// its policy
+pub(crate) fn payload(url: &str, size: usize) -> (HttpResponse, CachePolicy) {
+    let body: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+    let res = build_response(
+        url,
+        200,
+        &[
+            ("cache-control", "max-age=86400, public"),
+            ("content-type", "application/octet-stream"),
+        ],
+        &body,
+    )
+    .unwrap();
+    let req = http::Request::get(url).body(()).unwrap().into_parts().0;
+    let policy = CachePolicy::new(&req, &res.parts().unwrap());
+    (res, policy)
+}
+
+#[test]
+fn memory_manager() -> Result<()> {
+    let manager = MemoryManager::default();
+    futures::executor::block_on(async {
+        for size in [0, 1024, 64 * 1024] {
+            let url = format!("http://example.com/{size}");
+            let key = format!("{GET}:{url}");
+            let (res, policy) = payload(&url, size);
+            manager.put(key.clone(), res, policy).await?;
+            let (res, policy) = manager.get(&key).await?.unwrap();
+            assert_eq!(res.body.bytes().await?.len(), size);
+            assert!(crate::is_fresh(&policy, SystemTime::now()));
+            assert_eq!(manager.keys().await?, [key.as_str()]);
+            manager.delete(&key).await?;
+            assert!(manager.get(&key).await?.is_none());
+        }
+        Ok(())
+    })
+}
 
 #[test]
 fn hit_miss() -> Result<()> {
@@ -29,6 +248,21 @@ fn cache_mode() -> Result<()> {
     let mode = CacheMode::Default;
     assert_eq!(mode.clone(), CacheMode::Default);
     assert_eq!(format!("{:?}", mode), "Default");
+    for (mode, name) in [
+        (CacheMode::Default, "default"),
+        (CacheMode::NoStore, "no-store"),
+        (CacheMode::Reload, "reload"),
+        (CacheMode::NoCache, "no-cache"),
+        (CacheMode::ForceCache, "force-cache"),
+        (CacheMode::OnlyIfCached, "only-if-cached"),
+        (CacheMode::OnlyIfCachedFresh, "only-if-cached-fresh"),
+        (CacheMode::IgnoreRules, "ignore-rules"),
+    ] {
+        assert_eq!(mode.to_string(), name);
+        assert_eq!(name.parse::<CacheMode>()?, mode);
+        assert_eq!(name.to_uppercase().parse::<CacheMode>()?, mode);
+    }
+    assert!("no_store".parse::<CacheMode>().is_err());
     Ok(())
 }
 
@@ -36,16 +270,16 @@ fn cache_mode() -> Result<()> {
 fn cache_options() -> Result<()> {
     // Testing the Debug, Default and Clone traits for the HttpCacheOptions struct
     let mut opts = HttpCacheOptions::default();
-    assert_eq!(format!("{:?}", opts.clone()), "HttpCacheOptions { cache_options: None, cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: true }");
+    assert_eq!(format!("{:?}", opts.clone()), "HttpCacheOptions { cache_options: None, cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_options_fn: \"Fn(&request::Parts) -> CacheOptions\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: true, store_request: false, policy_override: \"Fn(&HttpResponse) -> Option<CachePolicy>\", cacheable_url_matcher: \"Fn(&Url) -> bool\", default_response_version: Http11, spawn: \"Fn(BoxFuture<'static, ()>)\", sleep: \"Fn(Duration) -> BoxFuture<'static, ()>\", trust_304_body: false, key_version: 0, strict_cache_bust: false, miss_status: 504, stale_response_transform: \"Fn(&mut HttpResponse)\", revalidate_with_head: false, skip_cache_on_set_cookie: true, canonical_cache_method: GET, max_variants_per_url: None, heuristic_immutable_matcher: \"Fn(&Url) -> bool\", observer: \"dyn CacheObserver\", vary_matcher: \"Fn(&HeaderName, &str, &str) -> bool\", max_concurrent_fetches: None, key_format: KeyFormat { separator: \":\", explicit_authority: false }, clock: \"Fn() -> SystemTime\", warning_agent: None, cacheable_methods: [], stale_grace: None, instance_name: None, is_offline: \"Fn() -> bool\", prefer_expires: false, cache_info: false, clock_skew_tolerance: 0ns, max_stored_header_bytes: None, max_stored_header_count: None, drop_excess_headers: false, cache_status_headers_fn: None, identity_fn: \"Fn(&request::Parts) -> Option<String>\", store_content_location: false, cacheable_status_codes: [], accept_normalizer: \"Fn(&str) -> String\", metadata_fn: \"Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>\", fallback_fn: \"Fn(&request::Parts) -> Option<HttpResponse>\", min_cacheable_body_bytes: None, max_cacheable_body_bytes: None, revalidation_retries: 0, revalidation_backoff: 100ms, honor_retry_after: true, on_corrupt: Discard, stream_while_caching: false, content_type_in_key: true, normalize_trailing_slash: false, override_cache_control: None, body_key: \"Fn(&request::Parts, &[u8]) -> Option<String>\" }");
     opts.cache_options = Some(CacheOptions::default());
-    assert_eq!(format!("{:?}", opts.clone()), "HttpCacheOptions { cache_options: Some(CacheOptions { shared: true, cache_heuristic: 0.1, immutable_min_time_to_live: 86400s, ignore_cargo_cult: false }), cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: true }");
+    assert_eq!(format!("{:?}", opts.clone()), "HttpCacheOptions { cache_options: Some(CacheOptions { shared: true, cache_heuristic: 0.1, immutable_min_time_to_live: 86400s, ignore_cargo_cult: false }), cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_options_fn: \"Fn(&request::Parts) -> CacheOptions\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: true, store_request: false, policy_override: \"Fn(&HttpResponse) -> Option<CachePolicy>\", cacheable_url_matcher: \"Fn(&Url) -> bool\", default_response_version: Http11, spawn: \"Fn(BoxFuture<'static, ()>)\", sleep: \"Fn(Duration) -> BoxFuture<'static, ()>\", trust_304_body: false, key_version: 0, strict_cache_bust: false, miss_status: 504, stale_response_transform: \"Fn(&mut HttpResponse)\", revalidate_with_head: false, skip_cache_on_set_cookie: true, canonical_cache_method: GET, max_variants_per_url: None, heuristic_immutable_matcher: \"Fn(&Url) -> bool\", observer: \"dyn CacheObserver\", vary_matcher: \"Fn(&HeaderName, &str, &str) -> bool\", max_concurrent_fetches: None, key_format: KeyFormat { separator: \":\", explicit_authority: false }, clock: \"Fn() -> SystemTime\", warning_agent: None, cacheable_methods: [], stale_grace: None, instance_name: None, is_offline: \"Fn() -> bool\", prefer_expires: false, cache_info: false, clock_skew_tolerance: 0ns, max_stored_header_bytes: None, max_stored_header_count: None, drop_excess_headers: false, cache_status_headers_fn: None, identity_fn: \"Fn(&request::Parts) -> Option<String>\", store_content_location: false, cacheable_status_codes: [], accept_normalizer: \"Fn(&str) -> String\", metadata_fn: \"Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>\", fallback_fn: \"Fn(&request::Parts) -> Option<HttpResponse>\", min_cacheable_body_bytes: None, max_cacheable_body_bytes: None, revalidation_retries: 0, revalidation_backoff: 100ms, honor_retry_after: true, on_corrupt: Discard, stream_while_caching: false, content_type_in_key: true, normalize_trailing_slash: false, override_cache_control: None, body_key: \"Fn(&request::Parts, &[u8]) -> Option<String>\" }");
     opts.cache_options = None;
-    opts.cache_key = Some(std::sync::Arc::new(|req: &http::request::Parts| {
+    opts.cache_key = Some(Arc::new(|req: &request::Parts| {
         format!("{}:{}:{:?}:test", req.method, req.uri, req.version)
     }));
-    assert_eq!(format!("{:?}", opts), "HttpCacheOptions { cache_options: None, cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: true }");
+    assert_eq!(format!("{:?}", opts), "HttpCacheOptions { cache_options: None, cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_options_fn: \"Fn(&request::Parts) -> CacheOptions\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: true, store_request: false, policy_override: \"Fn(&HttpResponse) -> Option<CachePolicy>\", cacheable_url_matcher: \"Fn(&Url) -> bool\", default_response_version: Http11, spawn: \"Fn(BoxFuture<'static, ()>)\", sleep: \"Fn(Duration) -> BoxFuture<'static, ()>\", trust_304_body: false, key_version: 0, strict_cache_bust: false, miss_status: 504, stale_response_transform: \"Fn(&mut HttpResponse)\", revalidate_with_head: false, skip_cache_on_set_cookie: true, canonical_cache_method: GET, max_variants_per_url: None, heuristic_immutable_matcher: \"Fn(&Url) -> bool\", observer: \"dyn CacheObserver\", vary_matcher: \"Fn(&HeaderName, &str, &str) -> bool\", max_concurrent_fetches: None, key_format: KeyFormat { separator: \":\", explicit_authority: false }, clock: \"Fn() -> SystemTime\", warning_agent: None, cacheable_methods: [], stale_grace: None, instance_name: None, is_offline: \"Fn() -> bool\", prefer_expires: false, cache_info: false, clock_skew_tolerance: 0ns, max_stored_header_bytes: None, max_stored_header_count: None, drop_excess_headers: false, cache_status_headers_fn: None, identity_fn: \"Fn(&request::Parts) -> Option<String>\", store_content_location: false, cacheable_status_codes: [], accept_normalizer: \"Fn(&str) -> String\", metadata_fn: \"Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>\", fallback_fn: \"Fn(&request::Parts) -> Option<HttpResponse>\", min_cacheable_body_bytes: None, max_cacheable_body_bytes: None, revalidation_retries: 0, revalidation_backoff: 100ms, honor_retry_after: true, on_corrupt: Discard, stream_while_caching: false, content_type_in_key: true, normalize_trailing_slash: false, override_cache_control: None, body_key: \"Fn(&request::Parts, &[u8]) -> Option<String>\" }");
     opts.cache_status_headers = false;
-    assert_eq!(format!("{:?}", opts), "HttpCacheOptions { cache_options: None, cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: false }");
+    assert_eq!(format!("{:?}", opts), "HttpCacheOptions { cache_options: None, cache_key: \"Fn(&request::Parts) -> String\", cache_mode_fn: \"Fn(&request::Parts) -> CacheMode\", cache_options_fn: \"Fn(&request::Parts) -> CacheOptions\", cache_bust: \"Fn(&request::Parts) -> Vec<String>\", cache_status_headers: false, store_request: false, policy_override: \"Fn(&HttpResponse) -> Option<CachePolicy>\", cacheable_url_matcher: \"Fn(&Url) -> bool\", default_response_version: Http11, spawn: \"Fn(BoxFuture<'static, ()>)\", sleep: \"Fn(Duration) -> BoxFuture<'static, ()>\", trust_304_body: false, key_version: 0, strict_cache_bust: false, miss_status: 504, stale_response_transform: \"Fn(&mut HttpResponse)\", revalidate_with_head: false, skip_cache_on_set_cookie: true, canonical_cache_method: GET, max_variants_per_url: None, heuristic_immutable_matcher: \"Fn(&Url) -> bool\", observer: \"dyn CacheObserver\", vary_matcher: \"Fn(&HeaderName, &str, &str) -> bool\", max_concurrent_fetches: None, key_format: KeyFormat { separator: \":\", explicit_authority: false }, clock: \"Fn() -> SystemTime\", warning_agent: None, cacheable_methods: [], stale_grace: None, instance_name: None, is_offline: \"Fn() -> bool\", prefer_expires: false, cache_info: false, clock_skew_tolerance: 0ns, max_stored_header_bytes: None, max_stored_header_count: None, drop_excess_headers: false, cache_status_headers_fn: None, identity_fn: \"Fn(&request::Parts) -> Option<String>\", store_content_location: false, cacheable_status_codes: [], accept_normalizer: \"Fn(&str) -> String\", metadata_fn: \"Fn(&request::Parts, &HttpResponse) -> HashMap<String, String>\", fallback_fn: \"Fn(&request::Parts) -> Option<HttpResponse>\", min_cacheable_body_bytes: None, max_cacheable_body_bytes: None, revalidation_retries: 0, revalidation_backoff: 100ms, honor_retry_after: true, on_corrupt: Discard, stream_while_caching: false, content_type_in_key: true, normalize_trailing_slash: false, override_cache_control: None, body_key: \"Fn(&request::Parts, &[u8]) -> Option<String>\" }");
     Ok(())
 }
 
@@ -59,6 +293,56 @@ fn test_errors() -> Result<()> {
     let bh = error::BadHeader::default();
     assert_eq!(format!("{:?}", bh.clone()), "BadHeader",);
     assert_eq!(bh.to_string(), "Error parsing header value".to_string(),);
+    let un = error::Unsupported::default();
+    assert_eq!(format!("{:?}", un.clone()), "Unsupported",);
+    assert_eq!(
+        un.to_string(),
+        "The operation is not supported by this cache manager".to_string(),
+    );
+    let mh = error::MissingRuntimeHook::default();
+    assert_eq!(format!("{:?}", mh.clone()), "MissingRuntimeHook",);
+    assert_eq!(
+        mh.to_string(),
+        "The spawn and sleep runtime hooks must be configured".to_string(),
+    );
+    let bm = error::BadCacheMode::default();
+    assert_eq!(format!("{:?}", bm.clone()), "BadCacheMode",);
+    assert_eq!(bm.to_string(), "Unknown cache mode".to_string(),);
+    Ok(())
+}
+
+#[test]
+fn cache_config() -> Result<()> {
+    let vars = HashMap::from([
+        ("HTTP_CACHE_MODE", "Force-Cache"),
+        ("HTTP_CACHE_CACHE_STATUS_HEADERS", "false"),
+        ("HTTP_CACHE_SHARED", "false"),
+        ("HTTP_CACHE_KEY_VERSION", " 2 "),
+        ("HTTP_CACHE_STALE_GRACE_SECS", "30"),
+        ("HTTP_CACHE_INSTANCE_NAME", "node-3"),
+    ]);
+    let config = CacheConfig::from_vars(|name| {
+        vars.get(name).map(|value| value.to_string())
+    })?;
+    assert_eq!(config.mode, Some(CacheMode::ForceCache));
+    let options = config.options();
+    assert!(!options.cache_status_headers);
+    assert!(!options.cache_options.unwrap().shared);
+    assert_eq!(options.key_version, 2);
+    assert_eq!(options.stale_grace, Some(Duration::from_secs(30)));
+    assert_eq!(options.instance_name.as_deref(), Some("node-3"));
+    // unset fields keep their defaults
+    assert!(options.skip_cache_on_set_cookie);
+    assert_eq!(options.miss_status, 504);
+
+    let err = CacheConfig::from_vars(|name| {
+        (name == "HTTP_CACHE_MODE").then(|| "sometimes".to_string())
+    })
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Invalid value for HTTP_CACHE_MODE: Unknown cache mode"
+    );
     Ok(())
 }
 
@@ -73,8 +357,11 @@ fn response_methods_work() -> Result<()> {
             url: url.clone(),
             version: HttpVersion::Http11,
         },
+        cache_info: None,
+        markers: Markers::default(),
+        decode_limit: None,
     };
-    assert_eq!(format!("{:?}", res), "HttpResponse { body: Body { inner: Full(b\"test\") }, parts: Parts { headers: {}, status: 200, url: Url { scheme: \"http\", cannot_be_a_base: false, username: \"\", password: None, host: Some(Domain(\"example.com\")), port: None, path: \"/\", query: None, fragment: None }, version: Http11 } }");
+    assert_eq!(format!("{:?}", res), "HttpResponse { body: Body { inner: Full(b\"test\") }, parts: Parts { headers: {}, status: 200, url: Url { scheme: \"http\", cannot_be_a_base: false, username: \"\", password: None, host: Some(Domain(\"example.com\")), port: None, path: \"/\", query: None, fragment: None }, version: Http11 }, cache_info: None }");
     res.add_warning(&url, 112, "Test Warning");
     let code = res.warning_code();
     assert!(code.is_some());
@@ -93,6 +380,102 @@ fn response_methods_work() -> Result<()> {
     assert_eq!(res.parts()?.headers, cloned_headers);
     res.parts.headers.remove(CACHE_CONTROL.as_str());
     assert!(!res.must_revalidate());
+    assert!(!res.is_no_transform());
+    res.parts
+        .headers
+        .insert(CACHE_CONTROL.to_string(), "max-age=60, No-Transform".into());
+    assert!(res.is_no_transform());
+    Ok(())
+}
+
+#[cfg(feature = "decode-gzip")]
+#[test]
+fn decoded_body() -> Result<()> {
+    use std::io::Write;
+
+    let mut encoder =
+        flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+    encoder.write_all(TEST_BODY)?;
+    let gzipped = encoder.finish()?;
+    let url = "http://example.com/";
+    let mut res =
+        build_response(url, 200, &[("content-encoding", "gzip")], &gzipped)?;
+    assert_eq!(res.decoded_body()?, TEST_BODY);
+    // The response keeps its encoded body
+    assert_eq!(res.body.as_bytes(), Some(&gzipped[..]));
+    // Decoding stops at the size limit of the cache
+    res.decode_limit = Some(TEST_BODY.len());
+    assert_eq!(res.decoded_body()?, TEST_BODY);
+    res.decode_limit = Some(TEST_BODY.len() - 1);
+    assert!(res.decoded_body().is_err_and(|e| e.is::<error::BodyTooLarge>()));
+
+    let res = build_response(url, 200, &[], TEST_BODY)?;
+    assert_eq!(res.decoded_body()?, TEST_BODY);
+    let res = build_response(url, 200, &[("content-encoding", "foo")], b"")?;
+    assert!(res
+        .decoded_body()
+        .is_err_and(|e| e.is::<error::UnsupportedEncoding>()));
+    Ok(())
+}
+
+#[test]
+fn add_warning_without_host() -> Result<()> {
+    let url = Url::parse("data:text/plain,test")?;
+    let mut res = build_response(url.as_str(), 200, &[], TEST_BODY)?;
+    res.add_warning(&url, 112, "Test Warning");
+    assert!(res.parts.headers["warning"].starts_with("112 - "));
+    Ok(())
+}
+
+#[test]
+fn request_snapshot_skips_credentials() -> Result<()> {
+    let req = http::Request::get("http://example.com/?q=1")
+        .header("accept", "text/html")
+        .header("authorization", "Bearer secret")
+        .header("cookie", "session=secret")
+        .body(())?;
+    let snapshot = RequestSnapshot::from(&req.into_parts().0);
+    assert_eq!(snapshot.method, GET);
+    assert_eq!(snapshot.uri, "http://example.com/?q=1");
+    assert_eq!(snapshot.headers.len(), 1);
+    assert_eq!(snapshot.headers.get("accept").unwrap(), "text/html");
+    Ok(())
+}
+
+#[test]
+fn key_spec() -> Result<()> {
+    let req = http::Request::get("http://example.com:8080/items?page=2")
+        .header("x-api-version", "2")
+        .body(())?;
+    let parts = req.into_parts().0;
+    let spec = KeySpec::new().method().host().path().header("X-Api-Version");
+    assert_eq!(spec.key(&parts), "GET:example.com:8080:/items:x-api-version=2");
+    let spec = KeySpec::new().path().query().header("accept");
+    assert_eq!(spec.key(&parts), "/items:page=2:accept=");
+    let cache_key = KeySpec::new().method().path().into_cache_key();
+    let opts =
+        HttpCacheOptions { cache_key: Some(cache_key), ..Default::default() };
+    assert_eq!(opts.create_cache_key(&parts, None), "GET:/items");
+    Ok(())
+}
+
+#[test]
+fn is_fresh() -> Result<()> {
+    let req = http::Request::get("http://example.com").body(())?;
+    let res = http::Response::builder()
+        .status(200)
+        .header(CACHE_CONTROL, "max-age=60")
+        .body(())?;
+    let now = SystemTime::now();
+    let policy = CachePolicy::new_options(&req, &res, now, Default::default());
+    assert!(crate::is_fresh(&policy, now));
+    assert!(!crate::is_fresh(&policy, now + Duration::from_secs(61)));
+    let res = http::Response::builder()
+        .status(200)
+        .header(CACHE_CONTROL, "no-cache")
+        .body(())?;
+    let policy = CachePolicy::new_options(&req, &res, now, Default::default());
+    assert!(!crate::is_fresh(&policy, now));
     Ok(())
 }
 
@@ -140,6 +523,59 @@ fn can_convert_versions_from_http() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn request_body_defaults_to_none() -> Result<()> {
+    use crate::{FnMiddleware, Middleware};
+
+    let (parts, ()) =
+        http::Request::post("http://example.com/").body(())?.into_parts();
+    let middleware = FnMiddleware::new(parts, |_| async {
+        Ok(http::Response::new(TEST_BODY.to_vec()))
+    });
+    assert!(middleware.request_body()?.is_none());
+    Ok(())
+}
+
+#[cfg(any(
+    feature = "manager-cacache",
+    feature = "manager-moka",
+    feature = "manager-blob",
+    feature = "encryption",
+    feature = "archive"
+))]
+#[test]
+fn canonical_parts_round_trip() -> Result<()> {
+    let url = Url::parse("http://example.com")?;
+    let mut first = Parts {
+        headers: HashMap::new(),
+        status: 200,
+        url: url.clone(),
+        version: HttpVersion::Http11,
+    };
+    let mut second = first.clone();
+    for (name, value) in
+        [("Content-Type", "text/plain "), ("ETag", "\"abc\""), ("x-a", "1")]
+    {
+        first.headers.insert(name.to_string(), value.to_string());
+    }
+    for (name, value) in
+        [("x-a", "1"), ("etag", " \"abc\""), ("content-type", "text/plain")]
+    {
+        second.headers.insert(name.to_string(), value.to_string());
+    }
+    let first = first.canonicalize();
+    let second = second.canonicalize();
+    assert_eq!(first.headers.get("content-type").unwrap(), "text/plain");
+    let bytes = bincode::serialize(&first)?;
+    assert_eq!(bytes, bincode::serialize(&second)?);
+    let decoded: Parts = bincode::deserialize(&bytes)?;
+    assert_eq!(decoded.headers, first.headers);
+    assert_eq!(decoded.status, first.status);
+    assert_eq!(decoded.url, first.url);
+    assert_eq!(decoded.version, first.version);
+    Ok(())
+}
+
 #[cfg(all(test, feature = "with-http-types"))]
 mod with_http_types {
     use super::*;
@@ -178,9 +614,11 @@ mod with_http_types {
 mod with_cacache {
 
     use super::*;
-    use crate::{Body, CACacheManager, CacheManager, Parts};
-
-    use http_cache_semantics::CachePolicy;
+    use crate::{
+        Body, CACacheManager, CacheManager, HttpCache, OnCorrupt, Parts, XCACHE,
+    };
+    use http::header::{ACCEPT_ENCODING, PRAGMA};
+    use std::sync::atomic::{AtomicBool, AtomicU64};
 
     #[cfg(feature = "cacache-async-std")]
     use async_attributes::test as async_test;
@@ -272,6 +710,4706 @@ mod with_cacache {
         assert_eq!(result, TEST_BODY);
         Ok(())
     }
+
+    fn build_cache(
+        tmp: &tempfile::TempDir,
+        options: HttpCacheOptions,
+    ) -> HttpCache<CACacheManager> {
+        HttpCache {
+            mode: CacheMode::Default,
+            manager: CACacheManager { path: tmp.path().to_path_buf() },
+            options,
+        }
+    }
+
+    #[async_test]
+    async fn store_request() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions { store_request: true, ..Default::default() },
+        );
+        let req =
+            http::Request::get(url).header("accept", "text/html").body(())?;
+        cache
+            .run(TestMiddleware::new(req, fetches.clone(), move |_| {
+                build_response(
+                    url,
+                    200,
+                    &[("cache-control", CACHEABLE_PUBLIC)],
+                    TEST_BODY,
+                )
+            }))
+            .await?;
+        let (res, _, request) = cache
+            .manager
+            .get_with_request(&format!("{}:{}", GET, url))
+            .await?
+            .unwrap();
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        let request = request.unwrap();
+        assert_eq!(request.method, GET);
+        assert_eq!(request.uri, url);
+        assert_eq!(request.headers.get("accept").unwrap(), "text/html");
+
+        // Disabled by default
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches,
+                move |_| {
+                    build_response(
+                        url,
+                        200,
+                        &[("cache-control", CACHEABLE_PUBLIC)],
+                        TEST_BODY,
+                    )
+                },
+            ))
+            .await?;
+        let (_, _, request) = cache
+            .manager
+            .get_with_request(&format!("{}:{}", GET, url))
+            .await?
+            .unwrap();
+        assert!(request.is_none());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn no_transform_stored_verbatim() -> Result<()> {
+        let url = "http://example.com/";
+        let body = b"\x1f\x8b raw bytes";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[
+                    ("cache-control", "max-age=86400, public, no-transform"),
+                    ("content-type", "application/octet-stream"),
+                ],
+                body,
+            )
+        };
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        let (stored, _) =
+            cache.manager.get(&format!("{}:{}", GET, url)).await?.unwrap();
+        assert!(stored.is_no_transform());
+        assert_eq!(
+            stored.parts.headers.get("content-type").unwrap(),
+            "application/octet-stream"
+        );
+        assert!(!stored.parts.headers.contains_key("content-encoding"));
+        assert_eq!(stored.body.bytes().await?, &body[..]);
+
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "HIT");
+        assert_eq!(res.body.bytes().await?, &body[..]);
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn policy_override() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", "no-store")],
+                TEST_BODY,
+            )
+        };
+
+        // Without an override the response is not stored
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        assert!(cache
+            .manager
+            .get(&format!("{}:{}", GET, url))
+            .await?
+            .is_none());
+
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                policy_override: Some(Arc::new(|res: &HttpResponse| {
+                    let req = http::Request::get(res.parts.url.as_str())
+                        .body(())
+                        .ok()?;
+                    let res = http::Response::builder()
+                        .status(res.parts.status)
+                        .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                        .body(())
+                        .ok()?;
+                    Some(CachePolicy::new(&req, &res))
+                })),
+                ..Default::default()
+            },
+        );
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "HIT");
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn too_many_requests_serves_stale() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                move |_| {
+                    build_response(
+                        url,
+                        200,
+                        &[
+                            ("cache-control", "max-age=0, must-revalidate"),
+                            ("etag", "\"abc\""),
+                        ],
+                        TEST_BODY,
+                    )
+                },
+            ))
+            .await?;
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                move |_| {
+                    build_response(
+                        url,
+                        429,
+                        &[("retry-after", "120")],
+                        b"slow down",
+                    )
+                },
+            ))
+            .await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(res.warning_code(), Some(111));
+        assert_eq!(res.parts.status, 200);
+        assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "HIT");
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn vary_star_not_stored() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", CACHEABLE_PUBLIC), ("vary", "accept, *")],
+                TEST_BODY,
+            )
+        };
+        for _ in 0..2 {
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    respond,
+                ))
+                .await?;
+            assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "MISS");
+            assert!(cache
+                .manager
+                .get(&format!("{}:{}", GET, url))
+                .await?
+                .is_none());
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cacheable_url_matcher() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                cacheable_url_matcher: Some(Arc::new(|url: &Url| {
+                    ["/api/", "/static/"]
+                        .iter()
+                        .any(|prefix| url.path().starts_with(prefix))
+                })),
+                ..Default::default()
+            },
+        );
+        for (url, cached) in [
+            ("http://example.com/api/items", true),
+            ("http://example.com/static/app.js", true),
+            ("http://example.com/admin", false),
+        ] {
+            let middleware = TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                move |_| {
+                    build_response(
+                        url,
+                        200,
+                        &[("cache-control", CACHEABLE_PUBLIC)],
+                        TEST_BODY,
+                    )
+                },
+            );
+            assert_eq!(cache.can_cache_request(&middleware)?, cached);
+            cache.run(middleware).await?;
+            assert_eq!(
+                cache.manager.get(&format!("{}:{}", GET, url)).await?.is_some(),
+                cached
+            );
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cacache_last_accessed() -> Result<()> {
+        let url = Url::parse("http://example.com")?;
+        let key = format!("{}:{}", GET, &url);
+        let tmp = tempfile::tempdir()?;
+        let manager = CACacheManager { path: tmp.path().to_path_buf() };
+        assert!(manager.last_accessed(&key).await?.is_none());
+        let req = http::Request::get(url.as_str()).body(())?;
+        let res = http::Response::builder().status(200).body(())?;
+        manager
+            .put(
+                key.clone(),
+                build_response(url.as_str(), 200, &[], TEST_BODY)?,
+                CachePolicy::new(&req, &res),
+            )
+            .await?;
+        let written = manager.last_accessed(&key).await?.unwrap();
+        let content = cacache::metadata(tmp.path(), &key).await?.unwrap();
+
+        // Reads right after the write don't record an access
+        manager.get(&key).await?.unwrap();
+        assert_eq!(manager.last_accessed(&key).await?.unwrap(), written);
+
+        // Backdate the entry past the resolution of access times
+        let backdated = content.time - 120_000;
+        cacache::index::insert_async(
+            tmp.path(),
+            &key,
+            cacache::WriteOpts::new()
+                .integrity(content.integrity.clone())
+                .size(content.size)
+                .time(backdated),
+        )
+        .await?;
+        manager.get(&key).await?.unwrap();
+        let accessed = manager.last_accessed(&key).await?.unwrap();
+        assert!(accessed >= written);
+        // The body content is left untouched
+        let touched = cacache::metadata(tmp.path(), &key).await?.unwrap();
+        assert!(touched.time > backdated);
+        assert_eq!(touched.integrity, content.integrity);
+        assert_eq!(touched.size, content.size);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cacache_peek() -> Result<()> {
+        let url = Url::parse("http://example.com")?;
+        let key = format!("{}:{}", GET, &url);
+        let tmp = tempfile::tempdir()?;
+        let manager = CACacheManager { path: tmp.path().to_path_buf() };
+        assert!(manager.peek(&key).await?.is_none());
+        let req = http::Request::get(url.as_str()).body(())?;
+        let res = http::Response::builder().status(200).body(())?;
+        manager
+            .put(
+                key.clone(),
+                build_response(url.as_str(), 200, &[], TEST_BODY)?,
+                CachePolicy::new(&req, &res),
+            )
+            .await?;
+        let written = manager.last_accessed(&key).await?.unwrap();
+
+        std::thread::sleep(Duration::from_millis(10));
+        let (res, _) = manager.peek(&key).await?.unwrap();
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        // Peeking doesn't count as an access
+        assert_eq!(manager.last_accessed(&key).await?.unwrap(), written);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn chunked_response_round_trip() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let respond = move |_: &request::Parts| {
+            let chunks: Vec<Result<&'static [u8]>> = vec![
+                Ok(b"chunk one,"),
+                Ok(b" chunk two,"),
+                Ok(b" chunk three"),
+            ];
+            let (parts, _) = build_response(
+                url,
+                200,
+                &[
+                    ("cache-control", CACHEABLE_PUBLIC),
+                    ("transfer-encoding", "chunked"),
+                ],
+                b"",
+            )?
+            .into_parts();
+            Ok(HttpResponse::from_parts(
+                parts,
+                Body::wrap_stream(futures_util::stream::iter(chunks)),
+            ))
+        };
+        let cold = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        assert_eq!(cold.parts.headers.get(XCACHE).unwrap(), "MISS");
+        let cold = cold.body.bytes().await?;
+        assert_eq!(cold, &b"chunk one, chunk two, chunk three"[..]);
+
+        let hot = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        assert_eq!(hot.parts.headers.get(XCACHE).unwrap(), "HIT");
+        assert_eq!(hot.body.bytes().await?, cold);
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn synthesized_response_version() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let mut cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                default_response_version: HttpVersion::H2,
+                ..Default::default()
+            },
+        );
+        cache.mode = CacheMode::OnlyIfCached;
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                move |_| build_response(url, 200, &[], TEST_BODY),
+            ))
+            .await?;
+        assert_eq!(res.parts.status, 504);
+        assert_eq!(res.parts.version, HttpVersion::H2);
+        assert_eq!(fetches.load(Ordering::SeqCst), 0);
+        Ok(())
+    }
+
+    #[cfg(feature = "cacache-async-std")]
+    #[async_test]
+    async fn spawn_pruner() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        assert!(cache.spawn_pruner(Duration::from_millis(10)).is_err());
+
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                spawn: Some(Arc::new(|task| {
+                    async_std::task::spawn(task);
+                })),
+                sleep: Some(Arc::new(|duration| {
+                    Box::pin(async_std::task::sleep(duration))
+                })),
+                // Records are judged by the clock of the cache
+                clock: Some(Arc::new(|| {
+                    SystemTime::now() + Duration::from_secs(7200)
+                })),
+                ..Default::default()
+            },
+        );
+        for (path, cache_control) in [
+            ("/expired", "max-age=0"),
+            ("/hour", "max-age=3600"),
+            ("/fresh", CACHEABLE_PUBLIC),
+        ] {
+            let url = format!("http://example.com{}", path);
+            let req = http::Request::get(&url).body(())?;
+            let res = http::Response::builder()
+                .status(200)
+                .header(CACHE_CONTROL, cache_control)
+                .body(())?;
+            cache
+                .manager
+                .put(
+                    format!("{}:{}", GET, url),
+                    build_response(&url, 200, &[], TEST_BODY)?,
+                    CachePolicy::new(&req, &res),
+                )
+                .await?;
+        }
+        let handle = cache.spawn_pruner(Duration::from_millis(10))?;
+        async_std::task::sleep(Duration::from_millis(200)).await;
+        drop(handle);
+        for path in ["/expired", "/hour"] {
+            assert!(cache
+                .manager
+                .get(&format!("GET:http://example.com{path}"))
+                .await?
+                .is_none());
+        }
+        assert!(cache
+            .manager
+            .get("GET:http://example.com/fresh")
+            .await?
+            .is_some());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn content_encoding_varies_on_accept_encoding() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let respond = move |req: &request::Parts| {
+            if req.headers.get(ACCEPT_ENCODING).is_some_and(|v| v == "gzip") {
+                build_response(
+                    url,
+                    200,
+                    &[
+                        ("cache-control", CACHEABLE_PUBLIC),
+                        ("content-encoding", "gzip"),
+                    ],
+                    TEST_BODY,
+                )
+            } else {
+                build_response(
+                    url,
+                    200,
+                    &[("cache-control", CACHEABLE_PUBLIC)],
+                    TEST_BODY,
+                )
+            }
+        };
+        let run = |accept_encoding: Option<&'static str>| {
+            let mut req = http::Request::get(url);
+            if let Some(accept_encoding) = accept_encoding {
+                req = req.header(ACCEPT_ENCODING, accept_encoding);
+            }
+            let req = req.body(());
+            let fetches = fetches.clone();
+            let cache = &cache;
+            async move {
+                cache.run(TestMiddleware::new(req?, fetches, respond)).await
+            }
+        };
+
+        let res = run(Some("gzip")).await?;
+        assert_eq!(res.parts.headers.get("content-encoding").unwrap(), "gzip");
+        assert_eq!(res.parts.headers.get("vary").unwrap(), "accept-encoding");
+
+        // A client that doesn't accept gzip must not get the encoded body
+        let res = run(None).await?;
+        assert!(!res.parts.headers.contains_key("content-encoding"));
+        assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "MISS");
+
+        let res = run(None).await?;
+        assert!(!res.parts.headers.contains_key("content-encoding"));
+        assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "HIT");
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cacache_touch() -> Result<()> {
+        let url = Url::parse("http://example.com")?;
+        let key = format!("{}:{}", GET, &url);
+        let tmp = tempfile::tempdir()?;
+        let manager = CACacheManager { path: tmp.path().into() };
+        assert!(!manager.touch(&key, Duration::from_secs(60)).await?);
+        // The response states no freshness lifetime, so it is stale right away
+        let req = http::Request::get(url.as_str()).body(())?;
+        let res = http::Response::builder().status(200).body(())?;
+        manager
+            .put(
+                key.clone(),
+                build_response(url.as_str(), 200, &[], TEST_BODY)?,
+                CachePolicy::new(&req, &res),
+            )
+            .await?;
+        assert!(manager.touch(&key, Duration::from_secs(60)).await?);
+        manager.evict_expired(SystemTime::now()).await?;
+        assert!(manager.get(&key).await?.is_some());
+        assert!(manager.touch(&key, Duration::ZERO).await?);
+        manager.evict_expired(SystemTime::now()).await?;
+        assert!(manager.get(&key).await?.is_none());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cacache_evict_expired() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let manager = CACacheManager { path: tmp.path().into() };
+        let cases: [(&str, &[(&str, &str)]); 4] = [
+            ("http://example.com/fresh", &[("cache-control", "max-age=60")]),
+            ("http://example.com/stale", &[]),
+            ("http://example.com/etag", &[("etag", "\"v1\"")]),
+            (
+                "http://example.com/swr",
+                &[("cache-control", "max-age=0, stale-while-revalidate=60")],
+            ),
+        ];
+        for (url, headers) in cases {
+            let req = http::Request::get(url).body(())?;
+            let mut res = http::Response::builder().status(200);
+            for (name, value) in headers {
+                res = res.header(*name, *value);
+            }
+            manager
+                .put(
+                    format!("{GET}:{url}"),
+                    build_response(url, 200, headers, TEST_BODY)?,
+                    CachePolicy::new(&req, &res.body(())?),
+                )
+                .await?;
+        }
+        let stored = |now| {
+            let manager = manager.clone();
+            async move {
+                manager.evict_expired(now).await?;
+                let mut keys = manager.keys().await?;
+                keys.sort();
+                Result::Ok(keys)
+            }
+        };
+        // Records that can be revalidated or served stale are kept
+        assert_eq!(
+            stored(SystemTime::now()).await?,
+            [
+                "GET:http://example.com/etag",
+                "GET:http://example.com/fresh",
+                "GET:http://example.com/swr",
+            ]
+        );
+        // Until the stale-while-revalidate window has passed
+        assert_eq!(
+            stored(SystemTime::now() + Duration::from_secs(120)).await?,
+            ["GET:http://example.com/etag"]
+        );
+        Ok(())
+    }
+
+    #[async_test]
+    async fn not_modified_with_body() -> Result<()> {
+        let url = "http://example.com/";
+        for trust_304_body in [false, true] {
+            let tmp = tempfile::tempdir()?;
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let observer = Arc::new(RecordingObserver::default());
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions {
+                    trust_304_body,
+                    observer: Some(observer.clone()),
+                    ..Default::default()
+                },
+            );
+            let respond = move |req: &request::Parts| {
+                if req.headers.contains_key(http::header::IF_NONE_MATCH) {
+                    build_response(url, 304, &[("etag", "\"abc\"")], b"new")
+                } else {
+                    build_response(
+                        url,
+                        200,
+                        &[("cache-control", "no-cache"), ("etag", "\"abc\"")],
+                        TEST_BODY,
+                    )
+                }
+            };
+            for _ in 0..2 {
+                cache
+                    .run(TestMiddleware::new(
+                        http::Request::get(url).body(())?,
+                        fetches.clone(),
+                        respond,
+                    ))
+                    .await?;
+            }
+            let (res, _) =
+                cache.manager.get(&format!("{}:{}", GET, url)).await?.unwrap();
+            let expected: &[u8] =
+                if trust_304_body { b"new" } else { TEST_BODY };
+            assert_eq!(res.body.bytes().await?, expected);
+            assert_eq!(fetches.load(Ordering::SeqCst), 2);
+            assert_eq!(
+                *observer.not_modified_bodies.lock().unwrap(),
+                [(format!("{}:{}", GET, url), b"new".to_vec())]
+            );
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn key_version() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", CACHEABLE_PUBLIC)],
+                TEST_BODY,
+            )
+        };
+        for key_version in [0, 1, 1] {
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions { key_version, ..Default::default() },
+            );
+            cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    respond,
+                ))
+                .await?;
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        let manager = CACacheManager { path: tmp.path().into() };
+        assert!(manager.get(&format!("{}:{}", GET, url)).await?.is_some());
+        assert!(manager.get(&format!("v1:{}:{}", GET, url)).await?.is_some());
+
+        // Busted keys are versioned too
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                key_version: 1,
+                cache_bust: Some(Arc::new(|_, _, _| {
+                    vec![format!("{}:{}", GET, "http://example.com/")]
+                })),
+                ..Default::default()
+            },
+        );
+        cache
+            .run_no_cache(&mut TestMiddleware::new(
+                http::Request::post("http://example.com/other").body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        assert!(manager.get(&format!("{}:{}", GET, url)).await?.is_some());
+        assert!(manager.get(&format!("v1:{}:{}", GET, url)).await?.is_none());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn revalidate_with_if_modified_since() -> Result<()> {
+        let url = "http://example.com/";
+        let last_modified = "Wed, 21 Oct 2015 07:28:00 GMT";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let respond = move |req: &request::Parts| match req
+            .headers
+            .get(http::header::IF_MODIFIED_SINCE)
+        {
+            Some(since) => {
+                assert_eq!(since, last_modified);
+                assert!(!req.headers.contains_key(http::header::IF_NONE_MATCH));
+                build_response(
+                    url,
+                    304,
+                    &[("last-modified", last_modified), ("x-version", "2")],
+                    b"",
+                )
+            }
+            None => build_response(
+                url,
+                200,
+                &[
+                    ("cache-control", "no-cache"),
+                    ("last-modified", last_modified),
+                    ("x-version", "1"),
+                ],
+                TEST_BODY,
+            ),
+        };
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(res.parts.status, 200);
+        assert_eq!(res.parts.headers.get("x-version").unwrap(), "2");
+        assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "HIT");
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        Ok(())
+    }
+
+    #[cfg(feature = "encryption")]
+    #[async_test]
+    async fn encrypted_manager() -> Result<()> {
+        use crate::EncryptedManager;
+
+        let url = "http://example.com/";
+        let key = format!("{}:{}", GET, url);
+        let tmp = tempfile::tempdir()?;
+        let inner = CACacheManager { path: tmp.path().into() };
+        let manager = EncryptedManager::new(inner.clone(), &[7; 32]);
+        let req = http::Request::get(url).body(())?;
+        let origin_res = http::Response::builder()
+            .status(200)
+            .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+            .body(())?;
+        manager
+            .put(
+                key.clone(),
+                build_response(
+                    url,
+                    200,
+                    &[("cache-control", CACHEABLE_PUBLIC)],
+                    TEST_BODY,
+                )?,
+                CachePolicy::new(&req, &origin_res),
+            )
+            .await?;
+
+        let (res, policy) = manager.get(&key).await?.unwrap();
+        assert_eq!(
+            res.parts.headers.get("cache-control").unwrap(),
+            CACHEABLE_PUBLIC
+        );
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        assert!(crate::is_fresh(&policy, SystemTime::now()));
+
+        // Nothing readable is stored by the wrapped manager
+        let (raw, raw_policy) = inner.get(&key).await?.unwrap();
+        assert!(raw.parts.headers.is_empty());
+        let raw_body = raw.body.bytes().await?;
+        assert!(!raw_body.windows(TEST_BODY.len()).any(|w| w == TEST_BODY));
+        assert!(crate::is_fresh(&raw_policy, SystemTime::now()));
+
+        // Request snapshots and metadata are sealed along with the response
+        let snapshot = RequestSnapshot::from(
+            &http::Request::get(url)
+                .header("accept", "text/html")
+                .body(())?
+                .into_parts()
+                .0,
+        );
+        let metadata = HashMap::from([("tenant".to_string(), "a".to_string())]);
+        manager
+            .put_with_metadata(
+                key.clone(),
+                build_response(url, 200, &[], TEST_BODY)?,
+                CachePolicy::new(&req, &origin_res),
+                Some(snapshot.clone()),
+                metadata.clone(),
+            )
+            .await?;
+        let (_, _, request) = manager.get_with_request(&key).await?.unwrap();
+        assert_eq!(request, Some(snapshot));
+        let (_, _, stored) = manager.get_with_metadata(&key).await?.unwrap();
+        assert_eq!(stored, metadata);
+        let (raw, _) = inner.get(&key).await?.unwrap();
+        let raw_body = raw.body.bytes().await?;
+        assert!(!raw_body.windows(9).any(|w| w == b"text/html"));
+        assert_eq!(manager.keys().await?, [key.as_str()]);
+        manager.set_variants(&key, vec![key.clone()]).await?;
+        assert_eq!(manager.variants(&key).await?, [key.as_str()]);
+
+        // Records that can't be decrypted are a miss
+        let other = EncryptedManager::new(inner.clone(), &[8; 32]);
+        assert!(other.get(&key).await?.is_none());
+        inner
+            .put(
+                key.clone(),
+                build_response(url, 200, &[], TEST_BODY)?,
+                CachePolicy::new(&req, &origin_res),
+            )
+            .await?;
+        assert!(manager.get(&key).await?.is_none());
+        Ok(())
+    }
+
+    #[derive(Debug)]
+    struct FailingDeleteManager(CACacheManager);
+
+    #[async_trait::async_trait]
+    impl CacheManager for FailingDeleteManager {
+        async fn get(
+            &self,
+            cache_key: &str,
+        ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+            self.0.get(cache_key).await
+        }
+        async fn put(
+            &self,
+            cache_key: String,
+            res: HttpResponse,
+            policy: CachePolicy,
+        ) -> Result<HttpResponse> {
+            self.0.put(cache_key, res, policy).await
+        }
+        async fn delete(&self, _cache_key: &str) -> Result<()> {
+            Err("delete failed".into())
+        }
+    }
+
+    #[async_test]
+    async fn cache_bust_failures() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        for strict_cache_bust in [false, true] {
+            let cache = HttpCache {
+                mode: CacheMode::Default,
+                manager: FailingDeleteManager(CACacheManager {
+                    path: tmp.path().into(),
+                }),
+                options: HttpCacheOptions {
+                    cache_bust: Some(Arc::new(|_, _, _| {
+                        vec![format!("{}:{}", GET, "http://example.com/other")]
+                    })),
+                    strict_cache_bust,
+                    ..Default::default()
+                },
+            };
+            let respond = move |_: &request::Parts| {
+                build_response(
+                    url,
+                    200,
+                    &[("cache-control", CACHEABLE_PUBLIC)],
+                    TEST_BODY,
+                )
+            };
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    respond,
+                ))
+                .await;
+            let no_cache = cache
+                .run_no_cache(&mut TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    respond,
+                ))
+                .await;
+            assert_eq!(res.is_err(), strict_cache_bust);
+            assert_eq!(no_cache.is_err(), strict_cache_bust);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn stale_response_transform() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                stale_response_transform: Some(Arc::new(|res| {
+                    res.parts.headers.insert("x-stale".into(), "1".into());
+                })),
+                ..Default::default()
+            },
+        );
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", "max-age=0, must-revalidate")],
+                TEST_BODY,
+            )
+        };
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        assert!(!res.parts.headers.contains_key("x-stale"));
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                move |_| build_response(url, 500, &[], b"error"),
+            ))
+            .await?;
+        assert_eq!(res.parts.status, 200);
+        assert_eq!(res.parts.headers.get("x-stale").unwrap(), "1");
+        Ok(())
+    }
+
+    #[async_test]
+    async fn miss_status() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = HttpCache {
+            mode: CacheMode::OnlyIfCached,
+            manager: CACacheManager { path: tmp.path().into() },
+            options: HttpCacheOptions {
+                miss_status: 503,
+                ..Default::default()
+            },
+        };
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                move |_| build_response(url, 200, &[], TEST_BODY),
+            ))
+            .await?;
+        assert_eq!(res.parts.status, 503);
+        assert_eq!(fetches.load(Ordering::SeqCst), 0);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn revalidate_with_head() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                revalidate_with_head: true,
+                ..Default::default()
+            },
+        );
+        let methods = Arc::new(Mutex::new(Vec::new()));
+        let respond = |etag: &'static str, body: &'static [u8]| {
+            let methods = methods.clone();
+            move |req: &request::Parts| {
+                methods.lock().unwrap().push(req.method.clone());
+                let matches = req
+                    .headers
+                    .get(http::header::IF_NONE_MATCH)
+                    .is_some_and(|v| v == etag);
+                let body =
+                    if req.method == http::Method::HEAD { b"" } else { body };
+                build_response(
+                    url,
+                    if matches { 304 } else { 200 },
+                    &[("cache-control", "no-cache"), ("etag", etag)],
+                    body,
+                )
+            }
+        };
+        for (etag, body) in
+            [("\"a\"", TEST_BODY), ("\"a\"", b"unused"), ("\"b\"", b"new")]
+        {
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    respond(etag, body),
+                ))
+                .await?;
+            let expected: &[u8] =
+                if etag == "\"a\"" { TEST_BODY } else { b"new" };
+            assert_eq!(res.body.bytes().await?, expected);
+        }
+        assert_eq!(
+            *methods.lock().unwrap(),
+            [
+                http::Method::GET,
+                http::Method::HEAD,
+                http::Method::HEAD,
+                http::Method::GET
+            ]
+        );
+        let (res, _) =
+            cache.manager.get(&format!("{}:{}", GET, url)).await?.unwrap();
+        assert_eq!(res.parts.headers.get("etag").unwrap(), "\"b\"");
+        Ok(())
+    }
+
+    #[async_test]
+    async fn skip_cache_on_set_cookie() -> Result<()> {
+        let url = "http://example.com/";
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[
+                    ("cache-control", CACHEABLE_PUBLIC),
+                    ("set-cookie", "session=abc"),
+                ],
+                TEST_BODY,
+            )
+        };
+        for (options, stored) in [
+            (HttpCacheOptions::default(), false),
+            (
+                HttpCacheOptions {
+                    skip_cache_on_set_cookie: false,
+                    ..Default::default()
+                },
+                true,
+            ),
+            (
+                HttpCacheOptions {
+                    cache_options: Some(CacheOptions {
+                        shared: false,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+                true,
+            ),
+        ] {
+            let tmp = tempfile::tempdir()?;
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let cache = build_cache(&tmp, options);
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    respond,
+                ))
+                .await?;
+            assert_eq!(
+                res.parts.headers.get("set-cookie").unwrap(),
+                "session=abc"
+            );
+            assert_eq!(res.body.bytes().await?, TEST_BODY);
+            assert_eq!(
+                cache.manager.get(&format!("{}:{}", GET, url)).await?.is_some(),
+                stored
+            );
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "archive")]
+    #[async_test]
+    async fn export_import_archive() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let urls = ["http://example.com/a", "http://example.com/b"];
+        for url in urls {
+            let req = http::Request::get(url).body(())?;
+            let res = http::Response::builder()
+                .status(200)
+                .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                .body(())?;
+            let request = RequestSnapshot::from(&req.clone().into_parts().0);
+            cache
+                .manager
+                .put_with_metadata(
+                    format!("{}:{}", GET, url),
+                    build_response(url, 200, &[], url.as_bytes())?,
+                    CachePolicy::new(&req, &res),
+                    Some(request),
+                    HashMap::from([("url".to_string(), url.to_string())]),
+                )
+                .await?;
+        }
+        let archive = tmp.path().join("cache.tar");
+        let file = std::fs::File::create(&archive)?;
+        assert_eq!(cache.export_archive(file).await?, 2);
+
+        let other = tempfile::tempdir()?;
+        let imported = build_cache(&other, HttpCacheOptions::default());
+        let file = std::fs::File::open(&archive)?;
+        assert_eq!(imported.import_archive(file).await?, 2);
+        let mut keys = imported.manager.keys().await?;
+        keys.sort();
+        assert_eq!(
+            keys,
+            ["GET:http://example.com/a", "GET:http://example.com/b"]
+        );
+        for url in urls {
+            let (res, policy) = imported
+                .manager
+                .get(&format!("{}:{}", GET, url))
+                .await?
+                .unwrap();
+            assert_eq!(res.parts.url.as_str(), url);
+            assert_eq!(res.body.bytes().await?, url.as_bytes());
+            assert!(crate::is_fresh(&policy, SystemTime::now()));
+            // Request snapshots and metadata are carried over
+            let (_, _, request) = imported
+                .manager
+                .get_with_request(&format!("{}:{}", GET, url))
+                .await?
+                .unwrap();
+            assert_eq!(request.unwrap().uri, url);
+            let (_, _, metadata) = imported
+                .manager
+                .get_with_metadata(&format!("{}:{}", GET, url))
+                .await?
+                .unwrap();
+            assert_eq!(metadata["url"], url);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "archive")]
+    #[async_test]
+    async fn archive_manager() -> Result<()> {
+        use crate::ArchiveManager;
+
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let urls = ["http://example.com/a", "http://example.com/b"];
+        for url in urls {
+            cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    Arc::new(AtomicUsize::new(0)),
+                    move |_| {
+                        build_response(
+                            url,
+                            200,
+                            &[("cache-control", CACHEABLE_PUBLIC)],
+                            url.as_bytes(),
+                        )
+                    },
+                ))
+                .await?;
+        }
+        let bundle = tmp.path().join("bundle.tar");
+        cache.export_archive(std::fs::File::create(&bundle)?).await?;
+
+        let bundled = HttpCache {
+            mode: CacheMode::OnlyIfCached,
+            manager: ArchiveManager::open(&bundle)?,
+            options: HttpCacheOptions::default(),
+        };
+        let mut keys = bundled.manager.keys().await?;
+        keys.sort();
+        assert_eq!(
+            keys,
+            ["GET:http://example.com/a", "GET:http://example.com/b"]
+        );
+        let fetches = Arc::new(AtomicUsize::new(0));
+        for url in urls {
+            let res = bundled
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    move |_| build_response(url, 500, &[], b""),
+                ))
+                .await?;
+            assert_eq!(res.parts.headers[XCACHE], HitOrMiss::HIT.to_string());
+            assert_eq!(res.body.bytes().await?, url.as_bytes());
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 0);
+        assert!(bundled
+            .manager
+            .get("GET:http://example.com/c")
+            .await?
+            .is_none());
+        assert!(bundled
+            .manager
+            .delete("GET:http://example.com/a")
+            .await
+            .is_err());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cache_options_fn() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", "max-age=86400, private")],
+                TEST_BODY,
+            )
+        };
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                cache_options_fn: Some(Arc::new(|parts: &request::Parts| {
+                    CacheOptions {
+                        shared: !parts.headers.contains_key("x-user"),
+                        ..Default::default()
+                    }
+                })),
+                ..Default::default()
+            },
+        );
+        let key = format!("{}:{}", GET, url);
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        assert!(cache.manager.get(&key).await?.is_none());
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).header("x-user", "alice").body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        assert!(cache.manager.get(&key).await?.is_some());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn canonical_cache_method() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                canonical_cache_method: http::Method::HEAD,
+                ..Default::default()
+            },
+        );
+        let req = http::Request::get(url).body(())?;
+        let res = http::Response::builder()
+            .status(200)
+            .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+            .body(())?;
+        for method in ["GET", "HEAD"] {
+            cache
+                .manager
+                .put(
+                    format!("{}:{}", method, url),
+                    build_response(url, 200, &[], TEST_BODY)?,
+                    CachePolicy::new(&req, &res),
+                )
+                .await?;
+        }
+        cache
+            .run(TestMiddleware::new(
+                http::Request::post(url).body(())?,
+                fetches.clone(),
+                move |_| build_response(url, 201, &[], TEST_BODY),
+            ))
+            .await?;
+        assert!(cache.manager.get(&format!("GET:{}", url)).await?.is_some());
+        assert!(cache.manager.get(&format!("HEAD:{}", url)).await?.is_none());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn streaming_stale_response_warning() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let respond = move |_: &request::Parts| {
+            let chunks: Vec<Result<&'static [u8]>> =
+                vec![Ok(b"stale"), Ok(b" body")];
+            let (parts, _) = build_response(
+                url,
+                200,
+                &[("cache-control", "max-age=0"), ("etag", "\"abc\"")],
+                b"",
+            )?
+            .into_parts();
+            Ok(HttpResponse::from_parts(
+                parts,
+                Body::wrap_stream(futures_util::stream::iter(chunks)),
+            ))
+        };
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?
+            .body
+            .bytes()
+            .await?;
+        let (stored, _) =
+            cache.manager.get(&format!("{}:{}", GET, url)).await?.unwrap();
+        assert!(stored.body.as_bytes().is_none());
+
+        // The origin is unreachable, so the stale streaming body is served
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                |_| Err("offline".into()),
+            ))
+            .await?;
+        assert_eq!(res.warning_code(), Some(111));
+        assert!(res.body.as_bytes().is_none());
+        assert_eq!(res.body.bytes().await?, &b"stale body"[..]);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn max_variants_per_url() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                cache_key: Some(Arc::new(|parts: &request::Parts| {
+                    format!(
+                        "{}:{}:{}",
+                        parts.method,
+                        parts.uri,
+                        parts.headers[http::header::ACCEPT_LANGUAGE]
+                            .to_str()
+                            .unwrap()
+                    )
+                })),
+                max_variants_per_url: Some(2),
+                ..Default::default()
+            },
+        );
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", CACHEABLE_PUBLIC)],
+                TEST_BODY,
+            )
+        };
+        for language in ["en", "fr", "en", "de"] {
+            if language == "en" && fetches.load(Ordering::SeqCst) == 2 {
+                // Access times have a resolution of a minute, so the stored
+                // variants are backdated for the hit to count as an access
+                for (language, minutes) in [("en", 3), ("fr", 2)] {
+                    let key = format!("{GET}:{url}:{language}");
+                    let metadata =
+                        cacache::metadata(tmp.path(), &key).await?.unwrap();
+                    cacache::index::insert_async(
+                        tmp.path(),
+                        &key,
+                        cacache::WriteOpts::new()
+                            .integrity(metadata.integrity)
+                            .size(metadata.size)
+                            .time(metadata.time - minutes * 60_000),
+                    )
+                    .await?;
+                }
+            }
+            cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url)
+                        .header(http::header::ACCEPT_LANGUAGE, language)
+                        .body(())?,
+                    fetches.clone(),
+                    respond,
+                ))
+                .await?;
+        }
+        // The second "en" request was a hit, so "fr" was the least recently used
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+        let mut keys = cache.manager.keys().await?;
+        keys.sort();
+        assert_eq!(
+            keys,
+            ["GET:http://example.com/:de", "GET:http://example.com/:en"]
+        );
+        let mut variants =
+            cache.manager.variants(&format!("{}:{}", GET, url)).await?;
+        variants.sort();
+        assert_eq!(variants, keys);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn max_variants_per_url_keys() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let options = || HttpCacheOptions {
+            cache_key: Some(Arc::new(|parts: &request::Parts| {
+                format!("{}:{}:variant", parts.method, parts.uri)
+            })),
+            max_variants_per_url: Some(2),
+            key_version: 2,
+            ..Default::default()
+        };
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", CACHEABLE_PUBLIC)],
+                TEST_BODY,
+            )
+        };
+        // The variants are tracked under the versioned key of the url
+        let cache = build_cache(&tmp, options());
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        assert_eq!(
+            cache.manager.variants(&format!("v2:{GET}:{url}")).await?,
+            [format!("v2:{GET}:{url}:variant")]
+        );
+        // Managers without variants store the response regardless of the limit
+        let cache = HttpCache {
+            mode: CacheMode::Default,
+            manager: FailingDeleteManager(CACacheManager {
+                path: tmp.path().join("unsupported"),
+            }),
+            options: options(),
+        };
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            ))
+            .await?;
+        assert!(cache
+            .manager
+            .get(&format!("v2:{GET}:{url}:variant"))
+            .await?
+            .is_some());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn pragma_no_cache() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let respond = move |req: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", CACHEABLE_PUBLIC)],
+                if req.headers.contains_key(CACHE_CONTROL) {
+                    b"revalidated"
+                } else {
+                    TEST_BODY
+                },
+            )
+        };
+        let run = |req: request::Builder| {
+            let middleware = req
+                .body(())
+                .map(|req| TestMiddleware::new(req, fetches.clone(), respond));
+            let cache = &cache;
+            async move { cache.run(middleware?).await }
+        };
+        run(http::Request::get(url)).await?;
+        let res =
+            run(http::Request::get(url).header(PRAGMA, "no-cache")).await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(res.body.bytes().await?, &b"revalidated"[..]);
+
+        Ok(())
+    }
+
+    #[async_test]
+    async fn fn_middleware() -> Result<()> {
+        use crate::FnMiddleware;
+
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        for expected in ["MISS", "HIT"] {
+            let fetches = fetches.clone();
+            let res = cache
+                .run(FnMiddleware::new(
+                    http::Request::get(url).body(())?.into_parts().0,
+                    move |parts: request::Parts| {
+                        fetches.fetch_add(1, Ordering::SeqCst);
+                        async move {
+                            assert_eq!(parts.uri, url);
+                            Ok(http::Response::builder()
+                                .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                                .body(bytes::Bytes::from_static(TEST_BODY))?)
+                        }
+                    },
+                ))
+                .await?;
+            assert_eq!(res.parts.headers.get(XCACHE).unwrap(), expected);
+            assert_eq!(res.parts.url.as_str(), url);
+            assert_eq!(res.body.bytes().await?, TEST_BODY);
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn heuristic_immutable_matcher() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                heuristic_immutable_matcher: Some(Arc::new(|url: &Url| {
+                    url.path().starts_with("/static/")
+                })),
+                ..Default::default()
+            },
+        );
+        let year = Duration::from_secs(60 * 60 * 24 * 364);
+        for (url, cache_control, long_lived) in [
+            ("http://example.com/static/app.abc123.js", "public", true),
+            ("http://example.com/app.js", "public", false),
+            ("http://example.com/static/app.js", "max-age=60", false),
+        ] {
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    move |_| {
+                        build_response(
+                            url,
+                            200,
+                            &[("cache-control", cache_control)],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+                .await?;
+            assert_eq!(
+                res.parts.headers.get("cache-control").unwrap(),
+                cache_control
+            );
+            let ttl =
+                match cache.manager.get(&format!("{}:{}", GET, url)).await? {
+                    Some((_, policy)) => policy.time_to_live(SystemTime::now()),
+                    None => Duration::ZERO,
+                };
+            assert_eq!(ttl > year, long_lived);
+        }
+        Ok(())
+    }
+
+    type ContentChange = (String, Vec<u8>, Vec<u8>);
+
+    #[derive(Default)]
+    struct RecordingObserver {
+        revalidations: Mutex<Vec<(String, Duration)>>,
+        modes: Mutex<Vec<(Url, CacheMode)>>,
+        changes: Mutex<Vec<ContentChange>>,
+        not_modified_bodies: Mutex<Vec<(String, Vec<u8>)>>,
+    }
+
+    impl crate::CacheObserver for RecordingObserver {
+        fn on_revalidation(&self, cache_key: &str, duration: Duration) {
+            self.revalidations
+                .lock()
+                .unwrap()
+                .push((cache_key.to_string(), duration));
+        }
+        fn on_mode_resolved(&self, url: &Url, mode: CacheMode) {
+            self.modes.lock().unwrap().push((url.clone(), mode));
+        }
+        fn on_content_changed(
+            &self,
+            cache_key: &str,
+            old: &HttpResponse,
+            new: &HttpResponse,
+        ) {
+            self.changes.lock().unwrap().push((
+                cache_key.to_string(),
+                old.body.as_bytes().unwrap().to_vec(),
+                new.body.as_bytes().unwrap().to_vec(),
+            ));
+        }
+        fn on_not_modified_body(&self, cache_key: &str, body: &[u8]) {
+            self.not_modified_bodies
+                .lock()
+                .unwrap()
+                .push((cache_key.to_string(), body.to_vec()));
+        }
+    }
+
+    #[async_test]
+    async fn revalidation_duration() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let observer = Arc::new(RecordingObserver::default());
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                observer: Some(observer.clone()),
+                ..Default::default()
+            },
+        );
+        let respond = move |req: &request::Parts| {
+            if req.headers.contains_key(http::header::IF_NONE_MATCH) {
+                std::thread::sleep(Duration::from_millis(20));
+                build_response(url, 304, &[("etag", "\"abc\"")], b"")
+            } else {
+                build_response(
+                    url,
+                    200,
+                    &[("cache-control", "no-cache"), ("etag", "\"abc\"")],
+                    TEST_BODY,
+                )
+            }
+        };
+        for _ in 0..2 {
+            cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    respond,
+                ))
+                .await?;
+        }
+        let revalidations = observer.revalidations.lock().unwrap();
+        assert_eq!(revalidations.len(), 1);
+        assert_eq!(revalidations[0].0, format!("{}:{}", GET, url));
+        assert!(revalidations[0].1 >= Duration::from_millis(20));
+        Ok(())
+    }
+
+    #[async_test]
+    async fn vary_matcher() -> Result<()> {
+        let url = "http://example.com/";
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", CACHEABLE_PUBLIC), ("vary", "Accept")],
+                TEST_BODY,
+            )
+        };
+        let matcher: crate::VaryMatcher = Arc::new(
+            |name: &http::HeaderName, stored: &str, requested: &str| {
+                name == http::header::ACCEPT
+                    && stored == "*/*"
+                    && requested.split(',').any(|t| t.trim() == "*/*")
+            },
+        );
+        for (vary_matcher, fetched) in [(None, 2), (Some(matcher), 1)] {
+            let tmp = tempfile::tempdir()?;
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions {
+                    store_request: true,
+                    vary_matcher,
+                    ..Default::default()
+                },
+            );
+            for accept in ["*/*", "text/html, */*"] {
+                cache
+                    .run(TestMiddleware::new(
+                        http::Request::get(url)
+                            .header(http::header::ACCEPT, accept)
+                            .body(())?,
+                        fetches.clone(),
+                        respond,
+                    ))
+                    .await?;
+            }
+            assert_eq!(fetches.load(Ordering::SeqCst), fetched);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn max_concurrent_fetches() -> Result<()> {
+        use crate::{FetchLimit, FnMiddleware};
+
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                max_concurrent_fetches: Some(FetchLimit::new(2)),
+                ..Default::default()
+            },
+        );
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let requests = (0..6).map(|i| {
+            let in_flight = in_flight.clone();
+            let max_in_flight = max_in_flight.clone();
+            let url = format!("http://example.com/{i}");
+            let cache = &cache;
+            async move {
+                let parts = http::Request::get(url).body(())?.into_parts().0;
+                cache
+                    .run(FnMiddleware::new(parts, move |_| {
+                        let in_flight = in_flight.clone();
+                        let max_in_flight = max_in_flight.clone();
+                        async move {
+                            let now = in_flight.fetch_add(1, Ordering::SeqCst);
+                            max_in_flight.fetch_max(now + 1, Ordering::SeqCst);
+                            async_std::task::sleep(Duration::from_millis(20))
+                                .await;
+                            in_flight.fetch_sub(1, Ordering::SeqCst);
+                            Ok(http::Response::new(TEST_BODY.to_vec()))
+                        }
+                    }))
+                    .await
+            }
+        });
+        for res in futures::future::join_all(requests).await {
+            res?;
+        }
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cacache_partial_write_is_miss() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let manager = CACacheManager { path: tmp.path().into() };
+
+        // The head claims more bytes than were written
+        let mut truncated = 1000u32.to_le_bytes().to_vec();
+        truncated.extend_from_slice(b"partial");
+        cacache::write(tmp.path(), "truncated", truncated).await?;
+        assert!(manager.get("truncated").await?.is_none());
+
+        // The index references content that was never committed
+        cacache::index::insert_async(
+            tmp.path(),
+            "dangling",
+            cacache::WriteOpts::new()
+                .integrity("sha256-deadbeef".parse()?)
+                .size(4),
+        )
+        .await?;
+        assert!(manager.get("dangling").await?.is_none());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cache_redirects() -> Result<()> {
+        let url = "http://example.com/old";
+        let location = "http://example.com/new";
+        for (status, cache_control, stored) in [
+            (301, "max-age=3600", true),
+            (308, "max-age=3600", true),
+            (307, "max-age=3600", true),
+            (302, "no-transform", false),
+        ] {
+            let tmp = tempfile::tempdir()?;
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let cache = build_cache(&tmp, HttpCacheOptions::default());
+            for _ in 0..2 {
+                let res = cache
+                    .run(TestMiddleware::new(
+                        http::Request::get(url).body(())?,
+                        fetches.clone(),
+                        move |_| {
+                            build_response(
+                                url,
+                                status,
+                                &[
+                                    ("cache-control", cache_control),
+                                    ("location", location),
+                                ],
+                                b"",
+                            )
+                        },
+                    ))
+                    .await?;
+                assert_eq!(res.parts.status, status);
+                assert_eq!(
+                    res.parts.headers.get("location").unwrap(),
+                    location
+                );
+            }
+            let expected = if stored { 1 } else { 2 };
+            assert_eq!(fetches.load(Ordering::SeqCst), expected, "{status}");
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn key_for_parts() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let parts = http::Request::get("http://example.com/items")
+            .body(())?
+            .into_parts()
+            .0;
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        assert_eq!(cache.key_for_parts(&parts), "GET:http://example.com/items");
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                cache_key: Some(Arc::new(|parts: &request::Parts| {
+                    format!("custom:{}", parts.uri.path())
+                })),
+                key_version: 2,
+                ..Default::default()
+            },
+        );
+        assert_eq!(cache.key_for_parts(&parts), "custom:/items");
+        Ok(())
+    }
+
+    #[async_test]
+    async fn no_cache_origin_failure() -> Result<()> {
+        use crate::{BadHeader, FnMiddleware};
+
+        let tmp = tempfile::tempdir()?;
+        let mut cache = build_cache(&tmp, HttpCacheOptions::default());
+        cache.mode = CacheMode::NoCache;
+        for (path, cache_control, served) in [
+            ("/stale", CACHEABLE_PUBLIC, true),
+            (
+                "/must-revalidate",
+                "max-age=86400, public, must-revalidate",
+                false,
+            ),
+        ] {
+            let url = format!("http://example.com{path}");
+            let fail = Arc::new(AtomicBool::new(false));
+            let run = || -> Result<_> {
+                let fail = fail.clone();
+                Ok(cache.run(FnMiddleware::new(
+                    http::Request::get(&url).body(())?.into_parts().0,
+                    move |_: request::Parts| {
+                        let fail = fail.load(Ordering::SeqCst);
+                        async move {
+                            if fail {
+                                return Err(BadHeader.into());
+                            }
+                            Ok(http::Response::builder()
+                                .header(CACHE_CONTROL, cache_control)
+                                .body(bytes::Bytes::from_static(TEST_BODY))?)
+                        }
+                    },
+                )))
+            };
+            run()?.await?;
+            fail.store(true, Ordering::SeqCst);
+            let res = run()?.await;
+            assert_eq!(res.is_ok(), served);
+            if let Ok(res) = res {
+                assert_eq!(res.warning_code(), Some(111));
+                assert_eq!(res.body.bytes().await?, TEST_BODY);
+            }
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn key_format() -> Result<()> {
+        use crate::KeyFormat;
+
+        let url = "http://example.com:8080/items?page=2";
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                key_version: 2,
+                key_format: KeyFormat {
+                    separator: "|".to_string(),
+                    explicit_authority: true,
+                },
+                ..Default::default()
+            },
+        );
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", CACHEABLE_PUBLIC)],
+                TEST_BODY,
+            )
+        };
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                Arc::new(AtomicUsize::new(0)),
+                respond,
+            ))
+            .await?;
+        let key = "GET|http|example.com:8080|/items?page=2";
+        assert_eq!(
+            cache.key_for_parts(
+                &http::Request::get(url).body(())?.into_parts().0
+            ),
+            key
+        );
+        let manager = CACacheManager { path: tmp.path().into() };
+        assert!(manager.get(&format!("v2|{key}")).await?.is_some());
+        assert!(manager.get(&format!("v2:{}:{}", GET, url)).await?.is_none());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn synthesized_date_ages() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let start = SystemTime::now();
+        let offset = Arc::new(AtomicU64::new(0));
+        let clock = offset.clone();
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                clock: Some(Arc::new(move || {
+                    start + Duration::from_secs(clock.load(Ordering::SeqCst))
+                })),
+                ..Default::default()
+            },
+        );
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", "max-age=3600")],
+                TEST_BODY,
+            )
+        };
+        let run = || {
+            cache.run(TestMiddleware::new(
+                http::Request::get(url).body(()).unwrap(),
+                fetches.clone(),
+                respond,
+            ))
+        };
+        let res = run().await?;
+        assert_eq!(
+            res.parts.headers.get("date"),
+            Some(&httpdate::fmt_http_date(start))
+        );
+        offset.store(1800, Ordering::SeqCst);
+        run().await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        offset.store(7200, Ordering::SeqCst);
+        run().await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        // The refetched response was received at the time of the clock
+        offset.store(9000, Ordering::SeqCst);
+        run().await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    struct UnavailableManager(Arc<AtomicBool>, CACacheManager);
+
+    impl UnavailableManager {
+        fn check(&self) -> Result<()> {
+            if self.0.load(Ordering::SeqCst) {
+                return Err("manager unavailable".into());
+            }
+            Ok(())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl CacheManager for UnavailableManager {
+        async fn get(
+            &self,
+            cache_key: &str,
+        ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+            self.check()?;
+            self.1.get(cache_key).await
+        }
+        async fn put(
+            &self,
+            cache_key: String,
+            res: HttpResponse,
+            policy: CachePolicy,
+        ) -> Result<HttpResponse> {
+            self.check()?;
+            self.1.put(cache_key, res, policy).await
+        }
+        async fn delete(&self, cache_key: &str) -> Result<()> {
+            self.check()?;
+            self.1.delete(cache_key).await
+        }
+    }
+
+    #[async_test]
+    async fn fallback_manager() -> Result<()> {
+        use crate::FallbackManager;
+
+        let url = "http://example.com/";
+        let primary_tmp = tempfile::tempdir()?;
+        let secondary_tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let unavailable = Arc::new(AtomicBool::new(false));
+        let cache = HttpCache {
+            mode: CacheMode::Default,
+            manager: FallbackManager::new(
+                UnavailableManager(
+                    unavailable.clone(),
+                    CACacheManager { path: primary_tmp.path().into() },
+                ),
+                CACacheManager { path: secondary_tmp.path().into() },
+            ),
+            options: HttpCacheOptions {
+                store_request: true,
+                ..Default::default()
+            },
+        };
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", CACHEABLE_PUBLIC)],
+                TEST_BODY,
+            )
+        };
+        let run = || {
+            cache.run(TestMiddleware::new(
+                http::Request::get(url).body(()).unwrap(),
+                fetches.clone(),
+                respond,
+            ))
+        };
+        run().await?;
+        let key = format!("{}:{}", GET, url);
+        // Both managers receive the stored request
+        let (_, _, request) =
+            cache.manager.secondary.get_with_request(&key).await?.unwrap();
+        assert!(request.is_some());
+
+        // An erroring primary falls back to the copy in the secondary
+        unavailable.store(true, Ordering::SeqCst);
+        let res = run().await?;
+        assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "HIT");
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.manager.keys().await?, vec![key.clone()]);
+        assert!(cache.manager.touch(&key, Duration::from_secs(60)).await?);
+
+        // A miss in the primary doesn't
+        unavailable.store(false, Ordering::SeqCst);
+        cache.manager.primary.delete(&key).await?;
+        run().await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn box_middleware() -> Result<()> {
+        use crate::FnMiddleware;
+
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", CACHEABLE_PUBLIC)],
+                TEST_BODY,
+            )
+        };
+        let middlewares: Vec<BoxMiddleware> = vec![
+            Box::new(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                respond,
+            )),
+            Box::new(FnMiddleware::new(
+                http::Request::get(url).body(())?.into_parts().0,
+                |_: request::Parts| async {
+                    Ok(http::Response::new(bytes::Bytes::new()))
+                },
+            )),
+        ];
+        for (middleware, expected) in
+            middlewares.into_iter().zip(["MISS", "HIT"])
+        {
+            let res = cache.run(middleware).await?;
+            assert_eq!(res.parts.headers.get(XCACHE).unwrap(), expected);
+            assert_eq!(res.body.bytes().await?, TEST_BODY);
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn non_http_scheme_not_cached() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                cache_status_headers: false,
+                ..Default::default()
+            },
+        );
+        for (url, cacheable) in
+            [("ftp://example.com/file", false), ("https://example.com/", true)]
+        {
+            let middleware = TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                Arc::new(AtomicUsize::new(0)),
+                move |_: &request::Parts| {
+                    build_response(
+                        url,
+                        200,
+                        &[("cache-control", CACHEABLE_PUBLIC)],
+                        TEST_BODY,
+                    )
+                },
+            );
+            assert_eq!(cache.can_cache_request(&middleware)?, cacheable);
+            cache.run(middleware).await?;
+            let manager = CACacheManager { path: tmp.path().into() };
+            let stored = manager.get(&format!("{}:{}", GET, url)).await?;
+            assert_eq!(stored.is_some(), cacheable);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn warning_agent_for_hostless_url() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let mut cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                warning_agent: Some("http-cache".to_string()),
+                ..Default::default()
+            },
+        );
+        // The stored url is taken from the response, which may lack a host
+        let respond = |_: &request::Parts| {
+            build_response(
+                "data:text/plain,test",
+                200,
+                &[("cache-control", CACHEABLE_PUBLIC)],
+                TEST_BODY,
+            )
+        };
+        let fetches = Arc::new(AtomicUsize::new(0));
+        for mode in [CacheMode::Default, CacheMode::ForceCache] {
+            cache.mode = mode;
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    respond,
+                ))
+                .await?;
+            if mode == CacheMode::ForceCache {
+                assert!(
+                    res.parts.headers["warning"].starts_with("112 http-cache ")
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cache_preflight_responses() -> Result<()> {
+        let url = "http://example.com/api";
+        let cors_headers = [
+            ("access-control-allow-origin", "https://app.example.com"),
+            ("access-control-allow-methods", "GET, POST, DELETE"),
+            ("access-control-allow-headers", "content-type, x-requested-with"),
+            ("access-control-max-age", "600"),
+        ];
+        let respond = move |_: &request::Parts| {
+            build_response(url, 204, &cors_headers, b"")
+        };
+        for (cacheable_methods, expected_fetches) in
+            [(vec![], 2), (vec![http::Method::OPTIONS], 1)]
+        {
+            let tmp = tempfile::tempdir()?;
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions {
+                    cacheable_methods,
+                    cache_status_headers: false,
+                    ..Default::default()
+                },
+            );
+            for _ in 0..2 {
+                let res = cache
+                    .run(TestMiddleware::new(
+                        http::Request::options(url)
+                            .header("origin", "https://app.example.com")
+                            .header("access-control-request-method", "DELETE")
+                            .body(())?,
+                        fetches.clone(),
+                        respond,
+                    ))
+                    .await?;
+                for (name, value) in cors_headers {
+                    assert_eq!(res.parts.headers[name], value);
+                }
+            }
+            assert_eq!(fetches.load(Ordering::SeqCst), expected_fetches);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "cacache-async-std")]
+    #[async_test]
+    async fn stale_grace() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let start = SystemTime::now();
+        let offset = Arc::new(AtomicU64::new(0));
+        let clock = offset.clone();
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                stale_grace: Some(Duration::from_secs(60)),
+                spawn: Some(Arc::new(|task| {
+                    async_std::task::spawn(task);
+                })),
+                clock: Some(Arc::new(move || {
+                    start + Duration::from_secs(clock.load(Ordering::SeqCst))
+                })),
+                ..Default::default()
+            },
+        );
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", "max-age=60")],
+                TEST_BODY,
+            )
+        };
+        let run = || {
+            cache.run(TestMiddleware::new(
+                http::Request::get(url).body(()).unwrap(),
+                fetches.clone(),
+                respond,
+            ))
+        };
+        run().await?;
+
+        // Within the grace period the stale response is served right away
+        offset.store(90, Ordering::SeqCst);
+        let res = run().await?;
+        assert_eq!(res.warning_code(), Some(110));
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        for _ in 0..20 {
+            if fetches.load(Ordering::SeqCst) == 2 {
+                break;
+            }
+            async_std::task::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        // Beyond it the response refreshed in the background is revalidated first
+        offset.store(300, Ordering::SeqCst);
+        let res = run().await?;
+        assert_eq!(res.warning_code(), None);
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn instance_name() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                instance_name: Some("node-3".to_string()),
+                ..Default::default()
+            },
+        );
+        let fetches = Arc::new(AtomicUsize::new(0));
+        for expected in ["MISS from node-3", "HIT from node-3"] {
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    move |_: &request::Parts| {
+                        build_response(
+                            url,
+                            200,
+                            &[("cache-control", CACHEABLE_PUBLIC)],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+                .await?;
+            assert_eq!(res.parts.headers[XCACHE], expected);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn mode_resolved() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let observer = Arc::new(RecordingObserver::default());
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                observer: Some(observer.clone()),
+                cache_mode_fn: Some(Arc::new(|parts: &request::Parts| {
+                    if parts.uri.path() == "/reload" {
+                        CacheMode::Reload
+                    } else {
+                        CacheMode::Default
+                    }
+                })),
+                ..Default::default()
+            },
+        );
+        for (req, expected) in [
+            (http::Request::get("http://example.com/"), CacheMode::Default),
+            (
+                http::Request::get("http://example.com/reload"),
+                CacheMode::Reload,
+            ),
+            (
+                http::Request::get("http://example.com/")
+                    .header(PRAGMA, "no-cache"),
+                CacheMode::NoCache,
+            ),
+        ] {
+            let req = req.body(())?;
+            let url = Url::parse(&req.uri().to_string())?;
+            observer.modes.lock().unwrap().clear();
+            cache
+                .run(TestMiddleware::new(
+                    req,
+                    Arc::new(AtomicUsize::new(0)),
+                    move |parts: &request::Parts| {
+                        build_response(
+                            &parts.uri.to_string(),
+                            200,
+                            &[("cache-control", CACHEABLE_PUBLIC)],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+                .await?;
+            assert_eq!(*observer.modes.lock().unwrap(), [(url, expected)]);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn offline_serves_stale() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let offline = Arc::new(AtomicBool::new(false));
+        let is_offline = offline.clone();
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                is_offline: Some(Arc::new(move || {
+                    is_offline.load(Ordering::SeqCst)
+                })),
+                ..Default::default()
+            },
+        );
+        for (path, cache_control, revalidated) in [
+            ("/stale", "no-cache", false),
+            ("/must-revalidate", "max-age=0, must-revalidate", true),
+        ] {
+            let url = format!("http://example.com{path}");
+            let run = |url: String| {
+                cache.run(TestMiddleware::new(
+                    http::Request::get(&url).body(()).unwrap(),
+                    fetches.clone(),
+                    move |_: &request::Parts| {
+                        build_response(
+                            &url,
+                            200,
+                            &[("cache-control", cache_control)],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+            };
+            offline.store(false, Ordering::SeqCst);
+            run(url.clone()).await?;
+            fetches.store(0, Ordering::SeqCst);
+            offline.store(true, Ordering::SeqCst);
+            let res = run(url).await?;
+            assert_eq!(fetches.load(Ordering::SeqCst) == 1, revalidated);
+            assert_eq!(res.warning_code() == Some(112), !revalidated);
+            assert_eq!(res.body.bytes().await?, TEST_BODY);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn request_max_stale() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let start = SystemTime::now();
+        let offset = Arc::new(AtomicU64::new(0));
+        let clock = offset.clone();
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                clock: Some(Arc::new(move || {
+                    start + Duration::from_secs(clock.load(Ordering::SeqCst))
+                })),
+                ..Default::default()
+            },
+        );
+        let run = |req: request::Builder| {
+            let middleware = req.body(()).map(|req| {
+                TestMiddleware::new(req, fetches.clone(), move |_| {
+                    build_response(
+                        url,
+                        200,
+                        &[("cache-control", "max-age=60")],
+                        TEST_BODY,
+                    )
+                })
+            });
+            let cache = &cache;
+            async move { cache.run(middleware?).await }
+        };
+        run(http::Request::get(url)).await?;
+        offset.store(90, Ordering::SeqCst);
+
+        // Stale for 30 seconds, which the first request accepts
+        let res =
+            run(http::Request::get(url).header(CACHE_CONTROL, "max-stale=60"))
+                .await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(res.warning_code(), Some(110));
+        run(http::Request::get(url).header(CACHE_CONTROL, "max-stale=10"))
+            .await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn received_at() -> Result<()> {
+        let url = "http://example.com/";
+        let respond = move |_: &request::Parts| {
+            build_response(
+                url,
+                200,
+                &[("cache-control", "max-age=60")],
+                TEST_BODY,
+            )
+        };
+        for (received_ago, expected_fetches) in [(0, 1), (120, 2)] {
+            let tmp = tempfile::tempdir()?;
+            let cache = build_cache(&tmp, HttpCacheOptions::default());
+            let fetches = Arc::new(AtomicUsize::new(0));
+            for _ in 0..2 {
+                let mut middleware = TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    respond,
+                );
+                middleware.received_at =
+                    Some(SystemTime::now() - Duration::from_secs(received_ago));
+                cache.run(middleware).await?;
+            }
+            assert_eq!(fetches.load(Ordering::SeqCst), expected_fetches);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn prefer_expires() -> Result<()> {
+        let url = "http://example.com/";
+        let now = SystemTime::now();
+        let in_an_hour =
+            httpdate::fmt_http_date(now + Duration::from_secs(3600));
+        let an_hour_ago =
+            httpdate::fmt_http_date(now - Duration::from_secs(3600));
+        for (max_age, expires, prefer_expires, expected_fetches) in [
+            ("max-age=0", &in_an_hour, false, 2),
+            ("max-age=0", &in_an_hour, true, 1),
+            ("max-age=3600", &an_hour_ago, false, 1),
+            ("max-age=3600", &an_hour_ago, true, 2),
+        ] {
+            let tmp = tempfile::tempdir()?;
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions { prefer_expires, ..Default::default() },
+            );
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let cache_control = format!("public, {max_age}");
+            for _ in 0..2 {
+                let headers = [
+                    ("cache-control".to_string(), cache_control.clone()),
+                    ("expires".to_string(), expires.clone()),
+                ];
+                cache
+                    .run(TestMiddleware::new(
+                        http::Request::get(url).body(())?,
+                        fetches.clone(),
+                        move |_: &request::Parts| {
+                            let headers: Vec<_> = headers
+                                .iter()
+                                .map(|(k, v)| (k.as_str(), v.as_str()))
+                                .collect();
+                            build_response(url, 200, &headers, TEST_BODY)
+                        },
+                    ))
+                    .await?;
+            }
+            assert_eq!(fetches.load(Ordering::SeqCst), expected_fetches);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cache_info() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                cache_info: true,
+                cache_status_headers: false,
+                ..Default::default()
+            },
+        );
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let mut infos = Vec::new();
+        for _ in 0..2 {
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    move |_| {
+                        build_response(
+                            url,
+                            200,
+                            &[("cache-control", CACHEABLE_PUBLIC)],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+                .await?;
+            assert!(!res.parts.headers.contains_key(XCACHE));
+            infos.push(res.cache_info().cloned().unwrap());
+        }
+        let key = format!("{}:{}", GET, url);
+        assert!(matches!(infos[0].hit_or_miss, HitOrMiss::MISS));
+        assert!(matches!(infos[1].hit_or_miss, HitOrMiss::HIT));
+        for info in &infos {
+            assert_eq!(info.key.as_deref(), Some(key.as_str()));
+            assert!(info.age.unwrap() < Duration::from_secs(60));
+            assert!(info.ttl.unwrap() > Duration::from_secs(86000));
+        }
+
+        // Uncacheable requests have no key
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::post(url).body(())?,
+                fetches.clone(),
+                move |_| build_response(url, 200, &[], TEST_BODY),
+            ))
+            .await?;
+        let info = res.cache_info().unwrap();
+        assert!(matches!(info.hit_or_miss, HitOrMiss::MISS));
+        assert!(info.key.is_none());
+
+        // Disabled by default
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches,
+                move |_| build_response(url, 200, &[], TEST_BODY),
+            ))
+            .await?;
+        assert!(res.cache_info().is_none());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn only_if_cached_fresh() -> Result<()> {
+        let fresh_url = "http://example.com/fresh";
+        let stale_url = "http://example.com/stale";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let mut cache = build_cache(&tmp, HttpCacheOptions::default());
+        for (url, cache_control) in
+            [(fresh_url, CACHEABLE_PUBLIC), (stale_url, "public, max-age=0")]
+        {
+            cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    move |_| {
+                        build_response(
+                            url,
+                            200,
+                            &[("cache-control", cache_control)],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+                .await?;
+        }
+
+        cache.mode = CacheMode::OnlyIfCachedFresh;
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(fresh_url).body(())?,
+                fetches.clone(),
+                move |_| build_response(fresh_url, 500, &[], b"error"),
+            ))
+            .await?;
+        assert_eq!(res.parts.status, 200);
+        assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "HIT");
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(stale_url).body(())?,
+                fetches.clone(),
+                move |_| build_response(stale_url, 500, &[], b"error"),
+            ))
+            .await?;
+        assert_eq!(res.parts.status, 504);
+        assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "MISS");
+        assert_eq!(res.parts.headers.get(crate::XCACHELOOKUP).unwrap(), "HIT");
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        // Still served by OnlyIfCached
+        cache.mode = CacheMode::OnlyIfCached;
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(stale_url).body(())?,
+                fetches.clone(),
+                move |_| build_response(stale_url, 500, &[], b"error"),
+            ))
+            .await?;
+        assert_eq!(res.parts.status, 200);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn get_put_many() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let manager = CACacheManager { path: tmp.path().into() };
+        let urls = ["http://example.com/a", "http://example.com/b"];
+        let mut entries = Vec::new();
+        for url in urls {
+            let req = http::Request::get(url).body(())?;
+            let origin_res = http::Response::builder()
+                .status(200)
+                .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                .body(())?;
+            entries.push((
+                format!("{}:{}", GET, url),
+                build_response(url, 200, &[], url.as_bytes())?,
+                CachePolicy::new(&req, &origin_res),
+            ));
+        }
+        let stored = manager.put_many(entries).await?;
+        assert_eq!(stored.len(), 2);
+
+        let keys = [urls[1], "http://example.com/missing", urls[0]]
+            .map(|url| format!("{}:{}", GET, url));
+        let records = manager.get_many(&keys).await?;
+        assert_eq!(records.len(), 3);
+        let mut records = records.into_iter();
+        let (res, _) = records.next().unwrap().unwrap();
+        assert_eq!(res.body.bytes().await?, urls[1].as_bytes());
+        assert!(records.next().unwrap().is_none());
+        let (res, _) = records.next().unwrap().unwrap();
+        assert_eq!(res.body.bytes().await?, urls[0].as_bytes());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn clock_skew_tolerance() -> Result<()> {
+        let url = "http://example.com/";
+        let now = SystemTime::now();
+        // The server clock runs a minute ahead, but Expires was set by a
+        // correct one
+        let date = httpdate::fmt_http_date(now + Duration::from_secs(60));
+        let expires = httpdate::fmt_http_date(now + Duration::from_secs(30));
+        for (tolerance, expected_fetches) in [(0, 2), (10, 2), (120, 1)] {
+            let tmp = tempfile::tempdir()?;
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions {
+                    clock_skew_tolerance: Duration::from_secs(tolerance),
+                    ..Default::default()
+                },
+            );
+            let fetches = Arc::new(AtomicUsize::new(0));
+            for _ in 0..2 {
+                let headers = [
+                    ("cache-control".to_string(), "public".to_string()),
+                    ("date".to_string(), date.clone()),
+                    ("expires".to_string(), expires.clone()),
+                ];
+                let res = cache
+                    .run(TestMiddleware::new(
+                        http::Request::get(url).body(())?,
+                        fetches.clone(),
+                        move |_: &request::Parts| {
+                            let headers: Vec<_> = headers
+                                .iter()
+                                .map(|(k, v)| (k.as_str(), v.as_str()))
+                                .collect();
+                            build_response(url, 200, &headers, TEST_BODY)
+                        },
+                    ))
+                    .await?;
+                // Served with the Date sent by the origin
+                assert_eq!(res.parts.headers["date"], date);
+            }
+            assert_eq!(fetches.load(Ordering::SeqCst), expected_fetches);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn logging_manager() -> Result<()> {
+        use crate::{AccessOutcome, CacheOperation, LoggingManager};
+
+        let url = "http://example.com/";
+        let key = format!("{}:{}", GET, url);
+        let tmp = tempfile::tempdir()?;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let manager = LoggingManager::new(
+            FailingDeleteManager(CACacheManager { path: tmp.path().into() }),
+            {
+                let log = log.clone();
+                move |op: CacheOperation, key: &str, outcome: AccessOutcome| {
+                    let outcome = match outcome {
+                        AccessOutcome::Failed(e) => e.to_string(),
+                        outcome => format!("{outcome:?}"),
+                    };
+                    log.lock().unwrap().push((op, key.to_string(), outcome));
+                }
+            },
+        );
+        let req = http::Request::get(url).body(())?;
+        let origin_res = http::Response::builder()
+            .status(200)
+            .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+            .body(())?;
+        assert!(manager.get(&key).await?.is_none());
+        let res = manager
+            .put(
+                key.clone(),
+                build_response(url, 200, &[], TEST_BODY)?,
+                CachePolicy::new(&req, &origin_res),
+            )
+            .await?;
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        assert!(manager.get(&key).await?.is_some());
+        assert!(manager.delete(&key).await.is_err());
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                (CacheOperation::Get, key.clone(), "Miss".to_string()),
+                (CacheOperation::Put, key.clone(), "Done".to_string()),
+                (CacheOperation::Get, key.clone(), "Hit".to_string()),
+                (CacheOperation::Delete, key, "delete failed".to_string()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[async_test]
+    async fn stored_header_limits() -> Result<()> {
+        let url = "http://example.com/";
+        let bomb: Vec<_> = (0..100)
+            .map(|i| (format!("x-junk-{i:03}"), "x".repeat(50)))
+            .collect();
+        for (max_bytes, max_count, drop_excess, expected_fetches) in [
+            (None, None, false, 1),
+            (Some(1024), None, false, 2),
+            (None, Some(10), false, 2),
+            (Some(1024), Some(10), true, 1),
+        ] {
+            let tmp = tempfile::tempdir()?;
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions {
+                    max_stored_header_bytes: max_bytes,
+                    max_stored_header_count: max_count,
+                    drop_excess_headers: drop_excess,
+                    ..Default::default()
+                },
+            );
+            let fetches = Arc::new(AtomicUsize::new(0));
+            for _ in 0..2 {
+                let bomb = bomb.clone();
+                let res = cache
+                    .run(TestMiddleware::new(
+                        http::Request::get(url).body(())?,
+                        fetches.clone(),
+                        move |_: &request::Parts| {
+                            let mut headers: Vec<_> = bomb
+                                .iter()
+                                .map(|(k, v)| (k.as_str(), v.as_str()))
+                                .collect();
+                            headers.push(("cache-control", CACHEABLE_PUBLIC));
+                            build_response(url, 200, &headers, TEST_BODY)
+                        },
+                    ))
+                    .await?;
+                assert_eq!(res.body.bytes().await?, TEST_BODY);
+            }
+            assert_eq!(fetches.load(Ordering::SeqCst), expected_fetches);
+            if drop_excess {
+                let (res, _) = cache
+                    .manager
+                    .get(&format!("{}:{}", GET, url))
+                    .await?
+                    .unwrap();
+                assert!(res.parts.headers.len() <= 10);
+                assert_eq!(
+                    res.parts.headers.get("cache-control").unwrap(),
+                    CACHEABLE_PUBLIC
+                );
+            }
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cache_status_headers_fn() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                cache_status_headers: false,
+                cache_status_headers_fn: Some(Arc::new(|parts| {
+                    parts.uri.path() != "/quiet"
+                })),
+                ..Default::default()
+            },
+        );
+        let fetches = Arc::new(AtomicUsize::new(0));
+        for (url, expected) in [
+            ("http://example.com/quiet", None),
+            ("http://example.com/loud", Some("MISS")),
+            ("http://example.com/quiet", None),
+            ("http://example.com/loud", Some("HIT")),
+        ] {
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    move |_| {
+                        build_response(
+                            url,
+                            200,
+                            &[("cache-control", CACHEABLE_PUBLIC)],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+                .await?;
+            assert_eq!(
+                res.parts.headers.get(XCACHE).map(String::as_str),
+                expected
+            );
+            assert_eq!(
+                res.parts.headers.contains_key(crate::XCACHELOOKUP),
+                expected.is_some()
+            );
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn identity_fn() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                identity_fn: Some(Arc::new(|parts| {
+                    parts
+                        .headers
+                        .get("x-user")
+                        .and_then(|user| user.to_str().ok())
+                        .map(str::to_string)
+                })),
+                cache_options: Some(CacheOptions {
+                    shared: false,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        let fetches = Arc::new(AtomicUsize::new(0));
+        for (user, expected_body) in
+            [("alice", "alice"), ("bob", "bob"), ("alice", "alice")]
+        {
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).header("x-user", user).body(())?,
+                    fetches.clone(),
+                    move |_| {
+                        build_response(
+                            url,
+                            200,
+                            &[("cache-control", "private, max-age=86400")],
+                            user.as_bytes(),
+                        )
+                    },
+                ))
+                .await?;
+            assert_eq!(res.body.bytes().await?, expected_body.as_bytes());
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert!(cache
+            .manager
+            .get(&format!("{}:{}:bob", GET, url))
+            .await?
+            .is_some());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn store_content_location() -> Result<()> {
+        let url = "http://example.com/docs";
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                store_content_location: true,
+                store_request: true,
+                ..Default::default()
+            },
+        );
+        let fetches = Arc::new(AtomicUsize::new(0));
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                move |_| {
+                    build_response(
+                        url,
+                        200,
+                        &[
+                            ("cache-control", CACHEABLE_PUBLIC),
+                            ("content-location", "/docs.en"),
+                        ],
+                        TEST_BODY,
+                    )
+                },
+            ))
+            .await?;
+        // The copy is stored along with the request that produced it
+        let (_, _, request) = cache
+            .manager
+            .get_with_request("GET:http://example.com/docs.en")
+            .await?
+            .unwrap();
+        assert_eq!(request.unwrap().uri, url);
+
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get("http://example.com/docs.en").body(())?,
+                fetches.clone(),
+                move |_| build_response(url, 500, &[], b"error"),
+            ))
+            .await?;
+        assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "HIT");
+        assert_eq!(res.parts.url.as_str(), "http://example.com/docs.en");
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        // Cross-origin locations are ignored
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                store_content_location: true,
+                ..Default::default()
+            },
+        );
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                move |_| {
+                    build_response(
+                        url,
+                        200,
+                        &[
+                            ("cache-control", CACHEABLE_PUBLIC),
+                            (
+                                "content-location",
+                                "http://other.example.com/docs.en",
+                            ),
+                        ],
+                        TEST_BODY,
+                    )
+                },
+            ))
+            .await?;
+        assert_eq!(cache.manager.keys().await?.len(), 1);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn unsafe_method_invalidates_locations() -> Result<()> {
+        let items = "http://example.com/items";
+        let item = "http://example.com/items/1";
+        let item_json = "http://example.com/items/1.json";
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let fetches = Arc::new(AtomicUsize::new(0));
+        for url in [item, item_json] {
+            cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    move |_| {
+                        build_response(
+                            url,
+                            200,
+                            &[("cache-control", CACHEABLE_PUBLIC)],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+                .await?;
+        }
+        assert_eq!(cache.manager.keys().await?.len(), 2);
+
+        // A failed request doesn't invalidate anything
+        for status in [500, 201] {
+            cache
+                .run(TestMiddleware::new(
+                    http::Request::post(items).body(())?,
+                    fetches.clone(),
+                    move |_| {
+                        build_response(
+                            items,
+                            status,
+                            &[
+                                ("location", "/items/1"),
+                                ("content-location", "/items/1.json"),
+                            ],
+                            b"",
+                        )
+                    },
+                ))
+                .await?;
+            let expected = if status == 500 { 2 } else { 0 };
+            assert_eq!(cache.manager.keys().await?.len(), expected);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn body_bytes_with_hint() -> Result<()> {
+        let res = build_response(
+            "http://example.com/",
+            200,
+            &[("Content-Length", " 8 ")],
+            b"",
+        )?;
+        assert_eq!(res.parts.content_length(), Some(8));
+        assert_eq!(
+            build_response("http://example.com/", 200, &[], b"")?
+                .parts
+                .content_length(),
+            None
+        );
+
+        // The hint is capped, so a bogus Content-Length can't exhaust memory
+        for size_hint in [0, 8, 1024, usize::MAX] {
+            let chunks: Vec<Result<_>> =
+                vec![Ok(&TEST_BODY[..2]), Ok(&TEST_BODY[2..]), Ok(TEST_BODY)];
+            let body = Body::wrap_stream(futures_util::stream::iter(chunks));
+            assert_eq!(body.bytes_with_hint(size_hint).await?, b"testtest"[..]);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn content_changed() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let observer = Arc::new(RecordingObserver::default());
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                observer: Some(observer.clone()),
+                ..Default::default()
+            },
+        );
+        let versions = Arc::new(Mutex::new(
+            vec![&b"v3"[..], &b"v2"[..], &b"v2"[..], &b"v1"[..]].into_iter(),
+        ));
+        for expected in [&b"v1"[..], b"v2", b"v2", b"v3"] {
+            let versions = versions.clone();
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    move |req: &request::Parts| {
+                        let body =
+                            versions.lock().unwrap().next_back().unwrap();
+                        let etag =
+                            format!("\"{}\"", String::from_utf8_lossy(body));
+                        if req
+                            .headers
+                            .get(http::header::IF_NONE_MATCH)
+                            .is_some_and(|v| *v == etag)
+                        {
+                            return build_response(url, 304, &[], b"");
+                        }
+                        build_response(
+                            url,
+                            200,
+                            &[("cache-control", "no-cache"), ("etag", &etag)],
+                            body,
+                        )
+                    },
+                ))
+                .await?;
+            assert_eq!(res.body.bytes().await?, expected);
+        }
+        let key = format!("{}:{}", GET, url);
+        assert_eq!(
+            *observer.changes.lock().unwrap(),
+            [
+                (key.clone(), b"v1".to_vec(), b"v2".to_vec()),
+                (key, b"v2".to_vec(), b"v3".to_vec()),
+            ]
+        );
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cacheable_status_codes() -> Result<()> {
+        let url = "http://example.com/";
+        // (configured statuses, mode, status, cache-control, stored)
+        let cases: [(&[u16], CacheMode, u16, &str, bool); 8] = [
+            (&[], CacheMode::Default, 200, CACHEABLE_PUBLIC, true),
+            (&[], CacheMode::Default, 200, "no-store", false),
+            (&[], CacheMode::IgnoreRules, 200, "no-store", true),
+            (&[], CacheMode::Default, 404, CACHEABLE_PUBLIC, false),
+            (&[], CacheMode::IgnoreRules, 404, CACHEABLE_PUBLIC, false),
+            (&[404], CacheMode::Default, 404, CACHEABLE_PUBLIC, true),
+            (&[500], CacheMode::Default, 500, "no-store", false),
+            (&[500], CacheMode::IgnoreRules, 500, "no-store", true),
+        ];
+        for (statuses, mode, status, cache_control, stored) in cases {
+            let tmp = tempfile::tempdir()?;
+            let mut cache = build_cache(
+                &tmp,
+                HttpCacheOptions {
+                    cacheable_status_codes: statuses.to_vec(),
+                    ..Default::default()
+                },
+            );
+            cache.mode = mode;
+            cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    Arc::new(AtomicUsize::new(0)),
+                    move |_| {
+                        build_response(
+                            url,
+                            status,
+                            &[("cache-control", cache_control)],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+                .await?;
+            let key = format!("{}:{}", GET, url);
+            assert_eq!(
+                cache.manager.get(&key).await?.is_some(),
+                stored,
+                "{statuses:?} {mode:?} {status} {cache_control}"
+            );
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn accept_normalizer() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                accept_normalizer: Some(Arc::new(|accept: &str| {
+                    let top = accept.split(',').next().unwrap_or_default();
+                    top.split(';').next().unwrap_or_default().trim().to_string()
+                })),
+                ..Default::default()
+            },
+        );
+        let fetches = Arc::new(AtomicUsize::new(0));
+        for accept in [
+            "text/html,application/xhtml+xml;q=0.9,*/*;q=0.8",
+            "text/html, */*",
+            "application/json",
+        ] {
+            cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).header("accept", accept).body(())?,
+                    fetches.clone(),
+                    move |req: &request::Parts| {
+                        // The origin receives the header sent by the client
+                        assert_eq!(req.headers.get("accept").unwrap(), accept);
+                        build_response(
+                            url,
+                            200,
+                            &[
+                                ("cache-control", CACHEABLE_PUBLIC),
+                                ("vary", "accept"),
+                            ],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+                .await?;
+        }
+        // The two requests preferring text/html share an entry
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        // Accept headers that aren't visible ASCII bypass the normalizer
+        let opaque = http::HeaderValue::from_bytes(b"text/caf\xe9")?;
+        for _ in 0..2 {
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url)
+                        .header("accept", opaque.clone())
+                        .body(())?,
+                    fetches.clone(),
+                    move |_| {
+                        build_response(
+                            url,
+                            200,
+                            &[
+                                ("cache-control", CACHEABLE_PUBLIC),
+                                ("vary", "accept"),
+                            ],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+                .await?;
+            assert_eq!(res.body.bytes().await?, TEST_BODY);
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn service_unavailable_defers_revalidation() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let start = SystemTime::now();
+        let offset = Arc::new(AtomicU64::new(0));
+        let clock = offset.clone();
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                clock: Some(Arc::new(move || {
+                    start + Duration::from_secs(clock.load(Ordering::SeqCst))
+                })),
+                ..Default::default()
+            },
+        );
+        let run = |status: u16| {
+            cache.run(TestMiddleware::new(
+                http::Request::get(url).body(()).unwrap(),
+                fetches.clone(),
+                move |_| {
+                    if status == 503 {
+                        return build_response(
+                            url,
+                            503,
+                            &[("retry-after", "120")],
+                            b"unavailable",
+                        );
+                    }
+                    build_response(
+                        url,
+                        200,
+                        &[("cache-control", "max-age=0"), ("etag", "\"v1\"")],
+                        TEST_BODY,
+                    )
+                },
+            ))
+        };
+        run(200).await?;
+        let res = run(503).await?;
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        // The date stored along with the entry isn't returned by the manager
+        let (stored, _) =
+            cache.manager.get(&format!("{GET}:{url}")).await?.unwrap();
+        assert!(!stored
+            .parts
+            .headers
+            .contains_key("x-http-cache-deferred-until"));
+
+        // The origin isn't contacted again before Retry-After
+        offset.store(60, Ordering::SeqCst);
+        let res = run(200).await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert!(res.parts.headers["warning"].starts_with("111 "));
+        assert!(!res.parts.headers.contains_key("x-http-cache-deferred-until"));
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+
+        offset.store(180, Ordering::SeqCst);
+        run(200).await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn service_unavailable_without_honor_retry_after() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions { honor_retry_after: false, ..Default::default() },
+        );
+        let run = |status: u16| {
+            cache.run(TestMiddleware::new(
+                http::Request::get(url).body(()).unwrap(),
+                fetches.clone(),
+                move |_| {
+                    if status == 503 {
+                        return build_response(
+                            url,
+                            503,
+                            &[("retry-after", "120")],
+                            b"unavailable",
+                        );
+                    }
+                    build_response(
+                        url,
+                        200,
+                        &[("cache-control", "max-age=0"), ("etag", "\"v1\"")],
+                        TEST_BODY,
+                    )
+                },
+            ))
+        };
+        run(200).await?;
+        let res = run(503).await?;
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        // The next request revalidates right away
+        run(200).await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn service_unavailable_keeps_must_revalidate() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(&tmp, Default::default());
+        let run = |status: u16| {
+            cache.run(TestMiddleware::new(
+                http::Request::get(url).body(()).unwrap(),
+                fetches.clone(),
+                move |_| {
+                    if status == 503 {
+                        return build_response(
+                            url,
+                            503,
+                            &[("retry-after", "120")],
+                            b"unavailable",
+                        );
+                    }
+                    build_response(
+                        url,
+                        200,
+                        &[
+                            ("cache-control", "max-age=0, must-revalidate"),
+                            ("etag", "\"v1\""),
+                        ],
+                        TEST_BODY,
+                    )
+                },
+            ))
+        };
+        run(200).await?;
+        run(503).await?;
+        // A stale must-revalidate response is never served without the origin
+        let res = run(200).await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 3);
+        assert!(!res.parts.headers.contains_key("warning"));
+        Ok(())
+    }
+
+    #[async_test]
+    async fn metadata_fn() -> Result<()> {
+        let url = "http://example.com/items";
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                metadata_fn: Some(Arc::new(
+                    |parts: &request::Parts, res: &HttpResponse| {
+                        HashMap::from([
+                            ("path".to_string(), parts.uri.path().to_string()),
+                            (
+                                "status".to_string(),
+                                res.parts.status.to_string(),
+                            ),
+                        ])
+                    },
+                )),
+                ..Default::default()
+            },
+        );
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                Arc::new(AtomicUsize::new(0)),
+                move |_| {
+                    build_response(
+                        url,
+                        200,
+                        &[("cache-control", CACHEABLE_PUBLIC)],
+                        TEST_BODY,
+                    )
+                },
+            ))
+            .await?;
+        assert!(!res.parts.headers.contains_key("path"));
+
+        let key = format!("{}:{}", GET, url);
+        let (res, _, metadata) =
+            cache.manager.get_with_metadata(&key).await?.unwrap();
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        assert_eq!(metadata["path"], "/items");
+        assert_eq!(metadata["status"], "200");
+
+        // Entries stored without metadata read back an empty map
+        let (res, policy) = cache.manager.get(&key).await?.unwrap();
+        cache.manager.put(key.clone(), res, policy).await?;
+        let (_, _, metadata) =
+            cache.manager.get_with_metadata(&key).await?.unwrap();
+        assert!(metadata.is_empty());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn fallback_fn() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let mut cache = build_cache(&tmp, HttpCacheOptions::default());
+        let middleware = || {
+            TestMiddleware::new(
+                http::Request::get(url).body(()).unwrap(),
+                fetches.clone(),
+                |_| Err("connection refused".into()),
+            )
+        };
+        assert!(cache.run(middleware()).await.is_err());
+
+        cache.options.fallback_fn = Some(Arc::new(|parts: &request::Parts| {
+            let url = parts.uri.to_string();
+            Some(build_response(&url, 503, &[], b"offline").unwrap())
+        }));
+        let res = cache.run(middleware()).await?;
+        assert_eq!(res.parts.status, 503);
+        assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "MISS");
+        assert_eq!(res.body.bytes().await?, &b"offline"[..]);
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+
+        cache.options.fallback_fn = Some(Arc::new(|_: &request::Parts| None));
+        assert!(cache.run(middleware()).await.is_err());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn not_modified_keeps_body_headers() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let run = |status: u16| {
+            cache.run(TestMiddleware::new(
+                http::Request::get(url).body(()).unwrap(),
+                fetches.clone(),
+                move |_| {
+                    if status == 304 {
+                        return build_response(
+                            url,
+                            304,
+                            &[
+                                ("cache-control", "max-age=3600"),
+                                ("content-type", "application/json"),
+                                ("content-encoding", "gzip"),
+                                ("etag", "\"v1\""),
+                            ],
+                            b"",
+                        );
+                    }
+                    build_response(
+                        url,
+                        200,
+                        &[
+                            ("cache-control", "max-age=0"),
+                            ("content-type", "text/plain"),
+                            ("etag", "\"v1\""),
+                        ],
+                        TEST_BODY,
+                    )
+                },
+            ))
+        };
+        run(200).await?;
+        let res = run(304).await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(res.parts.headers["content-type"], "text/plain");
+        assert!(!res.parts.headers.contains_key("content-encoding"));
+        // Other headers of the 304 are still taken
+        assert_eq!(res.parts.headers["cache-control"], "max-age=3600");
+
+        // The stored policy doesn't carry the conflicting headers either
+        let res = run(304).await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(res.parts.headers["content-type"], "text/plain");
+        assert!(!res.parts.headers.contains_key("content-encoding"));
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cacheable_body_size_band() -> Result<()> {
+        let url = "http://example.com/";
+        let key = format!("{}:{}", GET, url);
+        let chunks = || -> Vec<Result<&'static [u8]>> {
+            vec![Ok(b"chunk one,"), Ok(b" chunk two,"), Ok(b" chunk three")]
+        };
+        // (min, max, streaming, stored)
+        let cases = [
+            (Some(5), None, false, false),
+            (Some(4), None, false, true),
+            (None, Some(3), false, false),
+            (None, Some(4), false, true),
+            (None, Some(20), true, false),
+            (None, Some(40), true, true),
+        ];
+        for (min, max, streaming, stored) in cases {
+            let tmp = tempfile::tempdir()?;
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions {
+                    min_cacheable_body_bytes: min,
+                    max_cacheable_body_bytes: max,
+                    ..Default::default()
+                },
+            );
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    Arc::new(AtomicUsize::new(0)),
+                    move |_| {
+                        let headers = [("cache-control", CACHEABLE_PUBLIC)];
+                        if !streaming {
+                            return build_response(
+                                url, 200, &headers, TEST_BODY,
+                            );
+                        }
+                        let (parts, _) =
+                            build_response(url, 200, &headers, b"")?
+                                .into_parts();
+                        Ok(HttpResponse::from_parts(
+                            parts,
+                            Body::wrap_stream(futures_util::stream::iter(
+                                chunks(),
+                            )),
+                        ))
+                    },
+                ))
+                .await?;
+            // The response is passed on whole either way
+            let expected: &[u8] = if streaming {
+                b"chunk one, chunk two, chunk three"
+            } else {
+                TEST_BODY
+            };
+            assert_eq!(res.body.bytes().await?, expected);
+            assert_eq!(
+                cache.manager.get(&key).await?.is_some(),
+                stored,
+                "{min:?} {max:?} {streaming}"
+            );
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "cacache-async-std")]
+    #[async_test]
+    async fn mirror_manager() -> Result<()> {
+        use crate::MirrorManager;
+
+        // Waits for the background writes to reach the secondary manager
+        async fn eventually(check: impl Fn() -> bool) -> bool {
+            for _ in 0..100 {
+                if check() {
+                    return true;
+                }
+                async_std::task::sleep(Duration::from_millis(10)).await;
+            }
+            false
+        }
+
+        let url = "http://example.com/";
+        let key = format!("{}:{}", GET, url);
+        let primary = tempfile::tempdir()?;
+        let secondary = tempfile::tempdir()?;
+        let manager = MirrorManager::new(
+            CACacheManager { path: primary.path().into() },
+            CACacheManager { path: secondary.path().into() },
+            Arc::new(|task| {
+                async_std::task::spawn(task);
+            }),
+        );
+        let req = http::Request::get(url).body(())?;
+        let origin_res = http::Response::builder()
+            .status(200)
+            .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+            .body(())?;
+        let res = manager
+            .put(
+                key.clone(),
+                build_response(url, 200, &[], TEST_BODY)?,
+                CachePolicy::new(&req, &origin_res),
+            )
+            .await?;
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        assert!(manager.primary.get(&key).await?.is_some());
+        let path = secondary.path().to_path_buf();
+        let stored = |path: std::path::PathBuf, key: String| {
+            move || cacache::metadata_sync(&path, &key).unwrap().is_some()
+        };
+        assert!(eventually(stored(path.clone(), key.clone())).await);
+        let (res, _) = manager.secondary.get(&key).await?.unwrap();
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+
+        manager.delete(&key).await?;
+        assert!(manager.primary.get(&key).await?.is_none());
+        let stored = stored(path, key);
+        assert!(eventually(|| !stored()).await);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn shared_cache_authorization() -> Result<()> {
+        let url = "http://example.com/";
+        for (cache_control, shared, expected_fetches) in [
+            ("max-age=86400", true, 2),
+            ("max-age=86400, private", true, 2),
+            ("max-age=86400, public", true, 1),
+            ("s-maxage=86400", true, 1),
+            ("max-age=86400, must-revalidate", true, 1),
+            ("max-age=86400", false, 1),
+        ] {
+            let tmp = tempfile::tempdir()?;
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions {
+                    cache_options: Some(CacheOptions {
+                        shared,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                },
+            );
+            let fetches = Arc::new(AtomicUsize::new(0));
+            for _ in 0..2 {
+                cache
+                    .run(TestMiddleware::new(
+                        http::Request::get(url)
+                            .header("authorization", "Bearer token")
+                            .body(())?,
+                        fetches.clone(),
+                        move |_| {
+                            build_response(
+                                url,
+                                200,
+                                &[("cache-control", cache_control)],
+                                TEST_BODY,
+                            )
+                        },
+                    ))
+                    .await?;
+            }
+            assert_eq!(
+                fetches.load(Ordering::SeqCst),
+                expected_fetches,
+                "{cache_control}, shared: {shared}"
+            );
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn entry_freshness() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let start = SystemTime::now();
+        let offset = Arc::new(AtomicU64::new(0));
+        let clock = offset.clone();
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                clock: Some(Arc::new(move || {
+                    start + Duration::from_secs(clock.load(Ordering::SeqCst))
+                })),
+                key_version: 2,
+                ..Default::default()
+            },
+        );
+        let (parts, ()) = http::Request::get(url).body(())?.into_parts();
+        let key = cache.key_for_parts(&parts);
+        assert_eq!(cache.entry_freshness(&key).await?, None);
+
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                Arc::new(AtomicUsize::new(0)),
+                move |_| {
+                    build_response(
+                        url,
+                        200,
+                        &[("cache-control", "max-age=100, public")],
+                        TEST_BODY,
+                    )
+                },
+            ))
+            .await?;
+        // The policy dates the response with the system clock
+        let rounded = |duration: Duration| duration.as_secs_f64().round();
+        offset.store(40, Ordering::SeqCst);
+        let info = cache.entry_freshness(&key).await?.unwrap();
+        assert!(!info.is_stale);
+        assert_eq!(rounded(info.age), 40.0);
+        assert_eq!(info.age + info.time_to_live, Duration::from_secs(100));
+
+        offset.store(150, Ordering::SeqCst);
+        let info = cache.entry_freshness(&key).await?.unwrap();
+        assert!(info.is_stale);
+        assert_eq!(rounded(info.age), 150.0);
+        assert_eq!(info.time_to_live, Duration::ZERO);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn cacheable_methods_require_explicit_freshness() -> Result<()> {
+        let url = "http://example.com/dav/";
+        let propfind = http::Method::from_bytes(b"PROPFIND")?;
+        for (cacheable_methods, headers, expected_fetches) in [
+            (vec![], [("cache-control", "max-age=600")], 2),
+            (vec![propfind.clone()], [("cache-control", "max-age=600")], 1),
+            (
+                vec![propfind.clone()],
+                [("expires", "Wed, 21 Oct 2099 07:28:00 GMT")],
+                1,
+            ),
+            // Heuristic freshness from Last-Modified isn't enough
+            (
+                vec![propfind.clone()],
+                [("last-modified", "Wed, 21 Oct 2015 07:28:00 GMT")],
+                2,
+            ),
+        ] {
+            let tmp = tempfile::tempdir()?;
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions { cacheable_methods, ..Default::default() },
+            );
+            for _ in 0..2 {
+                let res = cache
+                    .run(TestMiddleware::new(
+                        http::Request::builder()
+                            .method(propfind.clone())
+                            .uri(url)
+                            .header("depth", "1")
+                            .body(())?,
+                        fetches.clone(),
+                        move |_| build_response(url, 200, &headers, TEST_BODY),
+                    ))
+                    .await?;
+                assert_eq!(res.body.bytes().await?, TEST_BODY);
+            }
+            assert_eq!(
+                fetches.load(Ordering::SeqCst),
+                expected_fetches,
+                "{headers:?}"
+            );
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn unsafe_cacheable_methods_invalidate() -> Result<()> {
+        let url = "http://example.com/dav/";
+        let propfind = http::Method::from_bytes(b"PROPFIND")?;
+        for (method, invalidated) in
+            [(http::Method::POST, true), (propfind.clone(), false)]
+        {
+            let tmp = tempfile::tempdir()?;
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions {
+                    cacheable_methods: vec![
+                        http::Method::POST,
+                        propfind.clone(),
+                    ],
+                    ..Default::default()
+                },
+            );
+            for method in [http::Method::GET, method] {
+                cache
+                    .run(TestMiddleware::new(
+                        http::Request::builder()
+                            .method(method)
+                            .uri(url)
+                            .body(())?,
+                        fetches.clone(),
+                        move |_| {
+                            build_response(
+                                url,
+                                200,
+                                &[("cache-control", CACHEABLE_PUBLIC)],
+                                TEST_BODY,
+                            )
+                        },
+                    ))
+                    .await?;
+            }
+            let cached = cache.manager.get(&format!("{GET}:{url}")).await?;
+            assert_eq!(cached.is_none(), invalidated);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn revalidation_retries() -> Result<()> {
+        let url = "http://example.com/";
+        for (failures, expected_body, expected_fetches) in
+            [(2, &b"updated"[..], 4), (5, TEST_BODY, 5)]
+        {
+            let tmp = tempfile::tempdir()?;
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let sleeps = Arc::new(Mutex::new(Vec::new()));
+            let slept = sleeps.clone();
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions {
+                    revalidation_retries: 3,
+                    sleep: Some(Arc::new(move |duration| {
+                        slept.lock().unwrap().push(duration);
+                        Box::pin(async {})
+                    })),
+                    ..Default::default()
+                },
+            );
+            let count = fetches.clone();
+            let respond = move |_: &request::Parts| match count
+                .load(Ordering::SeqCst)
+            {
+                1 => build_response(
+                    url,
+                    200,
+                    &[("cache-control", "max-age=0")],
+                    TEST_BODY,
+                ),
+                n if n <= 1 + failures => build_response(url, 500, &[], b""),
+                _ => build_response(
+                    url,
+                    200,
+                    &[("cache-control", "max-age=0")],
+                    b"updated",
+                ),
+            };
+            for _ in 0..2 {
+                cache
+                    .run(TestMiddleware::new(
+                        http::Request::get(url).body(())?,
+                        fetches.clone(),
+                        respond.clone(),
+                    ))
+                    .await?;
+            }
+            let res = cache
+                .manager
+                .get(&format!("{}:{}", GET, url))
+                .await?
+                .unwrap()
+                .0;
+            assert_eq!(res.body.bytes().await?, expected_body);
+            assert_eq!(fetches.load(Ordering::SeqCst), expected_fetches);
+            let backoff = Duration::from_millis(100);
+            assert_eq!(
+                *sleeps.lock().unwrap(),
+                [backoff, backoff * 2, backoff * 4][..expected_fetches - 2]
+            );
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn revalidate_with_both_validators() -> Result<()> {
+        let url = "http://example.com/";
+        let etag = "\"v1\"";
+        let last_modified = "Wed, 21 Oct 2015 07:28:00 GMT";
+        // The 304 may echo either validator
+        for validator in [("etag", etag), ("last-modified", last_modified)] {
+            let tmp = tempfile::tempdir()?;
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let cache = build_cache(&tmp, HttpCacheOptions::default());
+            let count = fetches.clone();
+            let respond = move |req: &request::Parts| {
+                if count.load(Ordering::SeqCst) == 1 {
+                    return build_response(
+                        url,
+                        200,
+                        &[
+                            ("cache-control", "max-age=0"),
+                            ("etag", etag),
+                            ("last-modified", last_modified),
+                        ],
+                        TEST_BODY,
+                    );
+                }
+                assert_eq!(req.headers["if-none-match"], etag);
+                assert_eq!(req.headers["if-modified-since"], last_modified);
+                build_response(
+                    url,
+                    304,
+                    &[("cache-control", "max-age=600"), validator],
+                    b"",
+                )
+            };
+            for _ in 0..3 {
+                let res = cache
+                    .run(TestMiddleware::new(
+                        http::Request::get(url).body(())?,
+                        fetches.clone(),
+                        respond.clone(),
+                    ))
+                    .await?;
+                assert_eq!(res.parts.status, 200);
+                assert_eq!(res.body.bytes().await?, TEST_BODY);
+            }
+            // The refreshed response is fresh for the third request
+            assert_eq!(fetches.load(Ordering::SeqCst), 2, "{validator:?}");
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn on_corrupt_policy() -> Result<()> {
+        let url = "http://example.com/";
+        let key = format!("{}:{}", GET, url);
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let discard = build_cache(&tmp, HttpCacheOptions::default());
+        let rebuild = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                on_corrupt: OnCorrupt::Rebuild,
+                ..Default::default()
+            },
+        );
+        let middleware = || {
+            TestMiddleware::new(
+                http::Request::get(url).body(()).unwrap(),
+                fetches.clone(),
+                move |_| {
+                    build_response(
+                        url,
+                        200,
+                        &[("cache-control", CACHEABLE_PUBLIC)],
+                        TEST_BODY,
+                    )
+                },
+            )
+        };
+        // Overwrites the serialized policy, which follows the length of the
+        // head, the response parts and the length of the policy itself
+        async fn corrupt_policy(
+            path: &std::path::Path,
+            key: &str,
+        ) -> Result<()> {
+            let manager = CACacheManager { path: path.to_path_buf() };
+            let (res, _) = manager.get(key).await?.unwrap();
+            let mut bytes = cacache::read(path, key).await?;
+            let start = 4 + bincode::serialize(&res.parts)?.len();
+            let len = u64::from_le_bytes(bytes[start..start + 8].try_into()?);
+            bytes[start + 8..][..len as usize].fill(0xff);
+            cacache::write(path, key, bytes).await?;
+            Ok(())
+        }
+
+        discard.run(middleware()).await?;
+        corrupt_policy(tmp.path(), &key).await?;
+        let res = discard.run(middleware()).await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+
+        corrupt_policy(tmp.path(), &key).await?;
+        let res = rebuild.run(middleware()).await?;
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(res.parts.headers[XCACHE], HitOrMiss::HIT.to_string());
+        assert!(!res.parts.headers.contains_key("x-http-cache-rebuilt-policy"));
+        assert_eq!(res.body.bytes().await?, TEST_BODY);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn stream_while_caching() -> Result<()> {
+        use futures::StreamExt;
+
+        let url = "http://example.com/";
+        let key = format!("{}:{}", GET, url);
+        let chunks: [&[u8]; 3] = [b"first ", b"second ", b"third"];
+        let tmp = tempfile::tempdir()?;
+        // The background writes are run by the test, once the body was read
+        let tasks = Arc::new(Mutex::new(Vec::new()));
+        let spawned = tasks.clone();
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                stream_while_caching: true,
+                spawn: Some(Arc::new(move |task| {
+                    spawned.lock().unwrap().push(task);
+                })),
+                ..Default::default()
+            },
+        );
+        let run = || {
+            cache.run(TestMiddleware::new(
+                http::Request::get(url).body(()).unwrap(),
+                Arc::new(AtomicUsize::new(0)),
+                move |_| {
+                    let res = build_response(
+                        url,
+                        200,
+                        &[("cache-control", CACHEABLE_PUBLIC)],
+                        b"",
+                    )?;
+                    let stream = futures::stream::iter(chunks)
+                        .map(|chunk| Ok::<_, error::BoxError>(chunk.to_vec()));
+                    Ok(HttpResponse::from_parts(
+                        res.into_parts().0,
+                        Body::wrap_stream(stream),
+                    ))
+                },
+            ))
+        };
+        let run_tasks = || {
+            let tasks = std::mem::take(&mut *tasks.lock().unwrap());
+            futures::future::join_all(tasks)
+        };
+
+        // A body dropped halfway isn't cached
+        let res = run().await?;
+        let mut stream = res.into_parts().1.into_data_stream();
+        assert_eq!(stream.next().await.unwrap()?, chunks[0]);
+        drop(stream);
+        run_tasks().await;
+        assert!(cache.manager.get(&key).await?.is_none());
+
+        let res = run().await?;
+        assert!(res.body.as_bytes().is_none());
+        let forwarded = res.body.bytes().await?;
+        assert_eq!(forwarded, chunks.concat());
+        run_tasks().await;
+        let (cached, _) = cache.manager.get(&key).await?.unwrap();
+        assert_eq!(cached.body.bytes().await?, forwarded);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn content_type_in_key() -> Result<()> {
+        let url = "http://example.com/search";
+        for (content_type_in_key, expected_fetches) in [(true, 2), (false, 1)] {
+            let tmp = tempfile::tempdir()?;
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions {
+                    cacheable_methods: vec![http::Method::POST],
+                    content_type_in_key,
+                    ..Default::default()
+                },
+            );
+            for content_type in
+                ["application/json", "application/x-www-form-urlencoded"]
+            {
+                for _ in 0..2 {
+                    cache
+                        .run(TestMiddleware::new(
+                            http::Request::post(url)
+                                .header("content-type", content_type)
+                                .body(())?,
+                            fetches.clone(),
+                            move |_| {
+                                build_response(
+                                    url,
+                                    200,
+                                    &[("cache-control", CACHEABLE_PUBLIC)],
+                                    TEST_BODY,
+                                )
+                            },
+                        ))
+                        .await?;
+                }
+            }
+            assert_eq!(fetches.load(Ordering::SeqCst), expected_fetches);
+        }
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                cacheable_methods: vec![http::Method::POST],
+                ..Default::default()
+            },
+        );
+        let (parts, ()) = http::Request::post(url)
+            .header("content-type", "application/json")
+            .body(())?
+            .into_parts();
+        assert_eq!(
+            cache.key_for_parts(&parts),
+            format!("POST:{url}:content-type=application/json")
+        );
+        Ok(())
+    }
+
+    #[async_test]
+    async fn normalize_trailing_slash() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                normalize_trailing_slash: true,
+                ..Default::default()
+            },
+        );
+        for url in ["http://example.com/items", "http://example.com/items/"] {
+            cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    move |_| {
+                        build_response(
+                            url,
+                            200,
+                            &[("cache-control", CACHEABLE_PUBLIC)],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+                .await?;
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        for (url, key) in [
+            ("http://example.com/", "GET:http://example.com/"),
+            ("http://example.com/a/?q=1", "GET:http://example.com/a?q=1"),
+            ("http://example.com/a//", "GET:http://example.com/a/"),
+        ] {
+            let (parts, ()) = http::Request::get(url).body(())?.into_parts();
+            assert_eq!(cache.key_for_parts(&parts), key);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn preconditions_bypass_cache() -> Result<()> {
+        let url = "http://example.com/";
+        for (name, value) in [
+            ("if-match", "\"v1\""),
+            ("if-unmodified-since", "Wed, 21 Oct 2015 07:28:00 GMT"),
+        ] {
+            let tmp = tempfile::tempdir()?;
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let cache = build_cache(&tmp, HttpCacheOptions::default());
+            let respond = move |req: &request::Parts| {
+                // The precondition holds, the origin answers with a newer body
+                let body: &[u8] = if req.headers.contains_key(name) {
+                    b"updated"
+                } else {
+                    TEST_BODY
+                };
+                build_response(
+                    url,
+                    200,
+                    &[("cache-control", CACHEABLE_PUBLIC), ("etag", "\"v2\"")],
+                    body,
+                )
+            };
+            cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    respond,
+                ))
+                .await?;
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).header(name, value).body(())?,
+                    fetches.clone(),
+                    respond,
+                ))
+                .await?;
+            assert_eq!(res.body.bytes().await?, &b"updated"[..], "{name}");
+            assert_eq!(res.parts.headers.get(XCACHE).unwrap(), "MISS");
+            assert_eq!(fetches.load(Ordering::SeqCst), 2);
+            // The response to a conditional request isn't stored, so the stored
+            // response is still served without preconditions
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    respond,
+                ))
+                .await?;
+            assert_eq!(res.body.bytes().await?, TEST_BODY);
+            assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn repeated_stale_serves_keep_one_warning() -> Result<()> {
+        let url = "http://example.com/";
+        let transformed = "214 origin \"Transformation, applied\"";
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let fetches = Arc::new(AtomicUsize::new(0));
+        cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                fetches.clone(),
+                move |_| {
+                    build_response(
+                        url,
+                        200,
+                        &[
+                            ("cache-control", "max-age=0"),
+                            ("warning", transformed),
+                        ],
+                        TEST_BODY,
+                    )
+                },
+            ))
+            .await?;
+        for _ in 0..3 {
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    |_| Err("offline".into()),
+                ))
+                .await?;
+            // The warning of the origin is kept along with a single 111
+            let warning = &res.parts.headers["warning"];
+            assert!(warning.starts_with(&format!("{transformed}, 111 ")));
+            assert_eq!(warning.matches("Revalidation failed").count(), 1);
+        }
+        let (stored, _) =
+            cache.manager.get(&format!("{}:{}", GET, url)).await?.unwrap();
+        assert_eq!(stored.parts.headers["warning"], transformed);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn override_cache_control() -> Result<()> {
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                override_cache_control: Some("max-age=300".to_string()),
+                ..Default::default()
+            },
+        );
+        for _ in 0..2 {
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    move |_| build_response(url, 200, &[], TEST_BODY),
+                ))
+                .await?;
+            assert_eq!(res.parts.headers["cache-control"], "max-age=300");
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        let (stored, policy) =
+            cache.manager.get(&format!("{}:{}", GET, url)).await?.unwrap();
+        assert_eq!(stored.parts.headers["cache-control"], "max-age=300");
+        assert!(
+            policy.time_to_live(SystemTime::now()) > Duration::from_secs(290)
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "graphql")]
+    #[async_test]
+    async fn graphql_operation_key() -> Result<()> {
+        use crate::GraphQlKey;
+
+        let url = "http://example.com/graphql";
+        let tmp = tempfile::tempdir()?;
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = build_cache(
+            &tmp,
+            HttpCacheOptions {
+                cacheable_methods: vec![http::Method::POST],
+                body_key: Some(GraphQlKey::new(["GetUser"]).into_body_key()),
+                ..Default::default()
+            },
+        );
+        let query = |body: &'static str| {
+            let mut middleware = TestMiddleware::new(
+                http::Request::post(url)
+                    .header("content-type", "application/json")
+                    .body(())
+                    .unwrap(),
+                fetches.clone(),
+                move |_| {
+                    build_response(
+                        url,
+                        200,
+                        &[("cache-control", CACHEABLE_PUBLIC)],
+                        body.as_bytes(),
+                    )
+                },
+            );
+            middleware.body = Some(body.into());
+            middleware
+        };
+        let first = r#"{"operationName":"GetUser","query":"query GetUser($id: ID, $full: Boolean) { user(id: $id) { name } }","variables":{"id":"1","full":true}}"#;
+        let reordered = r#"{"operationName":"GetUser","query":"query GetUser($id: ID, $full: Boolean) { user(id: $id) { name } }","variables":{"full":true,"id":"1"}}"#;
+        let second = r#"{"operationName":"GetUser","query":"query GetUser($id: ID, $full: Boolean) { user(id: $id) { name } }","variables":{"id":"2","full":true}}"#;
+        // The origin echoes the query, so hits return the body of the first query
+        // with the same variables
+        for (body, expected) in [
+            (first, first),
+            (second, second),
+            (reordered, first),
+            (second, second),
+        ] {
+            let res = cache.run(query(body)).await?;
+            assert_eq!(res.body.bytes().await?, expected.as_bytes());
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.manager.keys().await?.len(), 2);
+
+        // Operations missing from the allowlist aren't cached
+        let mutation = r#"{"operationName":"DeleteUser","query":"mutation DeleteUser($id: ID) { deleteUser(id: $id) }","variables":{"id":"1"}}"#;
+        for _ in 0..2 {
+            cache.run(query(mutation)).await?;
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 4);
+        assert_eq!(cache.manager.keys().await?.len(), 2);
+        Ok(())
+    }
+
+    #[async_test]
+    async fn body_error_while_caching() -> Result<()> {
+        use crate::IncompleteBody;
+
+        let url = "http://example.com/";
+        let tmp = tempfile::tempdir()?;
+        let cache = build_cache(&tmp, HttpCacheOptions::default());
+        let res = cache
+            .run(TestMiddleware::new(
+                http::Request::get(url).body(())?,
+                Arc::new(AtomicUsize::new(0)),
+                move |_| {
+                    let res = build_response(
+                        url,
+                        200,
+                        &[("cache-control", CACHEABLE_PUBLIC)],
+                        b"",
+                    )?;
+                    let chunks: [Result<Vec<u8>>; 2] =
+                        [Ok(TEST_BODY.to_vec()), Err(Box::new(IncompleteBody))];
+                    Ok(HttpResponse::from_parts(
+                        res.into_parts().0,
+                        Body::wrap_stream(futures::stream::iter(chunks)),
+                    ))
+                },
+            ))
+            .await;
+        let err = res.expect_err("the body error should be returned");
+        assert!(err.is::<IncompleteBody>());
+        assert!(cache.manager.get(&format!("{GET}:{url}")).await?.is_none());
+        Ok(())
+    }
+
+    #[async_test]
+    async fn shared_cache_s_maxage() -> Result<()> {
+        let url = "http://example.com/";
+        for (shared, lifetime, expected_fetches) in
+            [(true, 600, 1), (false, 60, 2)]
+        {
+            let tmp = tempfile::tempdir()?;
+            let fetches = Arc::new(AtomicUsize::new(0));
+            let start = SystemTime::now();
+            let offset = Arc::new(AtomicU64::new(0));
+            let clock = offset.clone();
+            let cache = build_cache(
+                &tmp,
+                HttpCacheOptions {
+                    cache_options: Some(CacheOptions {
+                        shared,
+                        ..Default::default()
+                    }),
+                    clock: Some(Arc::new(move || {
+                        start
+                            + Duration::from_secs(clock.load(Ordering::SeqCst))
+                    })),
+                    ..Default::default()
+                },
+            );
+            let run = || {
+                cache.run(TestMiddleware::new(
+                    http::Request::get(url).body(()).unwrap(),
+                    fetches.clone(),
+                    move |_| {
+                        build_response(
+                            url,
+                            200,
+                            &[("cache-control", "max-age=60, s-maxage=600")],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+            };
+            run().await?;
+            let key = format!("{GET}:{url}");
+            let info = cache.entry_freshness(&key).await?.unwrap();
+            assert_eq!(
+                info.age + info.time_to_live,
+                Duration::from_secs(lifetime)
+            );
+
+            // Stale for a private cache only
+            offset.store(120, Ordering::SeqCst);
+            run().await?;
+            assert_eq!(
+                fetches.load(Ordering::SeqCst),
+                expected_fetches,
+                "shared: {shared}"
+            );
+        }
+        Ok(())
+    }
+
+    #[async_test]
+    async fn final_url_after_redirects() -> Result<()> {
+        use crate::FnMiddleware;
+
+        let url = "http://example.com/old";
+        let final_url = Url::parse("https://cdn.example.org/new")?;
+        let tmp = tempfile::tempdir()?;
+        let mut cache = build_cache(&tmp, HttpCacheOptions::default());
+        let middleware = || {
+            let final_url = final_url.clone();
+            FnMiddleware::new(
+                http::Request::get(url).body(()).unwrap().into_parts().0,
+                move |_: request::Parts| {
+                    let final_url = final_url.clone();
+                    // The client followed a redirect to the final url
+                    async move {
+                        let mut res = http::Response::builder()
+                            .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                            .body(bytes::Bytes::from_static(TEST_BODY))?;
+                        res.extensions_mut().insert(final_url);
+                        Ok(res)
+                    }
+                },
+            )
+        };
+        cache.run(middleware()).await?;
+
+        // Stored under the request url, recording the final url
+        let (res, _) =
+            cache.manager.get(&format!("{GET}:{url}")).await?.unwrap();
+        assert_eq!(res.parts.url, final_url);
+
+        cache.mode = CacheMode::ForceCache;
+        let res = cache.run(middleware()).await?;
+        assert_eq!(res.warning_code(), Some(112));
+        assert!(res.parts.headers["warning"].contains(" cdn.example.org "));
+        Ok(())
+    }
+}
+
+#[cfg(feature = "manager-blob")]
+mod with_blob {
+    use super::*;
+    use crate::{BlobManager, BlobStore, CacheManager, HttpCache};
+
+    use async_attributes::test as async_test;
+    use bytes::Bytes;
+
+    #[derive(Debug, Default)]
+    struct MapStore(Mutex<HashMap<String, Bytes>>);
+
+    #[async_trait::async_trait]
+    impl BlobStore for MapStore {
+        async fn read(&self, key: &str) -> Result<Option<Bytes>> {
+            Ok(self.0.lock().unwrap().get(key).cloned())
+        }
+
+        async fn write(&self, key: &str, bytes: Bytes) -> Result<()> {
+            self.0.lock().unwrap().insert(key.to_string(), bytes);
+            Ok(())
+        }
+
+        async fn remove(&self, key: &str) -> Result<()> {
+            self.0.lock().unwrap().remove(key);
+            Ok(())
+        }
+    }
+
+    #[async_test]
+    async fn blob_manager() -> Result<()> {
+        let url = "http://example.com/";
+        let key = format!("{}:{}", GET, url);
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let cache = HttpCache {
+            mode: CacheMode::Default,
+            manager: BlobManager::new(MapStore::default()),
+            options: HttpCacheOptions {
+                store_request: true,
+                ..Default::default()
+            },
+        };
+        for _ in 0..2 {
+            let res = cache
+                .run(TestMiddleware::new(
+                    http::Request::get(url).body(())?,
+                    fetches.clone(),
+                    move |_| {
+                        build_response(
+                            url,
+                            200,
+                            &[("cache-control", CACHEABLE_PUBLIC)],
+                            TEST_BODY,
+                        )
+                    },
+                ))
+                .await?;
+            assert_eq!(res.body.bytes().await?, TEST_BODY);
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+
+        let (res, policy, request) =
+            cache.manager.get_with_request(&key).await?.unwrap();
+        assert_eq!(
+            res.parts.headers.get("cache-control").unwrap(),
+            CACHEABLE_PUBLIC
+        );
+        assert!(crate::is_fresh(&policy, SystemTime::now()));
+        assert_eq!(request.unwrap().uri, url);
+
+        // Blobs that can't be decoded are a miss
+        cache.manager.store.write(&key, Bytes::from_static(b"junk")).await?;
+        assert!(cache.manager.get(&key).await?.is_none());
+
+        cache.manager.delete(&key).await?;
+        assert!(cache.manager.store.read(&key).await?.is_none());
+        Ok(())
+    }
 }
 
 #[cfg(feature = "manager-moka")]